@@ -0,0 +1,386 @@
+use anyhow::{
+    Result,
+    Context,
+    anyhow,
+    bail,
+};
+use std::{
+    path::Path,
+    fs::read_to_string,
+    time::Duration,
+};
+use eka_core::{
+    checker::TypeChecker,
+    interpreter::{
+        object::*,
+        native::*,
+        Primitive,
+    },
+    ast::{
+        Interner,
+        FnId,
+    },
+    source::SourceMap,
+    diagnostics::{
+        Diagnostics,
+        render_error,
+    },
+};
+use eka_parser::{
+    modules::ModuleLoader,
+    ParserData,
+};
+use eka_interp_treewalk::{
+    data::GcWorkloadObject,
+    Interpreter,
+};
+pub use eka_interp_treewalk::snapshot::{
+    HeapSnapshot,
+    SnapshotDiff,
+};
+
+
+/// The garbage collector used by the engine. Object bundles used with the [`Engine`] should use
+/// this as their GC type.
+pub type Gc<O> = eka_interp_treewalk::data::Gc<O>;
+/// A reference to an object allocated by the engine.
+pub type DataRef<O> = eka_interp_treewalk::data::DataRef<O>;
+/// A reference that keeps the object alive until it is passed to [`Engine::unroot`].
+pub type RootDataRef<O> = eka_interp_treewalk::data::RootDataRef<O>;
+/// How much work the garbage collector does per increment.
+pub type GcWorkload = eka_interp_treewalk::data::GcWorkload;
+/// Statistics about the garbage collector.
+pub type GcStats = eka_interp_treewalk::data::GcStats;
+/// A value passed between Rust and scripts.
+pub type Value<O> = Primitive<Gc<O>, O>;
+
+
+/// A handle to a script function found with [`Engine::get_function`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScriptFn(FnId);
+
+
+pub struct Engine<O: ObjectBundle<Gc<O>>> {
+    interpreter: Interpreter<O>,
+    /// Every source loaded so far. New source is parsed into it, and only its new roots are run.
+    data: ParserData,
+    /// Loads the modules imported by new source, and knows the host modules
+    modules: ModuleLoader,
+    type_check: bool,
+}
+impl<O: ObjectBundle<Gc<O>> + 'static> Default for Engine<O> {
+    fn default()->Self {
+        Self::new()
+    }
+}
+impl<O: ObjectBundle<Gc<O>> + 'static> Engine<O> {
+    pub fn new()->Self {
+        let mut data = ParserData::default();
+        Engine {
+            interpreter: Interpreter::new(&mut data.interner),
+            data,
+            modules: ModuleLoader::default(),
+            type_check: true,
+        }
+    }
+
+    /// The interner used for all identifiers. Objects that need to intern their field or method
+    /// names should use this.
+    #[inline]
+    pub fn interner_mut(&mut self)->&mut Interner {
+        &mut self.data.interner
+    }
+
+    /// Allow the object type `name` in type annotations like `[pos :Vec2]`. It should match the
+    /// [`Object::type_name`](eka_core::interpreter::object::Object::type_name) of the objects.
+    /// Annotations with undeclared type names are parse errors.
+    #[inline]
+    pub fn declare_object_type(&mut self, name: &str) {
+        self.data.declare_object_type(name);
+    }
+
+    /// Enable or disable the static type checker. It is enabled by default and runs on every
+    /// loaded source before any of it is run.
+    #[inline]
+    pub fn set_type_check(&mut self, enabled: bool) {
+        self.type_check = enabled;
+    }
+
+    /// Every source loaded so far. Use it to look up the file and line of a [`Span`].
+    ///
+    /// [`Span`]: eka_core::source::Span
+    #[inline]
+    pub fn sources(&self)->&SourceMap {
+        &self.data.sources
+    }
+
+    /// Render an error returned by the engine with the source lines it points to. Parse errors, type
+    /// errors, and runtime errors are all supported. With `color`, ANSI escape codes are used for a
+    /// terminal.
+    #[inline]
+    pub fn render_error(&self, err: &anyhow::Error, color: bool)->String {
+        render_error(err, &self.data.sources, color)
+    }
+
+    /// Parse and run the source. Returns the value of the last top-level expression. Imports are
+    /// relative to the current directory.
+    #[inline]
+    pub fn load_str(&mut self, source: &str)->Result<Value<O>> {
+        self.load_named("<string>", source, Path::new("."))
+    }
+
+    /// Parse and run the source and the modules it imports, naming it `name` in spans and errors.
+    /// If anything fails, the rest is not run and the new modules are unloaded.
+    fn load_named(&mut self, name: &str, source: &str, dir: &Path)->Result<Value<O>> {
+        let checkpoint = self.modules.checkpoint();
+        let res = self.parse(name, source, dir)
+            .and_then(|_|if self.type_check {self.check_new_roots()} else {Ok(())})
+            .and_then(|_|self.run_new_roots());
+
+        if res.is_err() {
+            self.interpreter.skip_new_roots(&self.data.exprs);
+            self.modules.restore(checkpoint);
+        }
+
+        return res;
+    }
+
+    /// Parse and type check the source and the modules it imports without running them.
+    #[inline]
+    pub fn check_str(&mut self, source: &str)->Result<()> {
+        self.check_named("<string>", source, Path::new("."))
+    }
+
+    fn check_named(&mut self, name: &str, source: &str, dir: &Path)->Result<()> {
+        // nothing is run, so the modules have to be loaded again when they are imported for real
+        let checkpoint = self.modules.checkpoint();
+        let res = self.parse(name, source, dir)
+            .and_then(|_|self.check_new_roots());
+        self.interpreter.skip_new_roots(&self.data.exprs);
+        self.modules.restore(checkpoint);
+
+        return res;
+    }
+
+    /// Read, parse, and type check the file without running it.
+    pub fn check_file<P: AsRef<Path>>(&mut self, path: P)->Result<()> {
+        let path = path.as_ref();
+        let source = read_to_string(path)
+            .with_context(||format!("Could not read `{}`", path.display()))?;
+
+        return self.check_named(&path.display().to_string(), &source, parent_dir(path))
+            .with_context(||format!("In file `{}`", path.display()));
+    }
+
+    fn parse(&mut self, name: &str, source: &str, dir: &Path)->Result<()> {
+        self.modules.load_source(&mut self.data, name, source, dir)
+    }
+
+    /// Read, parse, and run the file. Returns the value of the last top-level expression. Imports
+    /// are relative to the directory of the file.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P)->Result<Value<O>> {
+        let path = path.as_ref();
+        let source = read_to_string(path)
+            .with_context(||format!("Could not read `{}`", path.display()))?;
+
+        return self.load_named(&path.display().to_string(), &source, parent_dir(path))
+            .with_context(||format!("In file `{}`", path.display()));
+    }
+
+    /// Run the garbage collector until every object that is dead right now has been finalized.
+    #[inline]
+    pub fn collect_full(&mut self) {
+        self.interpreter.gc_mut().collect_full();
+    }
+
+    /// Collect the young objects. Short-lived objects die here without going through a full cycle.
+    /// Returns the count of objects promoted to the old space.
+    #[inline]
+    pub fn gc_collect_minor(&mut self)->usize {
+        self.interpreter.gc_mut().collect_minor()
+    }
+
+    /// Collect the young objects, then spend up to `budget` on garbage collection, stopping early if
+    /// a cycle finishes. Meant to be called once per frame. Returns the count of increments run.
+    #[inline]
+    pub fn gc_step_for(&mut self, budget: Duration)->usize {
+        self.interpreter.gc_mut().step_for(budget)
+    }
+
+    /// Enable or disable collection during allocation. Disable it to only collect in
+    /// [`Engine::gc_step_for`] and [`Engine::collect_full`].
+    #[inline]
+    pub fn set_collect_on_alloc(&mut self, enabled: bool) {
+        self.interpreter.gc_mut().set_collect_on_alloc(enabled);
+    }
+
+    /// Set how much work each GC increment does. Every engine has its own workload.
+    #[inline]
+    pub fn set_gc_workload(&mut self, workload: GcWorkload) {
+        self.interpreter.gc_mut().set_workload(workload);
+    }
+
+    /// Take a snapshot of the live object graph. See [`HeapSnapshot`] for exporting and diffing.
+    #[inline]
+    pub fn heap_snapshot(&mut self)->HeapSnapshot {
+        HeapSnapshot::new(self.interpreter.gc_mut())
+    }
+
+    #[inline]
+    pub fn gc_stats(&self)->GcStats {
+        self.interpreter.gc().stats()
+    }
+
+    /// Create a `GcWorkload` object for scripts to tune the GC and read its stats.
+    pub fn gc_workload_object(&mut self)->GcWorkloadObject<O> {
+        self.interpreter.gc_workload_object(&mut self.data.interner)
+    }
+
+    /// Allocate an object and return a reference to it. The reference does not keep the object
+    /// alive: unless it is stored in a global or in another live object, the object can be
+    /// collected by the next allocation. Use [`Engine::root`] to hold it in Rust.
+    #[inline]
+    pub fn alloc<T: Into<O>>(&mut self, obj: T)->DataRef<O> {
+        self.interpreter.alloc(obj.into())
+    }
+
+    /// Keep the object alive until the root is passed to [`Engine::unroot`]. Returns `None` if it
+    /// is already rooted.
+    #[inline]
+    pub fn root(&mut self, dr: DataRef<O>)->Option<RootDataRef<O>> {
+        self.interpreter.gc_mut().root(dr)
+    }
+
+    #[inline]
+    pub fn unroot(&mut self, root: RootDataRef<O>) {
+        self.interpreter.gc_mut().unroot(root);
+    }
+
+    /// Define a global variable with the converted value.
+    pub fn set_global<T: IntoPrimitive<Gc<O>, O>>(&mut self, name: &str, val: T)->Result<()> {
+        let val = val.into_primitive(self.interpreter.gc_mut())?;
+        self.interpreter.def_global_str(name, val, &mut self.data.interner);
+
+        return Ok(());
+    }
+
+    /// Allocate an object and define it as a global variable.
+    pub fn set_global_object<T: Into<O>>(&mut self, name: &str, obj: T) {
+        let dr = self.alloc(obj);
+        self.interpreter.def_global_str(name, Primitive::Data(dr), &mut self.data.interner);
+    }
+
+    /// Get a global variable and convert it.
+    pub fn get_global<T: FromPrimitive<Gc<O>, O>>(&mut self, name: &str)->Result<T> {
+        let ident = self.data.interner.intern(name);
+        let val = self.interpreter.get_var(ident, &self.data.interner)?;
+        let got = val.type_name();
+
+        return T::from_primitive(val)
+            .ok_or_else(||anyhow!("Global `{name}` is a {got}, but expected {}", T::TYPE_NAME));
+    }
+
+    /// Register a Rust closure as a global native function. See [`IntoNativeFn`] for the supported
+    /// closure types.
+    #[inline]
+    pub fn register_fn<Args, F>(&mut self, name: &str, func: F)
+    where
+        F: IntoNativeFn<Gc<O>, O, Args>,
+        O: From<NativeFnObject<Gc<O>, O>>,
+    {
+        self.interpreter.def_native_fn(name, func, &mut self.data.interner);
+    }
+
+    /// Define an export of the host module `module`. Scripts use it after `(import "module")`.
+    pub fn set_module_global<T: IntoPrimitive<Gc<O>, O>>(&mut self, module: &str, name: &str, val: T)->Result<()> {
+        let val = val.into_primitive(self.interpreter.gc_mut())?;
+        let global = self.modules.host_export(module, name, &mut self.data.interner);
+        self.interpreter.def_global(global, val);
+
+        return Ok(());
+    }
+
+    /// Allocate an object and define it as an export of the host module `module`.
+    pub fn set_module_object<T: Into<O>>(&mut self, module: &str, name: &str, obj: T) {
+        let dr = self.alloc(obj);
+        let global = self.modules.host_export(module, name, &mut self.data.interner);
+        self.interpreter.def_global(global, Primitive::Data(dr));
+    }
+
+    /// Register a Rust closure as a native function exported by the host module `module`.
+    pub fn register_module_fn<Args, F>(&mut self, module: &str, name: &str, func: F)
+    where
+        F: IntoNativeFn<Gc<O>, O, Args>,
+        O: From<NativeFnObject<Gc<O>, O>>,
+    {
+        let global = self.modules.host_export(module, name, &mut self.data.interner);
+        let global = self.data.interner.get(global).to_string();
+        self.interpreter.def_native_fn(&global, func, &mut self.data.interner);
+    }
+
+    /// Look up a script function defined with `defn`.
+    pub fn get_function(&mut self, name: &str)->Result<ScriptFn> {
+        let ident = self.data.interner.intern(name);
+        match self.interpreter.get_var(ident, &self.data.interner)? {
+            Primitive::Fn(id)=>Ok(ScriptFn(id)),
+            p=>bail!("`{name}` is a {}, not a script function", p.type_name()),
+        }
+    }
+
+    /// Call a script function found with [`Engine::get_function`].
+    pub fn call_fn<A, R>(&mut self, func: ScriptFn, args: A)->Result<R>
+    where
+        A: IntoArgs<Gc<O>, O>,
+        R: FromPrimitive<Gc<O>, O>,
+    {
+        let ret = self.interpreter.call_with_args(Primitive::Fn(func.0), args, &self.data.exprs, &self.data.funcs, &mut self.data.interner)?;
+        let got = ret.type_name();
+
+        let name = self.data.interner.get(self.data.funcs[func.0].name);
+        return R::from_primitive(ret)
+            .ok_or_else(||anyhow!("`{name}` returned a {got}, but expected {}", R::TYPE_NAME));
+    }
+
+    /// Look up the global with the given name and call it. Works for script functions, native
+    /// functions, and callable objects.
+    pub fn call<A, R>(&mut self, name: &str, args: A)->Result<R>
+    where
+        A: IntoArgs<Gc<O>, O>,
+        R: FromPrimitive<Gc<O>, O>,
+    {
+        let ident = self.data.interner.intern(name);
+        let callee = self.interpreter.get_var(ident, &self.data.interner)?;
+        let ret = self.interpreter.call_with_args(callee, args, &self.data.exprs, &self.data.funcs, &mut self.data.interner)
+            .with_context(||format!("In call to `{name}`"))?;
+        let got = ret.type_name();
+
+        return R::from_primitive(ret)
+            .ok_or_else(||anyhow!("`{name}` returned a {got}, but expected {}", R::TYPE_NAME));
+    }
+
+    fn check_new_roots(&mut self)->Result<()> {
+        let roots = self.data.exprs.iter_roots()
+            .skip(self.interpreter.ran_roots())
+            .copied();
+        let errors = TypeChecker::new(&self.data.exprs, &self.data.funcs, &self.data.interner)
+            .check_roots(roots);
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let diags = errors.iter()
+            .map(|e|e.diagnostic(&self.data.exprs))
+            .collect();
+        bail!(Diagnostics(diags));
+    }
+
+    fn run_new_roots(&mut self)->Result<Value<O>> {
+        self.interpreter.run_new_roots(&self.data.exprs, &self.data.funcs, &mut self.data.interner)
+    }
+}
+
+
+fn parent_dir(path: &Path)->&Path {
+    path.parent().unwrap_or(Path::new("."))
+}
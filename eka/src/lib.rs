@@ -5,3 +5,6 @@ pub use eka_interp_treewalk as treewalk;
 pub use eka_parser as parser;
 
 pub use eka_core::*;
+
+#[cfg(all(feature="parser", feature="interp_treewalk"))]
+pub mod engine;
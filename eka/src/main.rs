@@ -1,4 +1,3 @@
-use logos::Logos;
 use anyhow::{
     Result,
     bail,
@@ -9,6 +8,7 @@ use std::{
         Stdout,
         Stderr,
         Write,
+        IsTerminal,
         stdin,
         stdout,
         stderr,
@@ -17,29 +17,36 @@ use std::{
         Instant,
         Duration,
     },
-    fs::read_to_string,
+    process::exit,
+    env::args,
+    fs,
 };
 use eka::{
     interpreter::{
         object::*,
+        native::*,
         Primitive,
     },
     ast::{
         Ident,
         Interner,
     },
-    treewalk::{
-        data::*,
-        Interpreter,
+    treewalk::data::{
+        GcWorkloadObject,
+        WeakObject,
+        make_weak,
     },
-    parser::{
-        lexer,
-        Parser,
+    parser::fmt::{
+        FormatOptions,
+        format_source,
     },
+    source::SourceMap,
+    diagnostics::render_error,
+    engine::Engine,
 };
 
 
-pub type Gc = eka::treewalk::data::Gc<EkaBaseBundle>;
+pub type Gc = eka::engine::Gc<EkaBaseBundle>;
 
 
 eka::bundle_object_types! {
@@ -49,6 +56,8 @@ eka::bundle_object_types! {
         Console: Console,
         Duration: DurationObject,
         Instant: InstantObject,
+        NativeFn: NativeFnObject<Gc, Self>,
+        Weak: WeakObject<Self>,
     }
 }
 
@@ -58,6 +67,11 @@ pub struct InstantObject(Instant);
 impl Object<Gc> for InstantObject {
     type ObjectBundle = EkaBaseBundle;
 
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        Some("Instant")
+    }
+
     fn get(&self, _: Ident, _: &Interner)->Result<Primitive<Gc, EkaBaseBundle>> {
         bail!("There are no fields on Instant");
     }
@@ -83,6 +97,11 @@ pub struct DurationObject(Duration);
 impl Object<Gc> for DurationObject {
     type ObjectBundle = EkaBaseBundle;
 
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        Some("Duration")
+    }
+
     fn get(&self, _: Ident, _: &Interner)->Result<Primitive<Gc, EkaBaseBundle>> {
         bail!("There are no fields on Duration");
     }
@@ -179,40 +198,103 @@ impl Object<Gc> for Console {
 }
 
 
-fn main() {
-    let source = read_to_string("example.eka").unwrap();
+/// Format the files in place, or only report the ones that aren't formatted if `check` is set.
+/// Returns if every file was formatted, or already was in check mode.
+fn format_files(paths: &[String], check: bool)->bool {
+    let options = FormatOptions::default();
+    let mut ok = true;
+
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source)=>source,
+            Err(e)=>{
+                eprintln!("Could not read `{path}`: {e}");
+                ok = false;
+                continue;
+            },
+        };
+
+        let mut sources = SourceMap::default();
+        let formatted = match format_source(path, &source, &mut sources, &options) {
+            Ok(formatted)=>formatted,
+            Err(e)=>{
+                eprint!("{}", render_error(&e, &sources, stderr().is_terminal()));
+                ok = false;
+                continue;
+            },
+        };
+        if formatted == source {
+            continue;
+        }
 
-    for tok in lexer::Token::lexer(&source) {
-        dbg!(tok).ok();
+        if check {
+            println!("`{path}` is not formatted");
+            ok = false;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("Could not write `{path}`: {e}");
+            ok = false;
+        }
     }
 
-    let mut parser = Parser::new_from_source(&source);
-    parser.parse().unwrap();
-
-    let mut data = parser.finish();
-    dbg!(&data);
-    let console = Console::new(&mut data.interner);
-    let gc_workload = GcWorkloadObject::<EkaBaseBundle>::new(&mut data.interner);
-
-    let mut interpreter = Interpreter::<EkaBaseBundle>::new(data.interner);
-
-    let console_dr = interpreter.alloc(console.into());
-    interpreter.def_global_str("console", Primitive::Data(console_dr));
-
-    let gc_workload_dr = interpreter.alloc(gc_workload.into());
-    interpreter.def_global_str("gcWorkload", Primitive::Data(gc_workload_dr));
-
-    interpreter.def_global_str("instantNow", Primitive::NativeFn(instant_now));
-
-    dbg!(interpreter.run(&data.exprs, &data.funcs).unwrap());
+    return ok;
 }
 
-fn instant_now(args: Vec<Primitive<Gc, EkaBaseBundle>>, _: &mut Interner, gc: &mut Gc)->Result<CallReturn<Gc, EkaBaseBundle>> {
-    if args.len() != 0 {
-        bail!("Expected zero args for instantNow");
-    }
 
-    let dr = gc.alloc(InstantObject(Instant::now()).into());
+fn main() {
+    let mut engine = Engine::<EkaBaseBundle>::new();
+
+    let console = Console::new(engine.interner_mut());
+    engine.set_global_object("console", console);
+
+    let gc_workload = engine.gc_workload_object();
+    engine.set_global_object("gcWorkload", gc_workload);
+
+    engine.register_fn("instantNow", ||Alloc(InstantObject(Instant::now())));
+    engine.set_global("weak", Primitive::NativeFn(make_weak)).unwrap();
+
+    engine.declare_object_type("Instant");
+    engine.declare_object_type("Duration");
+    engine.declare_object_type("Weak");
+
+    let mut args = args().skip(1);
+    match args.next().as_deref() {
+        Some("check")=>{
+            let path = args.next().unwrap_or("example.eka".into());
+            match engine.check_file(&path) {
+                Ok(_)=>println!("No type errors in `{path}`"),
+                Err(e)=>{
+                    eprint!("{}", engine.render_error(&e, stderr().is_terminal()));
+                    exit(1);
+                },
+            }
+        },
+        Some("fmt")=>{
+            let mut check = false;
+            let mut paths = Vec::new();
+            for arg in args {
+                match arg.as_str() {
+                    "--check"=>check = true,
+                    _=>paths.push(arg),
+                }
+            }
+            // formatting rewrites files, so there is no default file like the other commands
+            if paths.is_empty() {
+                eprintln!("Usage: eka fmt [--check] <files...>");
+                exit(1);
+            }
 
-    return Ok(CallReturn::Data(Primitive::Data(dr)))
+            if !format_files(&paths, check) {
+                exit(1);
+            }
+        },
+        path=>{
+            match engine.load_file(path.unwrap_or("example.eka")) {
+                Ok(val)=>{dbg!(val);},
+                Err(e)=>{
+                    eprint!("{}", engine.render_error(&e, stderr().is_terminal()));
+                    exit(1);
+                },
+            }
+        },
+    }
 }
@@ -0,0 +1,82 @@
+//! Tests for the static type checker run by `Engine::check_str`.
+
+
+mod common;
+
+use eka::engine::Engine;
+use common::*;
+
+
+fn engine()->Engine<TestBundle> {
+    let mut engine = Engine::new();
+    engine.declare_object_type("Vec2");
+
+    return engine;
+}
+
+/// Check the source and return the rendered errors, or an empty string if there are none.
+fn check(source: &str)->String {
+    let mut engine = engine();
+    match engine.check_str(source) {
+        Ok(_)=>String::new(),
+        Err(e)=>engine.render_error(&e, false),
+    }
+}
+
+fn assert_ok(source: &str) {
+    let errors = check(source);
+    assert!(errors.is_empty(), "`{source}` should type check, but got:\n{errors}");
+}
+
+fn assert_error(source: &str, message: &str) {
+    let errors = check(source);
+    assert!(errors.contains(message), "`{source}` should fail with `{message}`, but got:\n{errors}");
+}
+
+
+#[test]
+fn annotated_params() {
+    assert_ok("(defn f [a :int b] (+ a 1)) (f 1 \"b\")");
+    assert_error("(defn f [a :int] a) (f \"a\")", "Param `a` of `f` expects int, but got string");
+    assert_error("(defn f [a :int b :int] a) (f 1)", "`f` expects 2 args, but got 1");
+}
+
+#[test]
+fn annotated_returns() {
+    assert_ok("(defn f [] :int 1)");
+    assert_error("(defn f [] :string 1)", "`f` should return string, but returns int");
+
+    // the body starting with a keyword is not a return type
+    assert_ok("(defn f [] :kw (+ 1 2))");
+}
+
+#[test]
+fn annotated_defs() {
+    assert_ok("(def a :float 1.5)");
+    assert_error("(def a :float \"a\")", "Var `a` is annotated as float, but is assigned string");
+}
+
+#[test]
+fn object_types() {
+    assert_ok("(defn len [v :Vec2] v)");
+    assert_error("(defn len [v :Vec3] v)", "Unknown type `:Vec3`");
+    assert_error("(def a :Int 1)", "Unknown type `:Int`");
+    assert_error("(def a :strng \"a\")", "Unknown type `:strng`");
+}
+
+#[test]
+fn unannotated_reassignment() {
+    assert_ok("(def x 1) (set x \"a\")");
+    assert_ok("(def x 1) (set x \"a\") (set x 2.5)");
+    assert_ok("(defn f [] (def y #t) (set y 1))");
+
+    // the type of an unannotated var isn't known after a def either
+    assert_ok("(def x 1) (set x \"a\") (+ x 1)");
+}
+
+#[test]
+fn annotated_reassignment() {
+    assert_ok("(def x :int 1) (set x 2)");
+    assert_error("(def x :int 1) (set x \"a\")", "Var `x` has type int, but is set to string");
+    assert_error("(defn f [] (def y :bool #t) (set y 1))", "Var `y` has type bool, but is set to int");
+}
@@ -0,0 +1,121 @@
+//! The object bundle and helpers shared by the engine tests.
+#![allow(dead_code)]
+
+
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+use eka::{
+    interpreter::{
+        object::*,
+        native::*,
+        GcTrait,
+        Primitive,
+    },
+    ast::{
+        Interner,
+        Ident,
+    },
+    engine::Engine,
+};
+
+
+pub type Gc = eka::engine::Gc<TestBundle>;
+
+eka::bundle_object_types! {
+    bundle TestBundle where GC = Gc {
+        BaseObject: BaseObject<Gc, Self>,
+        NativeFn: NativeFnObject<Gc, Self>,
+        Node: Node,
+    }
+}
+
+
+/// The values of the dropped nodes.
+pub type DropLog = Rc<RefCell<Vec<i64>>>;
+
+
+/// An object with a value, which it logs when dropped. Its only field is `child`, which can hold
+/// any value.
+#[derive(Debug)]
+pub struct Node {
+    pub value: i64,
+    pub child: Primitive<Gc, TestBundle>,
+    log: DropLog,
+}
+impl Node {
+    pub fn new(value: i64, log: &DropLog)->Self {
+        Node {
+            value,
+            child: Primitive::None,
+            log: log.clone(),
+        }
+    }
+}
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.log.borrow_mut().push(self.value);
+    }
+}
+impl Object<Gc> for Node {
+    type ObjectBundle = TestBundle;
+
+    fn type_name(&self)->Option<&str> {
+        Some("Node")
+    }
+
+    fn get(&self, name: Ident, interner: &Interner)->Result<Primitive<Gc, TestBundle>> {
+        match interner.get(name) {
+            "child"=>Ok(self.child.clone()),
+            _=>Ok(Primitive::Number(self.value)),
+        }
+    }
+    fn set(&mut self, name: Ident, data: Primitive<Gc, TestBundle>, interner: &Interner)->Result<()> {
+        match interner.get(name) {
+            "child"=>self.child = data,
+            other=>bail!("Node has no field `{other}`"),
+        }
+
+        return Ok(());
+    }
+
+    fn call(&mut self, _: Vec<Primitive<Gc, TestBundle>>, _: &Interner, _: &mut Gc)->Result<CallReturn<Gc, TestBundle>> {
+        bail!("Cannot call Node");
+    }
+    fn method(&mut self, _: Ident, _: Vec<Primitive<Gc, TestBundle>>, _: &Interner, _: &mut Gc)->Result<CallReturn<Gc, TestBundle>> {
+        bail!("Node has no methods");
+    }
+
+    fn trace(&self, gc: &mut Gc) {
+        match &self.child {
+            Primitive::Data(d)=>gc.trace(d.clone()),
+            _=>{},
+        }
+    }
+}
+
+
+/// The value of a node passed in from a script.
+pub fn node_value(val: &Primitive<Gc, TestBundle>)->Result<i64> {
+    match val {
+        Primitive::Data(d)=>match &**d {
+            TestBundle::Node(n)=>Ok(n.value),
+            other=>bail!("Expected a Node, but got {other:?}"),
+        },
+        other=>bail!("Expected a Node, but got {other:?}"),
+    }
+}
+
+/// The count of live nodes on the heap.
+pub fn live_nodes(engine: &mut Engine<TestBundle>)->usize {
+    engine.heap_snapshot()
+        .objects
+        .iter()
+        .filter(|o|o.type_name.as_deref() == Some("Node"))
+        .count()
+}
@@ -0,0 +1,193 @@
+//! Tests for the `Engine` facade: loading source, globals shared with the host, and calls from
+//! Rust into scripts.
+
+
+mod common;
+
+use eka::{
+    interpreter::{
+        native::Alloc,
+        Primitive,
+    },
+    engine::{
+        Engine,
+        GcWorkload,
+    },
+};
+use common::*;
+
+
+fn engine()->Engine<TestBundle> {
+    Engine::new()
+}
+
+
+#[test]
+fn load_str_returns_the_last_value() {
+    let mut engine = engine();
+    assert!(matches!(engine.load_str("").unwrap(), Primitive::None));
+    assert!(matches!(engine.load_str("(def a 1) (+ a 2)").unwrap(), Primitive::Number(3)));
+
+    // later loads see the globals of earlier ones
+    assert!(matches!(engine.load_str("(* a 10)").unwrap(), Primitive::Number(10)));
+}
+
+#[test]
+fn load_str_errors() {
+    let mut engine = engine();
+    let err = engine.load_str("(def a").unwrap_err();
+    assert!(!format!("{err:#}").is_empty());
+
+    let err = engine.load_str("(undefinedFn 1)").unwrap_err();
+    assert!(format!("{err:#}").contains("undefinedFn"), "{err:#}");
+
+    // the engine is still usable after an error
+    assert!(matches!(engine.load_str("(+ 1 1)").unwrap(), Primitive::Number(2)));
+}
+
+#[test]
+fn failed_blocks_leave_the_global_scope() {
+    let mut engine = engine();
+    assert!(engine.load_str("(begin (def a 1) (missing))").is_err());
+
+    // defs after the failed block are globals, so functions can see them
+    engine.load_str("(def b 2) (defn getB [] b)").unwrap();
+    assert_eq!(engine.get_global::<i64>("b").unwrap(), 2);
+    assert_eq!(engine.call::<_, i64>("getB", ()).unwrap(), 2);
+}
+
+#[test]
+fn globals_round_trip() {
+    let mut engine = engine();
+    engine.set_global("count", 41i64).unwrap();
+    engine.set_global("name", "eka").unwrap();
+    engine.set_global("ratio", 0.5f64).unwrap();
+    engine.set_global("enabled", true).unwrap();
+
+    engine.load_str("(set count (+ count 1))").unwrap();
+    assert_eq!(engine.get_global::<i64>("count").unwrap(), 42);
+    assert_eq!(engine.get_global::<String>("name").unwrap(), "eka");
+    assert_eq!(engine.get_global::<f64>("ratio").unwrap(), 0.5);
+    assert!(engine.get_global::<bool>("enabled").unwrap());
+
+    // globals defined by scripts are visible to the host
+    engine.load_str("(def greeting \"hello\")").unwrap();
+    assert_eq!(engine.get_global::<String>("greeting").unwrap(), "hello");
+}
+
+#[test]
+fn get_global_errors() {
+    let mut engine = engine();
+    engine.set_global("count", 1i64).unwrap();
+
+    let err = engine.get_global::<String>("count").unwrap_err();
+    assert!(format!("{err}").contains("Global `count` is a Number, but expected String"), "{err}");
+    assert!(engine.get_global::<i64>("missing").is_err());
+}
+
+#[test]
+fn call_script_fns() {
+    let mut engine = engine();
+    engine.load_str("(defn add [a b] (+ a b)) (defn echo [s] s)").unwrap();
+
+    assert_eq!(engine.call::<_, i64>("add", (2i64, 3i64)).unwrap(), 5);
+    assert_eq!(engine.call::<_, String>("echo", ("eka",)).unwrap(), "eka");
+
+    let add = engine.get_function("add").unwrap();
+    assert_eq!(engine.call_fn::<_, i64>(add, (10i64, -4i64)).unwrap(), 6);
+}
+
+#[test]
+fn call_errors() {
+    let mut engine = engine();
+    engine.load_str("(defn add [a b] (+ a b)) (def notAFn 1)").unwrap();
+
+    let err = engine.call::<_, String>("add", (1i64, 2i64)).unwrap_err();
+    assert!(format!("{err}").contains("`add` returned a Number, but expected String"), "{err}");
+
+    let err = engine.get_function("notAFn").unwrap_err();
+    assert!(format!("{err}").contains("`notAFn` is a Number, not a script function"), "{err}");
+
+    assert!(engine.call::<_, i64>("missing", ()).is_err());
+}
+
+#[test]
+fn call_native_fns() {
+    let mut engine = engine();
+    engine.register_fn("double", |n: i64|n * 2);
+    engine.load_str("(defn quadruple [n] (double (double n)))").unwrap();
+
+    assert_eq!(engine.call::<_, i64>("double", (4i64,)).unwrap(), 8);
+    assert_eq!(engine.call::<_, i64>("quadruple", (4i64,)).unwrap(), 16);
+}
+
+#[test]
+fn call_args_survive_conversion() {
+    let mut engine = engine();
+    engine.set_gc_workload(GcWorkload {
+        nursery_size: 1,
+        ..GcWorkload::default()
+    });
+    engine.register_fn("nodeValue", |val: Primitive<Gc, TestBundle>|node_value(&val));
+    engine.load_str("(defn add [a b] (+ (nodeValue a) (nodeValue b)))").unwrap();
+
+    // allocating the second arg collects the nursery while the first one is only held by the call
+    let log = DropLog::default();
+    let args = (Alloc(Node::new(1, &log)), Alloc(Node::new(2, &log)));
+    assert_eq!(engine.call::<_, i64>("add", args).unwrap(), 3);
+    assert!(log.borrow().is_empty(), "Args were dropped: {:?}", log.borrow());
+}
+
+#[test]
+fn dropping_the_engine_drops_objects() {
+    let mut engine = engine();
+    let log = DropLog::default();
+
+    // reachable from a global
+    engine.set_global_object("node", Node::new(1, &log));
+
+    // dead and waiting to be recycled
+    let _ = engine.alloc(Node::new(2, &log));
+    engine.collect_full();
+    assert!(log.borrow().is_empty());
+
+    // still in the nursery
+    let _ = engine.alloc(Node::new(3, &log));
+
+    drop(engine);
+    let mut dropped = log.borrow().clone();
+    dropped.sort();
+    assert_eq!(dropped, [1, 2, 3]);
+}
+
+#[test]
+fn rooted_host_objects_survive() {
+    let mut engine = engine();
+    engine.set_gc_workload(GcWorkload {
+        nursery_size: 4,
+        ..GcWorkload::default()
+    });
+
+    let log = DropLog::default();
+    let dr = engine.alloc(Node::new(1, &log));
+    let root = engine.root(dr).unwrap();
+
+    // allocations run minor collections and increments, and the garbage is recycled
+    for _ in 0..100 {
+        let _ = engine.alloc(Node::new(-1, &log));
+    }
+    engine.collect_full();
+    assert!(log.borrow().contains(&-1));
+    assert!(!log.borrow().contains(&1));
+    assert!(matches!(&*root, TestBundle::Node(_)));
+
+    // the root can be passed to scripts
+    engine.register_fn("nodeValue", |val: Primitive<Gc, TestBundle>|node_value(&val));
+    assert_eq!(engine.call::<_, i64>("nodeValue", (Primitive::Data(root.data_ref()),)).unwrap(), 1);
+
+    // once unrooted, it is collected like any other object
+    let live = engine.gc_stats().live();
+    engine.unroot(root);
+    engine.collect_full();
+    assert_eq!(engine.gc_stats().live(), live - 1);
+}
@@ -0,0 +1,103 @@
+//! Tests for parsing more source into an existing program and running only the new roots, the way
+//! a REPL does.
+
+
+mod common;
+
+use eka::{
+    interpreter::{
+        Interpreter as _,
+        Primitive,
+    },
+    parser::ParserData,
+    treewalk::Interpreter,
+    engine::Engine,
+};
+use common::*;
+
+
+struct Repl {
+    data: ParserData,
+    interpreter: Interpreter<TestBundle>,
+}
+impl Repl {
+    fn new()->Self {
+        let mut data = ParserData::default();
+        let interpreter = Interpreter::new(&mut data.interner);
+        Repl {data, interpreter}
+    }
+
+    fn eval(&mut self, source: &str)->anyhow::Result<Primitive<Gc, TestBundle>> {
+        if let Err(e) = self.data.parse_source("<repl>", source) {
+            self.interpreter.skip_new_roots(&self.data.exprs);
+            return Err(e);
+        }
+
+        self.interpreter.run(&self.data.exprs, &self.data.funcs, &mut self.data.interner)
+    }
+
+    fn eval_int(&mut self, source: &str)->i64 {
+        match self.eval(source).unwrap() {
+            Primitive::Number(n)=>n,
+            other=>panic!("`{source}` returned {other:?}"),
+        }
+    }
+}
+
+
+#[test]
+fn globals_outlive_each_run() {
+    let mut repl = Repl::new();
+    repl.eval("(def a 2)").unwrap();
+    repl.eval("(defn double [x] (* x 2))").unwrap();
+
+    assert_eq!(repl.eval_int("(double a)"), 4);
+    assert_eq!(repl.eval_int("(+ a 1)"), 3);
+}
+
+#[test]
+fn only_new_roots_run() {
+    let mut repl = Repl::new();
+    repl.eval("(def n 1)").unwrap();
+    assert_eq!(repl.interpreter.ran_roots(), 1);
+
+    // rerunning the `def` would reset `n`
+    repl.eval("(set n (+ n 1))").unwrap();
+    repl.eval("(set n (+ n 1))").unwrap();
+    assert_eq!(repl.eval_int("n"), 3);
+    assert_eq!(repl.interpreter.ran_roots(), 4);
+
+    assert!(matches!(repl.eval("").unwrap(), Primitive::None));
+}
+
+#[test]
+fn broken_source_is_not_run() {
+    let mut repl = Repl::new();
+    repl.eval("(def n 1)").unwrap();
+
+    assert!(repl.eval("(set n 5) (def").is_err());
+    assert!(repl.eval("(undefinedVar)").is_err());
+
+    assert_eq!(repl.eval_int("n"), 1);
+}
+
+#[test]
+fn new_roots_are_returned() {
+    let mut data = ParserData::default();
+    assert_eq!(data.parse_source("a.eka", "(def a 1) (def b 2)").unwrap().len(), 2);
+
+    let roots = data.parse_source("b.eka", "(+ a b)").unwrap();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(data.exprs.root_count(), 3);
+    assert_eq!(data.exprs.iter_roots().last(), roots.last());
+}
+
+#[test]
+fn engine_loads_incrementally() {
+    let mut engine = Engine::<TestBundle>::new();
+    engine.load_str("(defn square [x :int] :int (* x x))").unwrap();
+    engine.load_str("(def total (square 4))").unwrap();
+
+    assert_eq!(engine.get_global::<i64>("total").unwrap(), 16);
+    assert_eq!(engine.call::<_, i64>("square", (5,)).unwrap(), 25);
+}
@@ -0,0 +1,150 @@
+//! Tests for modules, imports, and exports. The modules are in `tests/modules`, and imports in
+//! strings are relative to the crate root, which is where tests run.
+
+
+mod common;
+
+use eka::engine::Engine;
+use common::*;
+
+
+fn engine()->Engine<TestBundle> {
+    let mut engine = Engine::new();
+    engine.set_global("loadCount", 0i64).unwrap();
+
+    return engine;
+}
+
+fn eval_int(engine: &mut Engine<TestBundle>, source: &str)->i64 {
+    engine.load_str(&format!("(def result {source})")).unwrap();
+    engine.get_global::<i64>("result").unwrap()
+}
+
+fn load_err(engine: &mut Engine<TestBundle>, source: &str)->String {
+    let err = engine.load_str(source).unwrap_err();
+    format!("{err:#}")
+}
+
+
+#[test]
+fn exports_are_namespaced() {
+    let mut engine = engine();
+    engine.load_str("(import \"tests/modules/math.eka\")").unwrap();
+    assert_eq!(eval_int(&mut engine, "(math/add 2 (math/square 3))"), 11);
+
+    engine.load_str("(import \"tests/modules/math.eka\" :as m)").unwrap();
+    assert_eq!(eval_int(&mut engine, "(m/square 4)"), 16);
+}
+
+#[test]
+fn private_names_are_hidden() {
+    let mut engine = engine();
+    engine.load_str("(import \"tests/modules/math.eka\")").unwrap();
+
+    let err = load_err(&mut engine, "(math/secret)");
+    assert!(err.contains("Module `math` has no export `secret`"), "{err}");
+
+    // module globals don't clash with the globals of scripts
+    let err = load_err(&mut engine, "(secret)");
+    assert!(err.contains("`secret` is undefined"), "{err}");
+    engine.load_str("(defn add [a b] (- a b))").unwrap();
+    assert_eq!(eval_int(&mut engine, "(+ (add 5 1) (math/add 5 1))"), 10);
+}
+
+#[test]
+fn modules_without_exports_export_everything() {
+    let mut engine = engine();
+    engine.load_file("tests/modules/shapes/rect.eka").unwrap();
+    engine.load_str("(import \"tests/modules/shapes/rect.eka\")").unwrap();
+
+    assert_eq!(eval_int(&mut engine, "(rect/area rect/sides 3)"), 12);
+    assert_eq!(eval_int(&mut engine, "(rect/squareArea 5)"), 25);
+}
+
+#[test]
+fn modules_are_loaded_once() {
+    let mut engine = engine();
+    // `rect.eka` imports `../math.eka` relative to itself
+    engine.load_str("\
+(import \"tests/modules/shapes/rect.eka\")
+(import \"tests/modules/math.eka\")
+(import \"./tests/modules/shapes/../math.eka\" :as again)").unwrap();
+    engine.load_str("(import \"tests/modules/math.eka\")").unwrap();
+
+    assert_eq!(engine.get_global::<i64>("loadCount").unwrap(), 1);
+    assert_eq!(eval_int(&mut engine, "(again/add (rect/squareArea 2) 1)"), 5);
+}
+
+#[test]
+fn import_cycles_are_errors() {
+    let mut engine = engine();
+    let err = load_err(&mut engine, "(import \"tests/modules/cycle_a.eka\")");
+    assert!(err.contains("Import cycle: cycle_a.eka -> cycle_b.eka -> cycle_a.eka"), "{err}");
+}
+
+#[test]
+fn failed_loads_are_rolled_back() {
+    let mut engine = engine();
+    let err = load_err(&mut engine, "(import \"tests/modules/missing.eka\")");
+    assert!(err.contains("Could not find the module `tests/modules/missing.eka`"), "{err}");
+
+    // a module that failed to run is loaded again the next time it is imported
+    engine.load_str("(import \"tests/modules/broken.eka\")").unwrap_err();
+    engine.load_str("(import \"tests/modules/broken.eka\")").unwrap_err();
+    assert_eq!(engine.get_global::<i64>("loadCount").unwrap(), 2);
+}
+
+#[test]
+fn inline_modules() {
+    let mut engine = engine();
+    engine.load_str("\
+(module vec
+    (export len2)
+    (defn square [x] (* x x))
+    (defn len2 [x y] (+ (square x) (square y))))
+(module outer
+    (module inner
+        (def value 3))
+    (def value (* inner/value 2)))").unwrap();
+
+    assert_eq!(eval_int(&mut engine, "(vec/len2 3 4)"), 25);
+    assert_eq!(eval_int(&mut engine, "outer/value"), 6);
+
+    let err = load_err(&mut engine, "(vec/square 2)");
+    assert!(err.contains("Module `vec` has no export `square`"), "{err}");
+
+    let err = load_err(&mut engine, "(set outer/value 1)");
+    assert!(err.contains("Cannot set `outer/value` from outside of its module"), "{err}");
+
+    let err = load_err(&mut engine, "(defn f [] (import \"tests/modules/math.eka\"))");
+    assert!(err.contains("only allowed at the top level"), "{err}");
+
+    let err = load_err(&mut engine, "(module m (export missing))");
+    assert!(err.contains("`missing` is exported, but never defined in the module"), "{err}");
+}
+
+#[test]
+fn host_modules() {
+    let mut engine = engine();
+    engine.register_module_fn("host", "double", |x: i64|x * 2);
+    engine.set_module_global("host", "answer", 21i64).unwrap();
+
+    engine.load_str("(import \"host\" :as h)").unwrap();
+    assert_eq!(eval_int(&mut engine, "(h/double h/answer)"), 42);
+
+    let err = load_err(&mut engine, "(h/triple 1)");
+    assert!(err.contains("Module `h` has no export `triple`"), "{err}");
+}
+
+#[test]
+fn host_modules_are_not_files() {
+    // a host module named like the canonical path of a file doesn't stand in for the file
+    let mut engine = engine();
+    let path = std::fs::canonicalize("tests/modules/math.eka").unwrap();
+    engine.set_module_global(&path.display().to_string(), "answer", 42i64).unwrap();
+
+    engine.load_str("(import \"tests/modules/math.eka\")").unwrap();
+    assert_eq!(eval_int(&mut engine, "(math/square 3)"), 9);
+    let err = load_err(&mut engine, "math/answer");
+    assert!(err.contains("Module `math` has no export `answer`"), "{err}");
+}
@@ -0,0 +1,103 @@
+//! Tests for native functions: typed closures registered with `register_fn`, and native function
+//! objects that capture script values.
+
+
+mod common;
+
+use anyhow::{
+    Result,
+    bail,
+};
+use eka::{
+    interpreter::{
+        object::*,
+        native::*,
+        Primitive,
+    },
+    engine::Engine,
+};
+use common::*;
+
+
+fn call_err<A: IntoArgs<Gc, TestBundle>>(engine: &mut Engine<TestBundle>, name: &str, args: A)->String {
+    let err = engine.call::<_, Primitive<Gc, TestBundle>>(name, args).unwrap_err();
+    format!("{err:#}")
+}
+
+
+#[test]
+fn typed_fns_check_arity() {
+    let mut engine = Engine::<TestBundle>::new();
+    engine.register_fn("zero", ||0i64);
+    engine.register_fn("add", |a: i64, b: i64|a + b);
+
+    assert_eq!(engine.call::<_, i64>("zero", ()).unwrap(), 0);
+    assert_eq!(engine.call::<_, i64>("add", (1i64, 2i64)).unwrap(), 3);
+
+    let err = call_err(&mut engine, "zero", (1i64,));
+    assert!(err.contains("`zero` expected 0 args, but got 1"), "{err}");
+    let err = call_err(&mut engine, "add", (1i64,));
+    assert!(err.contains("`add` expected 2 args, but got 1"), "{err}");
+    let err = call_err(&mut engine, "add", (1i64, 2i64, 3i64));
+    assert!(err.contains("`add` expected 2 args, but got 3"), "{err}");
+}
+
+#[test]
+fn typed_fns_convert_args() {
+    let mut engine = Engine::<TestBundle>::new();
+    engine.register_fn("add", |a: i64, b: i64|a + b);
+    engine.register_fn("greet", |name: String, loud: bool|{
+        if loud {format!("HI {name}")} else {format!("hi {name}")}
+    });
+    engine.register_fn("orDefault", |n: Option<i64>|n.unwrap_or(-1));
+
+    let err = call_err(&mut engine, "add", (1i64, "two"));
+    assert!(err.contains("`add` argument 2 expected Number, but got String"), "{err}");
+    let err = call_err(&mut engine, "add", (1.5f64, 2i64));
+    assert!(err.contains("`add` argument 1 expected Number, but got Float"), "{err}");
+    let err = call_err(&mut engine, "greet", ("eka", 1i64));
+    assert!(err.contains("`greet` argument 2 expected Bool, but got Number"), "{err}");
+
+    assert_eq!(engine.call::<_, String>("greet", ("eka", true)).unwrap(), "HI eka");
+    assert_eq!(engine.call::<_, i64>("orDefault", ((),)).unwrap(), -1);
+    assert_eq!(engine.call::<_, i64>("orDefault", (5i64,)).unwrap(), 5);
+}
+
+#[test]
+fn typed_fn_errors_are_returned() {
+    let mut engine = Engine::<TestBundle>::new();
+    engine.register_fn("checkedDiv", |a: i64, b: i64|->Result<i64> {
+        if b == 0 {
+            bail!("Division by zero");
+        }
+        return Ok(a / b);
+    });
+
+    assert_eq!(engine.call::<_, i64>("checkedDiv", (6i64, 3i64)).unwrap(), 2);
+    let err = call_err(&mut engine, "checkedDiv", (1i64, 0i64));
+    assert!(err.contains("Division by zero"), "{err}");
+}
+
+#[test]
+fn captures_are_traced() {
+    let mut engine = Engine::<TestBundle>::new();
+    let log = DropLog::default();
+    let node = engine.alloc(Node::new(7, &log));
+
+    // rooted until the function holding it is stored, since storing it allocates
+    let root = engine.root(node).unwrap();
+    let func = NativeFnObject::with_captures("getNode", vec![Primitive::Data(root.data_ref())], |captures, _, _, _|{
+        Ok(CallReturn::Data(captures[0].clone()))
+    });
+    engine.set_global_object("getNode", func);
+    engine.unroot(root);
+
+    // the node is only reachable through the capture
+    engine.collect_full();
+    engine.collect_full();
+    assert_eq!(live_nodes(&mut engine), 1);
+    assert!(log.borrow().is_empty());
+
+    let node = engine.call::<_, Primitive<Gc, TestBundle>>("getNode", ()).unwrap();
+    assert_eq!(node_value(&node).unwrap(), 7);
+}
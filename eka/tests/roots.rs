@@ -0,0 +1,211 @@
+//! Stress tests for the GC roots of the interpreter. Each test keeps objects alive only through one
+//! kind of root while scripts run collections, and checks that none of them were freed.
+
+
+mod common;
+
+use anyhow::Result;
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::Duration,
+};
+use eka::{
+    interpreter::{
+        object::*,
+        native::*,
+        Primitive,
+    },
+    engine::{
+        Engine,
+        GcWorkload,
+    },
+};
+use common::*;
+
+
+/// The count of tracked nodes that were dropped when the last collection a script ran finished.
+/// Nodes with negative values are garbage that tests don't track.
+type DropCount = Rc<Cell<usize>>;
+
+
+/// An engine with these globals:
+/// - `(makeNode value)` allocates a node
+/// - `(nodeValue node)` returns the value of a node
+/// - `(collect)` runs a full collection and frees the dead objects
+/// - `(step)` collects the nursery and runs a single increment
+///
+/// Both record the count of tracked nodes dropped so far.
+fn engine()->(Engine<TestBundle>, DropLog, DropCount) {
+    let log = DropLog::default();
+    let drops = DropCount::default();
+    let mut engine = Engine::new();
+
+    let node_log = log.clone();
+    engine.register_fn("makeNode", move|value: i64|Alloc(Node::new(value, &node_log)));
+    engine.register_fn("nodeValue", |node: Primitive<Gc, TestBundle>|->Result<i64> {
+        node_value(&node)
+    });
+
+    let (collect_log, collect_drops) = (log.clone(), drops.clone());
+    engine.set_global_object("collect", NativeFnObject::new("collect", move|_, _, gc: &mut Gc|{
+        gc.collect_full();
+        gc.free_dead();
+        collect_drops.set(tracked_drops(&collect_log));
+        return Ok(CallReturn::Data(Primitive::None));
+    }));
+    let (step_log, step_drops) = (log.clone(), drops.clone());
+    engine.set_global_object("step", NativeFnObject::new("step", move|_, _, gc: &mut Gc|{
+        gc.step_for(Duration::ZERO);
+        step_drops.set(tracked_drops(&step_log));
+        return Ok(CallReturn::Data(Primitive::None));
+    }));
+
+    return (engine, log, drops);
+}
+
+fn tracked_drops(log: &DropLog)->usize {
+    log.borrow()
+        .iter()
+        .filter(|v|**v >= 0)
+        .count()
+}
+
+/// Run the source and check that no tracked node was dropped during the collections it ran.
+/// Returns the value of the source.
+fn run(source: &str)->i64 {
+    run_with(source, GcWorkload::default())
+}
+
+fn run_with(source: &str, workload: GcWorkload)->i64 {
+    let (mut engine, log, drops) = engine();
+    engine.set_gc_workload(workload);
+    let val = match engine.load_str(source) {
+        Ok(Primitive::Number(n))=>n,
+        Ok(other)=>panic!("Expected a number, but got {other:?}"),
+        Err(e)=>panic!("{}", engine.render_error(&e, false)),
+    };
+
+    assert_eq!(drops.get(), 0, "Nodes were dropped: {:?}", log.borrow());
+    return val;
+}
+
+
+#[test]
+fn globals() {
+    assert_eq!(run("\
+(def a (makeNode 1))
+(def b (makeNode 2))
+(collect)
+(collect)
+(+ (nodeValue a) (nodeValue b))"), 3);
+}
+
+#[test]
+fn locals() {
+    assert_eq!(run("\
+(defn f []
+    (def local (makeNode 1))
+    (collect)
+    (nodeValue local))
+(f)"), 1);
+}
+
+#[test]
+fn params() {
+    assert_eq!(run("\
+(defn f [a b]
+    (collect)
+    (+ (nodeValue a) (nodeValue b)))
+(f (makeNode 1) (makeNode 2))"), 3);
+}
+
+#[test]
+fn caller_frames() {
+    assert_eq!(run("\
+(defn inner [depth]
+    (collect)
+    depth)
+(defn middle []
+    (def m (makeNode 2))
+    (+ (inner 1) (nodeValue m)))
+(defn outer []
+    (def o (makeNode 3))
+    (+ (middle) (nodeValue o)))
+(outer)"), 6);
+}
+
+#[test]
+fn temps() {
+    // the first node is only held as an evaluated argument while the second one is evaluated
+    assert_eq!(run("\
+(defn add [a b] (+ (nodeValue a) (nodeValue b)))
+(add (makeNode 1) (begin (collect) (makeNode 2)))"), 3);
+}
+
+/// Nodes held by a global, locals in nested frames, and a temp, while garbage is allocated and
+/// the GC runs in small steps.
+fn churn_source()->String {
+    let churn = "(churn)\n".repeat(20);
+    return format!("\
+(defn churn [] (makeNode -1) (makeNode -1) (step))
+(defn add [a b] (+ (nodeValue a) (nodeValue b)))
+(defn level3 []
+    (def c (makeNode 3))
+    {churn}
+    (add (makeNode 4) (begin {churn} (makeNode 5))))
+(defn level2 []
+    (def b (makeNode 2))
+    {churn}
+    (+ (level3) (nodeValue b)))
+(def a (makeNode 1))
+{churn}
+(+ (level2) (nodeValue a))");
+}
+
+#[test]
+fn small_steps() {
+    assert_eq!(run(&churn_source()), 12);
+}
+
+#[test]
+fn collect_during_alloc() {
+    // the smallest workload collects the nursery and steps the GC on almost every allocation
+    let workload = GcWorkload {
+        traces: 1,
+        mark_dead: 1,
+        gc_when_no_dead: true,
+        nursery_size: 1,
+    };
+    assert_eq!(run_with(&churn_source(), workload), 12);
+
+    let workload = GcWorkload {
+        nursery_size: 0,
+        ..workload
+    };
+    assert_eq!(run_with(&churn_source(), workload), 12);
+}
+
+#[test]
+fn fields() {
+    // the only reference to the child is stored in a node that may already be traced
+    let churn = "(churn)\n".repeat(20);
+    assert_eq!(run(&format!("\
+(defn churn [] (makeNode -1) (makeNode -1) (step))
+(def holder (makeNode 0))
+{churn}
+(set holder/child (makeNode 1))
+{churn}
+(collect)
+(nodeValue holder/child)")), 1);
+}
+
+#[test]
+fn garbage_is_collected() {
+    let (mut engine, log, _) = engine();
+    engine.load_str("(defn f [] (makeNode 1) (makeNode 2) 0) (f) (collect)").unwrap();
+
+    let mut dropped = log.borrow().clone();
+    dropped.sort();
+    assert_eq!(dropped, [1, 2]);
+}
@@ -17,8 +17,8 @@ pub type IdentMap<T> = FxHashMap<Ident, T>;
 pub enum Expr {
     Begin(Vec<ExprId>),
 
-    /// Define a var in the given scope
-    DefVar(Ident, ExprId),
+    /// Define a var in the given scope with an optional type annotation
+    DefVar(Ident, Option<Type>, ExprId),
     /// Set a variable with the given expr's data
     SetVar(Ident, ExprId),
     /// Get the data in a variable
@@ -58,6 +58,20 @@ pub enum Expr {
     Bool(bool),
     Keyword(Ident),
     None,
+
+    /// `(module name ...)` defines a module inside a file. Like `import` and `export`, it has to
+    /// be resolved by a module loader before it can be run.
+    Module(Ident, Vec<ExprId>),
+    /// `(import "path.eka" :as name)` makes the exports of the module usable as `name/export`.
+    /// Without `:as`, the name is the file name without the extension.
+    Import(Rc<String>, Option<Ident>),
+    /// `(export names...)` makes the names visible to modules importing this one. Modules without
+    /// an export export every top-level definition.
+    Export(Vec<Ident>),
+
+    /// Source that failed to parse. The parser keeps going after errors, so tooling can still use
+    /// the rest of the AST.
+    Error,
 }
 
 
@@ -79,6 +93,12 @@ impl Interner {
     pub fn get(&self, id: Ident)->&str {
         self.0.get_index(id.0).unwrap()
     }
+
+    /// Get the ident for the string without interning it.
+    #[inline]
+    pub fn lookup(&self, s: &str)->Option<Ident> {
+        self.0.get_index_of(s).map(Ident::from_id)
+    }
 }
 
 #[derive(Debug)]
@@ -92,5 +112,59 @@ pub struct Function {
     pub name: Ident,
     pub captures: Vec<Ident>,
     pub params: Vec<Ident>,
+    /// The type annotations for `params`. Always the same length as `params`.
+    pub param_types: Vec<Option<Type>>,
+    pub ret_type: Option<Type>,
     pub block: ExprId,
+    /// From a `;;;` doc comment before the definition, or a string right after the name.
+    pub doc: Option<Rc<String>>,
+}
+
+/// A type annotation like `:int` or `:Vec2`. Unannotated values are dynamic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Any,
+    Int,
+    Float,
+    String,
+    Char,
+    Bool,
+    Keyword,
+    None,
+    Function,
+    /// An object type named by [`Object::type_name`](crate::interpreter::object::Object::type_name)
+    Object(Ident),
+}
+impl Type {
+    /// The builtin type with the name, or `None` if there isn't one. Object types are looked up by
+    /// the parser, because it knows which ones are declared.
+    pub fn from_name(name: &str)->Option<Self> {
+        match name {
+            "any"=>Some(Type::Any),
+            "int"=>Some(Type::Int),
+            "float"=>Some(Type::Float),
+            "string"=>Some(Type::String),
+            "char"=>Some(Type::Char),
+            "bool"=>Some(Type::Bool),
+            "keyword"=>Some(Type::Keyword),
+            "none"=>Some(Type::None),
+            "fn"=>Some(Type::Function),
+            _=>None,
+        }
+    }
+
+    pub fn name<'a>(&self, interner: &'a Interner)->&'a str {
+        match self {
+            Type::Any=>"any",
+            Type::Int=>"int",
+            Type::Float=>"float",
+            Type::String=>"string",
+            Type::Char=>"char",
+            Type::Bool=>"bool",
+            Type::Keyword=>"keyword",
+            Type::None=>"none",
+            Type::Function=>"fn",
+            Type::Object(name)=>interner.get(*name),
+        }
+    }
 }
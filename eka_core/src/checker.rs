@@ -0,0 +1,402 @@
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult,
+};
+use crate::{
+    ast::*,
+    diagnostics::Diagnostic,
+};
+
+
+/// A type inferred by the [`TypeChecker`]. Anything the checker can't figure out is `Any`, so
+/// unannotated code always passes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InferredType {
+    Any,
+    Known(Type),
+    /// A script function with a known definition
+    Function(FnId),
+    Builtin(Builtin),
+}
+impl InferredType {
+    pub fn name<'a>(&self, interner: &'a Interner)->&'a str {
+        match self {
+            InferredType::Any=>"any",
+            InferredType::Known(ty)=>ty.name(interner),
+            InferredType::Function(_)|InferredType::Builtin(_)=>"fn",
+        }
+    }
+
+    /// Check if a value of this type can be stored where `ty` is expected.
+    pub fn fits(&self, ty: Type)->bool {
+        match (self, ty) {
+            (_, Type::Any)|(InferredType::Any, _)=>true,
+            (InferredType::Known(t), ty)=>*t == ty,
+            (InferredType::Function(_)|InferredType::Builtin(_), Type::Function)=>true,
+            _=>false,
+        }
+    }
+}
+
+/// The builtin functions the checker knows about.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Builtin {
+    /// `+`, `-`, `*`, and `/`
+    Arithmetic,
+    Format,
+    Doc,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    pub expr: ExprId,
+    pub message: String,
+}
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+impl TypeError {
+    /// A diagnostic pointing at the expression, if the parser recorded where it is.
+    pub fn diagnostic(&self, exprs: &ExprStore)->Diagnostic {
+        let diag = Diagnostic::error(self.message.clone());
+        match exprs.span(self.expr) {
+            Some(span)=>diag.with_label(span, ""),
+            None=>diag,
+        }
+    }
+}
+
+
+/// A static type checker that runs over the AST before it is run. It only reports errors it is
+/// sure about, so values it can't infer are treated as `any`.
+pub struct TypeChecker<'a> {
+    exprs: &'a ExprStore,
+    funcs: &'a FunctionStore,
+    interner: &'a Interner,
+    globals: IdentMap<InferredType>,
+    scopes: Vec<IdentMap<InferredType>>,
+    errors: Vec<TypeError>,
+}
+impl<'a> TypeChecker<'a> {
+    pub fn new(exprs: &'a ExprStore, funcs: &'a FunctionStore, interner: &'a Interner)->Self {
+        let mut checker = TypeChecker {
+            exprs,
+            funcs,
+            interner,
+            globals: IdentMap::default(),
+            scopes: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for name in ["+", "-", "*", "/"] {
+            checker.declare_global_str(name, InferredType::Builtin(Builtin::Arithmetic));
+        }
+        checker.declare_global_str("format", InferredType::Builtin(Builtin::Format));
+        checker.declare_global_str("doc", InferredType::Builtin(Builtin::Doc));
+
+        return checker;
+    }
+
+    /// Declare the type of a global defined by the host. Undeclared globals are `any`.
+    pub fn declare_global(&mut self, name: Ident, ty: InferredType) {
+        self.globals.insert(name, ty);
+    }
+
+    fn declare_global_str(&mut self, name: &str, ty: InferredType) {
+        // If the name was never interned, then no code uses it.
+        if let Some(name) = self.interner.lookup(name) {
+            self.globals.insert(name, ty);
+        }
+    }
+
+    /// Check every root in the expression store.
+    pub fn check(self)->Vec<TypeError> {
+        let roots = self.exprs.iter_roots().copied().collect::<Vec<_>>();
+        self.check_roots(roots)
+    }
+
+    /// Check the given roots. Top-level functions are declared before anything is checked, so they
+    /// can be used before their definition.
+    pub fn check_roots<I: IntoIterator<Item = ExprId>>(mut self, roots: I)->Vec<TypeError> {
+        let roots = roots.into_iter().collect::<Vec<_>>();
+
+        let exprs = self.exprs;
+        for root in roots.iter() {
+            if let Expr::DefVar(name, _, data) = &exprs[*root] {
+                if let Expr::Function(id) = &exprs[*data] {
+                    self.globals.insert(*name, InferredType::Function(*id));
+                }
+            }
+        }
+
+        for root in roots {
+            self.infer(root);
+        }
+
+        return self.errors;
+    }
+
+    fn infer(&mut self, id: ExprId)->InferredType {
+        use InferredType::*;
+        let exprs = self.exprs;
+        match &exprs[id] {
+            Expr::Begin(block)=>{
+                self.scopes.push(IdentMap::default());
+                let mut last = Known(Type::None);
+                for expr in block.iter() {
+                    last = self.infer(*expr);
+                }
+                self.scopes.pop();
+
+                last
+            },
+
+            Expr::DefVar(name, ty, data)=>{
+                let inferred = self.infer(*data);
+                // unannotated vars can be set to any type later, so only annotations are `Known`
+                let mut var_ty = match inferred {
+                    Known(_)=>Any,
+                    _=>inferred,
+                };
+                if let Some(ty) = ty {
+                    if !inferred.fits(*ty) {
+                        self.error(id, format!(
+                            "Var `{}` is annotated as {}, but is assigned {}",
+                            self.interner.get(*name),
+                            ty.name(self.interner),
+                            inferred.name(self.interner),
+                        ));
+                    }
+                    var_ty = Known(*ty);
+                }
+                self.define(*name, var_ty);
+
+                Known(Type::None)
+            },
+            Expr::SetVar(name, data)=>{
+                let inferred = self.infer(*data);
+                // vars can change type unless annotated, so we only keep annotated types.
+                if let Some(Known(ty)) = self.lookup(*name) {
+                    if !inferred.fits(ty) {
+                        self.error(id, format!(
+                            "Var `{}` has type {}, but is set to {}",
+                            self.interner.get(*name),
+                            ty.name(self.interner),
+                            inferred.name(self.interner),
+                        ));
+                    }
+                } else {
+                    self.assign(*name, Any);
+                }
+
+                Known(Type::None)
+            },
+            Expr::GetVar(name)=>self.lookup(*name).unwrap_or(Any),
+
+            Expr::Cond{branches,default}=>{
+                let mut out: Option<InferredType> = Option::None;
+                let mut bodies = branches.iter()
+                    .map(|b|{
+                        self.infer(b.condition);
+                        b.body
+                    })
+                    .collect::<Vec<_>>();
+                bodies.extend(default.iter().copied());
+
+                for body in bodies {
+                    let ty = self.infer(body);
+                    out = match out {
+                        Some(prev) if prev != ty=>Some(Any),
+                        _=>Some(ty),
+                    };
+                }
+
+                if default.is_none() {
+                    Any
+                } else {
+                    out.unwrap_or(Known(Type::None))
+                }
+            },
+
+            Expr::Function(func)|Expr::Closure(func)=>{
+                self.check_function(*func);
+                Function(*func)
+            },
+
+            Expr::Call(lhs, args)=>{
+                let lhs_ty = match &exprs[*lhs] {
+                    // method calls are always dynamic
+                    Expr::GetPath(_)=>Any,
+                    _=>self.infer(*lhs),
+                };
+                let arg_tys = args.iter()
+                    .map(|arg|self.infer(*arg))
+                    .collect::<Vec<_>>();
+
+                self.check_call(id, lhs_ty, &arg_tys)
+            },
+
+            Expr::Method(lhs, _, args)=>{
+                self.infer(*lhs);
+                for arg in args.iter() {
+                    self.infer(*arg);
+                }
+
+                Any
+            },
+
+            Expr::GetPath(_)=>Any,
+            Expr::SetPath{data,..}=>{
+                self.infer(*data);
+                Known(Type::None)
+            },
+
+            Expr::String(_)=>Known(Type::String),
+            Expr::Number(_)=>Known(Type::Int),
+            Expr::Float(_)=>Known(Type::Float),
+            Expr::Char(_)=>Known(Type::Char),
+            Expr::Bool(_)=>Known(Type::Bool),
+            Expr::Keyword(_)=>Known(Type::Keyword),
+            Expr::None=>Known(Type::None),
+
+            // the module loader removes these before anything is checked
+            Expr::Module(..)|Expr::Import(..)|Expr::Export(_)=>Known(Type::None),
+
+            Expr::Error=>Any,
+        }
+    }
+
+    fn check_function(&mut self, id: FnId) {
+        let funcs = self.funcs;
+        let func = &funcs[id];
+
+        // captures and params are the only things visible in a function besides globals
+        let mut scope = IdentMap::default();
+        for cap in func.captures.iter() {
+            let ty = self.lookup(*cap).unwrap_or(InferredType::Any);
+            scope.insert(*cap, ty);
+        }
+        for (param, ty) in func.params.iter().zip(func.param_types.iter()) {
+            let ty = ty.map(InferredType::Known).unwrap_or(InferredType::Any);
+            scope.insert(*param, ty);
+        }
+
+        let old_scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let ret = self.infer(func.block);
+        self.scopes = old_scopes;
+
+        if let Some(ty) = func.ret_type {
+            if !ret.fits(ty) {
+                self.error(func.block, format!(
+                    "`{}` should return {}, but returns {}",
+                    self.interner.get(func.name),
+                    ty.name(self.interner),
+                    ret.name(self.interner),
+                ));
+            }
+        }
+    }
+
+    fn check_call(&mut self, id: ExprId, lhs: InferredType, args: &[InferredType])->InferredType {
+        use InferredType::*;
+        match lhs {
+            Any=>Any,
+            Known(Type::Any|Type::Function|Type::Object(_))=>Any,
+            Known(ty)=>{
+                self.error(id, format!("Cannot call a value of type {}", ty.name(self.interner)));
+                Any
+            },
+            Function(func_id)=>{
+                let funcs = self.funcs;
+                let func = &funcs[func_id];
+                let name = self.interner.get(func.name);
+
+                if func.params.len() != args.len() {
+                    self.error(id, format!(
+                        "`{name}` expects {} args, but got {}",
+                        func.params.len(),
+                        args.len(),
+                    ));
+                    return func.ret_type.map(Known).unwrap_or(Any);
+                }
+
+                for ((param, ty), arg) in func.params.iter().zip(func.param_types.iter()).zip(args) {
+                    if let Some(ty) = ty {
+                        if !arg.fits(*ty) {
+                            self.error(id, format!(
+                                "Param `{}` of `{name}` expects {}, but got {}",
+                                self.interner.get(*param),
+                                ty.name(self.interner),
+                                arg.name(self.interner),
+                            ));
+                        }
+                    }
+                }
+
+                func.ret_type.map(Known).unwrap_or(Any)
+            },
+            InferredType::Builtin(self::Builtin::Format)=>Known(Type::String),
+            // a string, or `None` for undocumented functions
+            InferredType::Builtin(self::Builtin::Doc)=>Any,
+            InferredType::Builtin(self::Builtin::Arithmetic)=>{
+                let mut out: Option<Type> = Option::None;
+                for arg in args {
+                    match arg {
+                        Known(ty@(Type::Int|Type::Float))=>match out {
+                            Some(prev) if prev != *ty=>{
+                                self.error(id, "Cannot do arithmetic on a mix of int and float".into());
+                                return Any;
+                            },
+                            _=>out = Some(*ty),
+                        },
+                        Known(Type::Any|Type::Object(_))|Any=>return Any,
+                        other=>{
+                            self.error(id, format!("Cannot do arithmetic on {}", other.name(self.interner)));
+                            return Any;
+                        },
+                    }
+                }
+
+                out.map(Known).unwrap_or(Known(Type::None))
+            },
+        }
+    }
+
+    fn define(&mut self, name: Ident, ty: InferredType) {
+        match self.scopes.last_mut() {
+            Some(scope)=>{scope.insert(name, ty);},
+            None=>{self.globals.insert(name, ty);},
+        }
+    }
+
+    /// Overwrite the type of an existing var.
+    fn assign(&mut self, name: Ident, ty: InferredType) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(var) = scope.get_mut(&name) {
+                *var = ty;
+                return;
+            }
+        }
+
+        if let Some(var) = self.globals.get_mut(&name) {
+            *var = ty;
+        }
+    }
+
+    fn lookup(&self, name: Ident)->Option<InferredType> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.get(&name) {
+                return Some(*ty);
+            }
+        }
+
+        return self.globals.get(&name).copied();
+    }
+
+    fn error(&mut self, expr: ExprId, message: String) {
+        self.errors.push(TypeError {expr, message});
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::Error;
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult,
+    Write,
+};
+use crate::source::*;
+
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+
+/// How wide a tab is when a source line is shown.
+const TAB_WIDTH: usize = 4;
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+impl Severity {
+    pub fn name(&self)->&'static str {
+        match self {
+            Severity::Error=>"error",
+            Severity::Warning=>"warning",
+            Severity::Note=>"note",
+        }
+    }
+
+    fn color(&self)->&'static str {
+        match self {
+            Severity::Error=>"\x1b[1;31m",
+            Severity::Warning=>"\x1b[1;33m",
+            Severity::Note=>"\x1b[1;32m",
+        }
+    }
+}
+
+/// A span in the source with an optional message. Primary labels are underlined with `^` and
+/// secondary ones with `-`.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// An error, warning, or note about the source. Build one with [`Diagnostic::error`] and the
+/// `with_*` methods, then show it with [`Diagnostic::render`].
+///
+/// It is also an error type, so it can be returned through `anyhow` and found again with
+/// [`from_error`].
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+impl std::error::Error for Diagnostic {}
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>)->Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    #[inline]
+    pub fn error(message: impl Into<String>)->Self {
+        Self::new(Severity::Error, message)
+    }
+
+    #[inline]
+    pub fn warning(message: impl Into<String>)->Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    #[inline]
+    pub fn note(message: impl Into<String>)->Self {
+        Self::new(Severity::Note, message)
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>)->Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        return self;
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>)->Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        return self;
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>)->Self {
+        self.notes.push(note.into());
+        return self;
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>)->Self {
+        self.help = Some(help.into());
+        return self;
+    }
+
+    /// The span of the first primary label.
+    pub fn primary_span(&self)->Option<Span> {
+        self.labels.iter()
+            .find(|l|l.primary)
+            .map(|l|l.span)
+    }
+
+    /// Render the diagnostic with the source lines its labels point to. With `color`, ANSI escape
+    /// codes are used for a terminal.
+    pub fn render(&self, sources: &SourceMap, color: bool)->String {
+        let paint = |code: &'static str|if color {code} else {""};
+        let reset = paint(RESET);
+        let gutter_color = paint(BLUE);
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "{}{}{reset}{}: {}{reset}",
+            paint(self.severity.color()),
+            self.severity.name(),
+            paint(BOLD),
+            self.message,
+        ).unwrap();
+
+        // only labels in files we know about can be shown
+        let mut labels = self.labels.iter()
+            .filter(|l|sources.get(l.span.file).is_some())
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|l|(l.span.file.0, l.span.line, l.span.col));
+
+        let gutter = labels.iter()
+            .map(|l|l.span.line.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let pad = " ".repeat(gutter);
+
+        if let Some(span) = self.primary_span().or(labels.first().map(|l|l.span)) {
+            if sources.get(span.file).is_some() {
+                writeln!(out, "{pad}{gutter_color}-->{reset} {}", span.display(sources)).unwrap();
+            }
+        }
+
+        let mut i = 0;
+        while i < labels.len() {
+            let span = labels[i].span;
+            let file = &sources[span.file];
+            let line = file.line(span.line).unwrap_or("");
+            let same_line = labels[i..].iter()
+                .take_while(|l|l.span.file == span.file && l.span.line == span.line)
+                .count();
+
+            writeln!(out, "{pad} {gutter_color}|{reset}").unwrap();
+            writeln!(out, "{gutter_color}{:>gutter$} |{reset} {}", span.line, expand_tabs(line)).unwrap();
+
+            for label in labels[i..(i + same_line)].iter() {
+                let (start, len) = underline(file, line, label.span);
+                let (mark, mark_color) = if label.primary {
+                    ('^', paint(self.severity.color()))
+                } else {
+                    ('-', gutter_color)
+                };
+
+                write!(
+                    out,
+                    "{pad} {gutter_color}|{reset} {}{mark_color}{}",
+                    " ".repeat(start),
+                    mark.to_string().repeat(len),
+                ).unwrap();
+                if !label.message.is_empty() {
+                    write!(out, " {}", label.message).unwrap();
+                }
+                writeln!(out, "{reset}").unwrap();
+            }
+
+            i += same_line;
+        }
+
+        if !labels.is_empty() && (!self.notes.is_empty() || self.help.is_some()) {
+            writeln!(out, "{pad} {gutter_color}|{reset}").unwrap();
+        }
+        for note in self.notes.iter() {
+            writeln!(out, "{pad} {gutter_color}={reset} {}note{reset}: {note}", paint(BOLD)).unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(out, "{pad} {gutter_color}={reset} {}help{reset}: {help}", paint(CYAN)).unwrap();
+        }
+
+        return out;
+    }
+}
+
+/// Several diagnostics returned as one error, like every type error in a source.
+#[derive(Debug, Clone)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self.0.as_slice() {
+            [one]=>write!(f, "{one}"),
+            all=>{
+                write!(f, "{} errors:", all.len())?;
+                for diag in all {
+                    write!(f, "\n    {diag}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+impl std::error::Error for Diagnostics {}
+
+
+/// Get the diagnostics from an error. Errors that are not a [`Diagnostic`] or [`Diagnostics`]
+/// become a single error with the span from [`with_span`], if it has one. Any context added on
+/// top of the diagnostics is kept as notes.
+pub fn from_error(err: &Error)->Vec<Diagnostic> {
+    let span = error_span(err);
+    let span_context = span.map(|s|ErrorSpan(s).to_string());
+
+    // the innermost cause is the actual error and the rest is context
+    let mut context = err.chain()
+        .map(|cause|cause.to_string())
+        .filter(|msg|Some(msg) != span_context.as_ref())
+        .collect::<Vec<_>>();
+    let message = context.pop().unwrap_or_default();
+
+    let mut diags = if let Some(diags) = err.downcast_ref::<Diagnostics>() {
+        diags.0.clone()
+    } else if let Some(diag) = err.downcast_ref::<Diagnostic>() {
+        vec![diag.clone()]
+    } else {
+        let mut diag = Diagnostic::error(message);
+        if let Some(span) = span {
+            diag = diag.with_label(span, "");
+        }
+        vec![diag]
+    };
+
+    // the outermost context is the least specific, so it goes last
+    for diag in diags.iter_mut() {
+        diag.notes.extend(context.iter().rev().cloned());
+    }
+    return diags;
+}
+
+/// Render every diagnostic in the error. See [`from_error`].
+pub fn render_error(err: &Error, sources: &SourceMap, color: bool)->String {
+    from_error(err)
+        .iter()
+        .map(|diag|diag.render(sources, color))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+
+/// Replace tabs so the underline lines up with the shown source line.
+fn expand_tabs(line: &str)->String {
+    line.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// The column and length of the underline for the span on its first line.
+fn underline(file: &SourceFile, line: &str, span: Span)->(usize, usize) {
+    let display_width = |s: &str|s.chars()
+        .map(|c|if c == '\t' {TAB_WIDTH} else {1})
+        .sum::<usize>();
+
+    let col = (span.col as usize).saturating_sub(1);
+    let before = line.chars().take(col).collect::<String>();
+    let start = display_width(&before);
+
+    // spans over several lines are only underlined to the end of the first one
+    let line_rest = line.chars().skip(col).collect::<String>();
+    let text = file.source.get(span.start..span.end).unwrap_or("");
+    let text = text.split('\n').next().unwrap_or("");
+    let text = if text.len() > line_rest.len() {&line_rest} else {text};
+
+    return (start, display_width(text).max(1));
+}
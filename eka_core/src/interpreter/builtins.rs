@@ -81,6 +81,14 @@ pub fn div<Gc: GcTrait<O>, O: ObjectBundle<Gc>>(args: Vec<Primitive<Gc, O>>, _:
     return Ok(CallReturn::Data(first));
 }
 
+pub fn doc<Gc: GcTrait<O>, O: ObjectBundle<Gc>>(args: Vec<Primitive<Gc, O>>, _: &mut Interner, _: &mut Gc)->Result<CallReturn<Gc, O>> {
+    match args.as_slice() {
+        [Primitive::Fn(id)]=>Ok(CallReturn::Doc(*id)),
+        [other]=>bail!("Only script functions have docs, but got {}", other.type_name()),
+        _=>bail!("Expected 1 arg, but got {}", args.len()),
+    }
+}
+
 pub fn format<Gc: GcTrait<O>, O: ObjectBundle<Gc>>(args: Vec<Primitive<Gc, O>>, interner: &mut Interner, _: &mut Gc)->Result<CallReturn<Gc, O>> {
     let mut out = String::new();
     for arg in args {
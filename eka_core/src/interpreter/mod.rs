@@ -1,6 +1,7 @@
 use anyhow::Result;
 use std::{
     fmt::Debug,
+    ops::Deref,
     rc::Rc,
 };
 use object::{
@@ -13,18 +14,20 @@ use crate::ast::{
     Interner,
     Ident,
     FnId,
+    Type,
 };
 
 
 pub mod object;
 pub mod builtins;
+pub mod native;
 
 
 pub type NativeFn<Gc, O> = fn(Vec<Primitive<Gc, O>>, &mut Interner, &mut Gc)->Result<CallReturn<Gc, O>>;
 
 
 pub trait GcTrait<O: ObjectBundle<Self>>: Sized + Debug {
-    type DataRef: Clone + Debug;
+    type DataRef: Clone + Debug + Deref<Target = O>;
     fn alloc<RO: Into<O>>(&mut self, data: RO)->Self::DataRef;
     fn trace(&mut self, ptr: Self::DataRef);
 }
@@ -42,8 +45,10 @@ pub trait Interpreter<O: ObjectBundle<Self::Gc>> {
         _interner: &mut Interner,
     )->Result<()> {Ok(())}
 
-    /// Run the given expressions. Callers are required to ensure `compile` was called after any
-    /// changes to `ExprStore` or `FunctionStore` were made and before this method is called.
+    /// Run the roots added to the `ExprStore` since the last call, so more source can be parsed
+    /// into the stores between runs. Globals defined by earlier runs stay defined. Callers are
+    /// required to ensure `compile` was called after any changes to `ExprStore` or `FunctionStore`
+    /// were made and before this method is called.
     fn run(&mut self,
         exprs: &ExprStore,
         funcs: &FunctionStore,
@@ -82,3 +87,42 @@ impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> Clone for Primitive<Gc, O> {
         }
     }
 }
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> Primitive<Gc, O> {
+    /// The name of the primitive's type used in error messages.
+    pub fn type_name(&self)->&'static str {
+        use Primitive::*;
+        match self {
+            Data(_)=>"Data",
+            String(_)=>"String",
+            Number(_)=>"Number",
+            Float(_)=>"Float",
+            Char(_)=>"Char",
+            Bool(_)=>"Bool",
+            Keyword(_)=>"Keyword",
+            NativeFn(_)=>"NativeFn",
+            Fn(_)=>"Function",
+            None=>"None",
+        }
+    }
+
+    /// Check if the primitive matches the type annotation. Objects match if their
+    /// [`Object::type_name`](object::Object::type_name) is the annotation's name. Callable objects
+    /// can name themselves `fn` to match `:fn`.
+    pub fn matches_type(&self, ty: Type, interner: &Interner)->bool {
+        use Primitive::*;
+        match (ty, self) {
+            (Type::Any, _)|
+                (Type::Int, Number(_))|
+                (Type::Float, Float(_))|
+                (Type::String, String(_))|
+                (Type::Char, Char(_))|
+                (Type::Bool, Bool(_))|
+                (Type::Keyword, Keyword(_))|
+                (Type::None, None)|
+                (Type::Function, Fn(_)|NativeFn(_))=>true,
+            (Type::Function, Data(d))=>d.type_name() == Some("fn"),
+            (Type::Object(name), Data(d))=>d.type_name() == Some(interner.get(name)),
+            _=>false,
+        }
+    }
+}
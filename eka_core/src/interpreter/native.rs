@@ -0,0 +1,369 @@
+use anyhow::{
+    Result,
+    anyhow,
+    bail,
+};
+use std::{
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    rc::Rc,
+};
+use crate::ast::{
+    Interner,
+    Ident,
+};
+use super::{
+    object::{
+        Object,
+        ObjectBundle,
+        CallReturn,
+    },
+    Primitive,
+    GcTrait,
+};
+
+
+/// The boxed closure stored in a [`NativeFnObject`]. The first argument is the function's captures.
+pub type BoxedNativeFn<Gc, O> = Box<dyn FnMut(&[Primitive<Gc, O>], Vec<Primitive<Gc, O>>, &Interner, &mut Gc)->Result<CallReturn<Gc, O>>>;
+
+
+/// Convert a [`Primitive`] into a Rust type. Used for the arguments of typed native functions.
+pub trait FromPrimitive<Gc: GcTrait<O>, O: ObjectBundle<Gc>>: Sized {
+    /// The name of the expected type used in error messages.
+    const TYPE_NAME: &'static str;
+
+    /// Returns `None` if the primitive is not the right type.
+    fn from_primitive(p: Primitive<Gc, O>)->Option<Self>;
+}
+
+/// Convert a Rust type into a [`Primitive`]. Used for the return values of typed native functions.
+pub trait IntoPrimitive<Gc: GcTrait<O>, O: ObjectBundle<Gc>> {
+    fn into_primitive(self, gc: &mut Gc)->Result<Primitive<Gc, O>>;
+}
+
+/// Convert a Rust closure into a [`NativeFnObject`]. This is implemented for every `FnMut` whose
+/// arguments implement [`FromPrimitive`] and whose return type implements [`IntoPrimitive`].
+///
+/// The `Args` parameter is only there so the implementations for different arities don't overlap.
+pub trait IntoNativeFn<Gc: GcTrait<O>, O: ObjectBundle<Gc>, Args> {
+    fn into_native_fn(self, name: &str)->NativeFnObject<Gc, O>;
+}
+
+/// Convert a tuple of Rust values into the arguments for a call from Rust into a script.
+///
+/// Converting an arg can allocate, which can run a collection, so `keep` is called with each arg
+/// as soon as it is converted. The caller has to keep them alive until the call returns.
+pub trait IntoArgs<Gc: GcTrait<O>, O: ObjectBundle<Gc>> {
+    fn into_args(self, gc: &mut Gc, keep: &mut dyn FnMut(&Primitive<Gc, O>))->Result<Vec<Primitive<Gc, O>>>;
+}
+
+
+/// Allocates the inner value when returned from a typed native function.
+/// ```rust,ignore
+/// interpreter.def_native_fn("instantNow", ||Alloc(InstantObject(Instant::now())));
+/// ```
+pub struct Alloc<T>(pub T);
+
+
+/// A native function object that wraps a Rust closure, so unlike [`Primitive::NativeFn`] it can
+/// capture state.
+///
+/// Closures can't be traced, so a `DataRef` moved into the closure is not kept alive by the GC. Pass
+/// script values the closure needs as captures with [`NativeFnObject::with_captures`] instead.
+/// Captures are traced with the function object.
+pub struct NativeFnObject<Gc: GcTrait<O>, O: ObjectBundle<Gc>> {
+    name: Rc<str>,
+    captures: Vec<Primitive<Gc, O>>,
+    func: BoxedNativeFn<Gc, O>,
+}
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> Debug for NativeFnObject<Gc, O> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "<NativeFn {}>", self.name)
+    }
+}
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> NativeFnObject<Gc, O> {
+    /// Wrap an untyped closure. Argument checking is left to the closure.
+    pub fn new<F>(name: &str, mut func: F)->Self
+    where
+        F: FnMut(Vec<Primitive<Gc, O>>, &Interner, &mut Gc)->Result<CallReturn<Gc, O>> + 'static,
+    {
+        NativeFnObject {
+            name: name.into(),
+            captures: Vec::new(),
+            func: Box::new(move|_, args, interner, gc|func(args, interner, gc)),
+        }
+    }
+
+    /// Wrap an untyped closure that uses script values. The captures are passed to every call and
+    /// traced by the GC, so any `DataRef`s in them stay alive as long as the function does.
+    pub fn with_captures<F>(name: &str, captures: Vec<Primitive<Gc, O>>, func: F)->Self
+    where
+        F: FnMut(&[Primitive<Gc, O>], Vec<Primitive<Gc, O>>, &Interner, &mut Gc)->Result<CallReturn<Gc, O>> + 'static,
+    {
+        NativeFnObject {
+            name: name.into(),
+            captures,
+            func: Box::new(func),
+        }
+    }
+
+    /// Wrap a typed closure. Arity and argument types are checked before the closure is called.
+    #[inline]
+    pub fn typed<Args, F: IntoNativeFn<Gc, O, Args>>(name: &str, func: F)->Self {
+        func.into_native_fn(name)
+    }
+
+    pub fn name(&self)->&str {
+        &self.name
+    }
+}
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> Object<Gc> for NativeFnObject<Gc, O> {
+    type ObjectBundle = O;
+
+    fn get(&self, _: Ident, _: &Interner)->Result<Primitive<Gc, O>> {
+        bail!("There are no fields on NativeFn `{}`", self.name);
+    }
+    fn set(&mut self, _: Ident, _: Primitive<Gc, O>, _: &Interner)->Result<()> {
+        bail!("There are no fields on NativeFn `{}`", self.name);
+    }
+
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        Some("fn")
+    }
+
+    fn call(&mut self, args: Vec<Primitive<Gc, O>>, interner: &Interner, gc: &mut Gc)->Result<CallReturn<Gc, O>> {
+        (self.func)(&self.captures, args, interner, gc)
+    }
+    fn method(&mut self, _: Ident, _: Vec<Primitive<Gc, O>>, _: &Interner, _: &mut Gc)->Result<CallReturn<Gc, O>> {
+        bail!("NativeFn `{}` has no methods", self.name);
+    }
+
+    fn trace(&self, tracer: &mut Gc) {
+        for val in self.captures.iter() {
+            match val {
+                Primitive::Data(d)=>tracer.trace(d.clone()),
+                _=>{},
+            }
+        }
+    }
+}
+
+
+/// Check the argument count for the native function `name`.
+pub fn check_arity(name: &str, expected: usize, got: usize)->Result<()> {
+    if expected != got {
+        bail!("`{name}` expected {expected} args, but got {got}");
+    }
+
+    return Ok(());
+}
+
+/// Convert argument number `idx` (zero-based) of the native function `name`.
+pub fn convert_arg<Gc: GcTrait<O>, O: ObjectBundle<Gc>, T: FromPrimitive<Gc, O>>(name: &str, idx: usize, arg: Primitive<Gc, O>)->Result<T> {
+    let got = arg.type_name();
+    T::from_primitive(arg)
+        .ok_or_else(||anyhow!("`{name}` argument {} expected {}, but got {got}", idx + 1, T::TYPE_NAME))
+}
+
+
+macro_rules! impl_from_primitive {
+    ($($ty:ty, $type_name:literal, $($pat:pat => $val:expr),+;)*)=>{
+        $(
+            impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> FromPrimitive<Gc, O> for $ty {
+                const TYPE_NAME: &'static str = $type_name;
+
+                #[inline]
+                fn from_primitive(p: Primitive<Gc, O>)->Option<Self> {
+                    match p {
+                        $($pat=>Some($val),)+
+                        _=>None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_primitive! {
+    i64, "Number", Primitive::Number(n)=>n;
+    f64, "Float", Primitive::Float(f)=>f;
+    bool, "Bool", Primitive::Bool(b)=>b;
+    char, "Char", Primitive::Char(c)=>c;
+    Rc<String>, "String", Primitive::String(s)=>s;
+    String, "String", Primitive::String(s)=>s.as_ref().clone();
+    Ident, "Keyword", Primitive::Keyword(k)=>k;
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> FromPrimitive<Gc, O> for Primitive<Gc, O> {
+    const TYPE_NAME: &'static str = "Any";
+
+    #[inline]
+    fn from_primitive(p: Primitive<Gc, O>)->Option<Self> {
+        Some(p)
+    }
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>, T: FromPrimitive<Gc, O>> FromPrimitive<Gc, O> for Option<T> {
+    const TYPE_NAME: &'static str = T::TYPE_NAME;
+
+    #[inline]
+    fn from_primitive(p: Primitive<Gc, O>)->Option<Self> {
+        match p {
+            Primitive::None=>Some(None),
+            p=>T::from_primitive(p).map(Some),
+        }
+    }
+}
+
+
+macro_rules! impl_into_primitive {
+    ($($ty:ty, $name:ident => $val:expr;)*)=>{
+        $(
+            impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> IntoPrimitive<Gc, O> for $ty {
+                #[inline]
+                fn into_primitive(self, _: &mut Gc)->Result<Primitive<Gc, O>> {
+                    let $name = self;
+                    Ok($val)
+                }
+            }
+        )*
+    };
+}
+
+impl_into_primitive! {
+    i64, n=>Primitive::Number(n);
+    f64, f=>Primitive::Float(f);
+    bool, b=>Primitive::Bool(b);
+    char, c=>Primitive::Char(c);
+    Rc<String>, s=>Primitive::String(s);
+    String, s=>Primitive::String(Rc::new(s));
+    &str, s=>Primitive::String(Rc::new(s.to_string()));
+    Ident, k=>Primitive::Keyword(k);
+    (), _u=>Primitive::None;
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> IntoPrimitive<Gc, O> for Primitive<Gc, O> {
+    #[inline]
+    fn into_primitive(self, _: &mut Gc)->Result<Primitive<Gc, O>> {
+        Ok(self)
+    }
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>, T: IntoPrimitive<Gc, O>> IntoPrimitive<Gc, O> for Option<T> {
+    #[inline]
+    fn into_primitive(self, gc: &mut Gc)->Result<Primitive<Gc, O>> {
+        match self {
+            Some(t)=>t.into_primitive(gc),
+            None=>Ok(Primitive::None),
+        }
+    }
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>, T: IntoPrimitive<Gc, O>> IntoPrimitive<Gc, O> for Result<T> {
+    #[inline]
+    fn into_primitive(self, gc: &mut Gc)->Result<Primitive<Gc, O>> {
+        self?.into_primitive(gc)
+    }
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>, T: Into<O>> IntoPrimitive<Gc, O> for Alloc<T> {
+    #[inline]
+    fn into_primitive(self, gc: &mut Gc)->Result<Primitive<Gc, O>> {
+        Ok(Primitive::Data(gc.alloc(self.0)))
+    }
+}
+
+
+macro_rules! count {
+    ()=>{0usize};
+    ($head:ident $($tail:ident)*)=>{1usize + count!($($tail)*)};
+}
+
+macro_rules! impl_into_native_fn {
+    ($(($($arg:ident),*))*)=>{
+        $(
+            impl<Gc, O, Func, R, $($arg,)*> IntoNativeFn<Gc, O, ($($arg,)*)> for Func
+            where
+                Gc: GcTrait<O> + 'static,
+                O: ObjectBundle<Gc> + 'static,
+                Func: FnMut($($arg),*)->R + 'static,
+                R: IntoPrimitive<Gc, O>,
+                $($arg: FromPrimitive<Gc, O>,)*
+            {
+                #[allow(non_snake_case, unused_mut, unused_variables)]
+                fn into_native_fn(mut self, name: &str)->NativeFnObject<Gc, O> {
+                    let fn_name: Rc<str> = name.into();
+                    NativeFnObject::new(name, move|args, _, gc|{
+                        check_arity(&fn_name, count!($($arg)*), args.len())?;
+
+                        let mut args_iter = args.into_iter().enumerate();
+                        $(
+                            let (idx, arg) = args_iter.next().unwrap();
+                            let $arg: $arg = convert_arg(&fn_name, idx, arg)?;
+                        )*
+
+                        let ret = (self)($($arg),*);
+                        return Ok(CallReturn::Data(ret.into_primitive(gc)?));
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_into_native_fn! {
+    ()
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+    (A, B, C, D, E)
+    (A, B, C, D, E, F)
+    (A, B, C, D, E, F, G)
+    (A, B, C, D, E, F, G, H)
+}
+
+impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>> IntoArgs<Gc, O> for Vec<Primitive<Gc, O>> {
+    #[inline]
+    fn into_args(self, _: &mut Gc, keep: &mut dyn FnMut(&Primitive<Gc, O>))->Result<Vec<Primitive<Gc, O>>> {
+        self.iter().for_each(keep);
+        return Ok(self);
+    }
+}
+
+macro_rules! impl_into_args {
+    ($(($($arg:ident),*))*)=>{
+        $(
+            impl<Gc: GcTrait<O>, O: ObjectBundle<Gc>, $($arg: IntoPrimitive<Gc, O>,)*> IntoArgs<Gc, O> for ($($arg,)*) {
+                #[allow(non_snake_case, unused_variables, unused_mut)]
+                fn into_args(self, gc: &mut Gc, keep: &mut dyn FnMut(&Primitive<Gc, O>))->Result<Vec<Primitive<Gc, O>>> {
+                    let ($($arg,)*) = self;
+                    let mut args = Vec::new();
+                    $(
+                        let $arg = $arg.into_primitive(gc)?;
+                        keep(&$arg);
+                        args.push($arg);
+                    )*
+
+                    return Ok(args);
+                }
+            }
+        )*
+    };
+}
+
+impl_into_args! {
+    ()
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+    (A, B, C, D, E)
+    (A, B, C, D, E, F)
+    (A, B, C, D, E, F, G)
+    (A, B, C, D, E, F, G, H)
+}
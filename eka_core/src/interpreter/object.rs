@@ -11,6 +11,7 @@ use crate::ast::{
     FnId,
 };
 use super::{
+    native::NativeFnObject,
     Primitive,
     GcTrait,
 };
@@ -18,7 +19,7 @@ use super::{
 
 /// A macro to bundle multiple types implementing the [`Object`] trait into one enum without having
 /// to write a lot of code. It implements `From<ObjectType>` for each type. Example:
-/// ```rust
+/// ```rust,ignore
 /// bundle_object_types! {
 ///     bundle MyObjects {
 ///         // The variants can be called whatever you want, and generics can use `Self` to refer
@@ -47,7 +48,15 @@ macro_rules! bundle_object_types {
                 }
             }
         )+
-        impl ObjectBundle<$gc_ty> for $name {}
+        impl ObjectBundle<$gc_ty> for $name {
+            fn variant_name(&self)->&'static str {
+                match self {
+                    $(
+                        $name::$obj_name(_)=>stringify!($obj_name),
+                    )+
+                }
+            }
+        }
         impl Object<$gc_ty> for $name {
             type ObjectBundle = Self;
 
@@ -102,6 +111,15 @@ macro_rules! bundle_object_types {
                 }
             }
 
+            #[inline]
+            fn type_name(&self)->Option<&str> {
+                match self {
+                    $(
+                        $name::$obj_name(variant)=>variant.type_name(),
+                    )+
+                }
+            }
+
             #[inline]
             fn trace(&self, tracer: &mut $gc_ty) {
                 match self {
@@ -128,7 +146,15 @@ macro_rules! bundle_object_types {
                 }
             }
         )+
-        impl<$gc_name: $crate::interpreter::GcTrait<Self>> ObjectBundle<$gc_name> for $name<$gc_name> {}
+        impl<$gc_name: $crate::interpreter::GcTrait<Self>> ObjectBundle<$gc_name> for $name<$gc_name> {
+            fn variant_name(&self)->&'static str {
+                match self {
+                    $(
+                        $name::$obj_name(_)=>stringify!($obj_name),
+                    )+
+                }
+            }
+        }
         impl<$gc_name: $crate::interpreter::GcTrait<Self>> Object<$gc_name> for $name<$gc_name> {
             type ObjectBundle = Self;
 
@@ -183,6 +209,15 @@ macro_rules! bundle_object_types {
                 }
             }
 
+            #[inline]
+            fn type_name(&self)->Option<&str> {
+                match self {
+                    $(
+                        $name::$obj_name(variant)=>variant.type_name(),
+                    )+
+                }
+            }
+
             #[inline]
             fn trace(&self, tracer: &mut $gc_name) {
                 match self {
@@ -200,6 +235,7 @@ macro_rules! bundle_object_types {
 bundle_object_types! {
     bundle CoreObjectBundle<Gc> {
         Base: BaseObject<Gc, CoreObjectBundle<Gc>>,
+        NativeFn: NativeFnObject<Gc, CoreObjectBundle<Gc>>,
     }
 }
 
@@ -236,6 +272,13 @@ pub trait Object<Gc: GcTrait<Self::ObjectBundle>>: Sized {
     #[inline]
     fn finalize(&mut self) {}
 
+    /// The name used for this object in type annotations like `[pos :Vec2]`. Objects without a
+    /// name only match `:any`.
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        None
+    }
+
     fn get(&self, name: Ident, interner: &Interner)->Result<Primitive<Gc, Self::ObjectBundle>>;
     fn set(&mut self, name: Ident, data: Primitive<Gc, Self::ObjectBundle>, interner: &Interner)->Result<()>;
 
@@ -255,12 +298,17 @@ pub trait Object<Gc: GcTrait<Self::ObjectBundle>>: Sized {
 }
 
 pub trait ObjectBundle<Gc: GcTrait<Self::ObjectBundle>>: Sized + Object<Gc, ObjectBundle = Self> + Debug {
+    /// The name of the bundle variant holding the object.
+    fn variant_name(&self)->&'static str;
 }
 
 
 pub enum CallReturn<Gc: GcTrait<O>, O: ObjectBundle<Gc>> {
     CallFn(FnId, Vec<Primitive<Gc, O>>),
     Data(Primitive<Gc, O>),
+    /// Return the doc of the function, or `None` if it doesn't have one. Natives can't see the
+    /// function store, so the interpreter looks it up.
+    Doc(FnId),
 }
 
 
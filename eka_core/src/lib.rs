@@ -1,3 +1,6 @@
 pub mod misc;
+pub mod source;
+pub mod diagnostics;
 pub mod ast;
 pub mod interpreter;
+pub mod checker;
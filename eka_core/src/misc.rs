@@ -1,5 +1,8 @@
 use indexmap::IndexSet;
-use rustc_hash::FxBuildHasher;
+use rustc_hash::{
+    FxBuildHasher,
+    FxHashMap,
+};
 use misc_utils::{
     KeyedVec,
     Key,
@@ -11,6 +14,7 @@ use std::{
     },
     hash::Hash,
 };
+use crate::source::Span;
 
 
 pub type FxIndexSet<K> = IndexSet<K, FxBuildHasher>;
@@ -20,12 +24,15 @@ pub type FxIndexSet<K> = IndexSet<K, FxBuildHasher>;
 pub struct IndexedItemStore<K: Hash + PartialEq + Eq + Key, V> {
     all: KeyedVec<K, V>,
     roots: FxIndexSet<K>,
+    /// Where each item came from. Items created by the host have no span.
+    spans: FxHashMap<K, Span>,
 }
 impl<K: Hash + PartialEq + Eq + Key, V> Default for IndexedItemStore<K, V> {
     fn default()->Self {
         IndexedItemStore {
             all: KeyedVec::new(),
             roots: FxIndexSet::default(),
+            spans: FxHashMap::default(),
         }
     }
 }
@@ -34,6 +41,16 @@ impl<K: Hash + PartialEq + Eq + Key, V> IndexedItemStore<K, V> {
         self.all.insert(val)
     }
 
+    /// Record where the item came from.
+    pub fn set_span(&mut self, id: K, span: Span) {
+        self.spans.insert(id, span);
+    }
+
+    #[inline]
+    pub fn span(&self, id: K)->Option<Span> {
+        self.spans.get(&id).copied()
+    }
+
     pub fn add_root(&mut self, id: K) {
         self.roots.insert(id);
     }
@@ -42,6 +59,13 @@ impl<K: Hash + PartialEq + Eq + Key, V> IndexedItemStore<K, V> {
         self.roots.shift_remove(&id);
     }
 
+    /// Remove the roots from index `at` on, and return them in order.
+    pub fn split_off_roots(&mut self, at: usize)->Vec<K> {
+        self.roots.split_off(at)
+            .into_iter()
+            .collect()
+    }
+
     /// Iterates through the roots in order.
     pub fn iter_roots(&self)->impl Iterator<Item = &K> {
         self.roots.iter()
@@ -0,0 +1,155 @@
+use anyhow::Error;
+use misc_utils::{
+    Key,
+    define_keys,
+};
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    ops::Index,
+};
+
+
+define_keys!(FileId);
+
+
+/// A location in a source file. `start..end` is the byte range, and `line` and `col` are the
+/// 1-based position of `start`. Columns count chars, not bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub col: u32,
+}
+impl Span {
+    /// Display the span as `name:line:col`. Spans only store the file id, so the name is looked up
+    /// in `sources`.
+    #[inline]
+    pub fn display<'a>(&self, sources: &'a SourceMap)->SpanDisplay<'a> {
+        SpanDisplay {
+            span: *self,
+            sources,
+        }
+    }
+}
+
+/// A [`Span`] with the name of its file. Created with [`Span::display`].
+pub struct SpanDisplay<'a> {
+    span: Span,
+    sources: &'a SourceMap,
+}
+impl Display for SpanDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let name = self.sources.get(self.span.file)
+            .map(|file|file.name.as_str())
+            .unwrap_or("<unknown>");
+        write!(f, "{name}:{}:{}", self.span.line, self.span.col)
+    }
+}
+
+/// Attached to errors as context with the location of the expression that caused them. Use
+/// [`error_span`] to find it again.
+#[derive(Debug, Copy, Clone)]
+pub struct ErrorSpan(pub Span);
+impl Display for ErrorSpan {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "At line {}, column {}", self.0.line, self.0.col)
+    }
+}
+
+/// Attach the span to the error unless it already has one. Errors bubble up through every
+/// enclosing expression, so the first span attached is the most precise one.
+pub fn with_span(err: Error, span: Option<Span>)->Error {
+    match span {
+        Some(span) if error_span(&err).is_none()=>err.context(ErrorSpan(span)),
+        _=>err,
+    }
+}
+
+/// Get the span attached to the error with [`with_span`].
+#[inline]
+pub fn error_span(err: &Error)->Option<Span> {
+    err.downcast_ref::<ErrorSpan>().map(|s|s.0)
+}
+
+
+#[derive(Debug)]
+pub struct SourceFile {
+    /// The path of the file, or a placeholder like `<string>` for sources that aren't from a file
+    pub name: String,
+    pub source: String,
+    /// The byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+impl SourceFile {
+    pub fn new(name: String, source: String)->Self {
+        let line_starts = [0].into_iter()
+            .chain(source.match_indices('\n').map(|(i, _)|i + 1))
+            .collect();
+
+        SourceFile {
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    /// The 1-based line and column of the byte offset.
+    pub fn line_col(&self, offset: usize)->(u32, u32) {
+        let line = self.line_starts.partition_point(|start|*start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let col = self.source.get(line_start..offset)
+            .map(|s|s.chars().count())
+            .unwrap_or(offset - line_start);
+
+        return (line as u32 + 1, col as u32 + 1);
+    }
+
+    /// The text of the 1-based line without the line ending.
+    pub fn line(&self, line: u32)->Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)? as usize)?;
+        let end = self.line_starts.get(line as usize)
+            .copied()
+            .unwrap_or(self.source.len());
+
+        return Some(self.source[start..end].trim_end_matches(['\n', '\r']));
+    }
+
+    #[inline]
+    pub fn line_count(&self)->usize {
+        self.line_starts.len()
+    }
+}
+
+/// Every source that has been parsed, so spans can be turned back into file names and lines.
+#[derive(Debug, Default)]
+pub struct SourceMap(Vec<SourceFile>);
+impl SourceMap {
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>)->FileId {
+        self.0.push(SourceFile::new(name.into(), source.into()));
+        return FileId::from_id(self.0.len() - 1);
+    }
+
+    #[inline]
+    pub fn get(&self, id: FileId)->Option<&SourceFile> {
+        self.0.get(id.0)
+    }
+
+    /// Create a span for the byte range in the file.
+    pub fn span(&self, file: FileId, start: usize, end: usize)->Span {
+        let (line, col) = self[file].line_col(start);
+        Span {file, start, end, line, col}
+    }
+}
+impl Index<FileId> for SourceMap {
+    type Output = SourceFile;
+
+    fn index(&self, id: FileId)->&SourceFile {
+        &self.0[id.0]
+    }
+}
@@ -0,0 +1,183 @@
+//! Snapshot tests for rendering diagnostics. The expected output is inline, so a change to the
+//! rendering shows up as a diff of these strings.
+
+
+use anyhow::{
+    Context,
+    anyhow,
+};
+use eka_core::{
+    diagnostics::*,
+    source::*,
+};
+
+
+fn sources(source: &str)->(SourceMap, FileId) {
+    let mut sources = SourceMap::default();
+    let file = sources.add("test.eka", source);
+    return (sources, file);
+}
+
+/// The span of the first occurrence of `text` in the file.
+fn find(sources: &SourceMap, file: FileId, text: &str)->Span {
+    let start = sources[file].source.find(text).unwrap();
+    return sources.span(file, start, start + text.len());
+}
+
+
+#[test]
+fn error_with_label() {
+    let (sources, file) = sources("(def a 1)\n(+ a undefinedVar)\n");
+    let diag = Diagnostic::error("Var `undefinedVar` is undefined")
+        .with_label(find(&sources, file, "undefinedVar"), "not defined in any scope");
+
+    assert_eq!(diag.render(&sources, false), "\
+error: Var `undefinedVar` is undefined
+ --> test.eka:2:6
+  |
+2 | (+ a undefinedVar)
+  |      ^^^^^^^^^^^^ not defined in any scope
+");
+}
+
+#[test]
+fn labels_notes_and_help() {
+    let (sources, file) = sources("(defn add [a :int b :int] :int\n    (+ a b))\n\n(add 1 \"two\")\n");
+    let diag = Diagnostic::error("Param `b` of `add` expected int, but got string")
+        .with_label(find(&sources, file, "\"two\""), "this is a string")
+        .with_secondary_label(find(&sources, file, "b :int"), "declared here")
+        .with_note("In call to `add`")
+        .with_help("convert the string to a number first");
+
+    assert_eq!(diag.render(&sources, false), "\
+error: Param `b` of `add` expected int, but got string
+ --> test.eka:4:8
+  |
+1 | (defn add [a :int b :int] :int
+  |                   ------ declared here
+  |
+4 | (add 1 \"two\")
+  |        ^^^^^ this is a string
+  |
+  = note: In call to `add`
+  = help: convert the string to a number first
+");
+}
+
+#[test]
+fn several_labels_on_one_line() {
+    let (sources, file) = sources("(set x (+ x y))");
+    let diag = Diagnostic::warning("Unused result")
+        .with_label(find(&sources, file, "(+ x y)"), "computed here")
+        .with_secondary_label(find(&sources, file, "set"), "");
+
+    assert_eq!(diag.render(&sources, false), "\
+warning: Unused result
+ --> test.eka:1:8
+  |
+1 | (set x (+ x y))
+  |  ---
+  |        ^^^^^^^ computed here
+");
+}
+
+#[test]
+fn wide_gutter_and_tabs() {
+    let source = format!("{}\t(oops)\n", "\n".repeat(99));
+    let (sources, file) = sources(&source);
+    let diag = Diagnostic::note("Tabs are expanded")
+        .with_label(find(&sources, file, "oops"), "");
+
+    assert_eq!(diag.render(&sources, false), "\
+note: Tabs are expanded
+   --> test.eka:100:3
+    |
+100 |     (oops)
+    |      ^^^^
+");
+}
+
+#[test]
+fn multi_line_span_underlines_the_first_line() {
+    let (sources, file) = sources("(begin\n    1\n    2)");
+    let diag = Diagnostic::error("Bad begin")
+        .with_label(find(&sources, file, "(begin\n    1\n    2)"), "");
+
+    assert_eq!(diag.render(&sources, false), "\
+error: Bad begin
+ --> test.eka:1:1
+  |
+1 | (begin
+  | ^^^^^^
+");
+}
+
+#[test]
+fn no_labels() {
+    let (sources, _) = sources("");
+    let diag = Diagnostic::error("Something went wrong")
+        .with_help("try again");
+
+    assert_eq!(diag.render(&sources, false), "\
+error: Something went wrong
+  = help: try again
+");
+}
+
+#[test]
+fn colored() {
+    let (sources, file) = sources("(x)");
+    let diag = Diagnostic::error("Var `x` is undefined")
+        .with_label(find(&sources, file, "x"), "here");
+
+    assert_eq!(diag.render(&sources, true), "\
+\x1b[1;31merror\x1b[0m\x1b[1m: Var `x` is undefined\x1b[0m
+ \x1b[1;34m-->\x1b[0m test.eka:1:2
+  \x1b[1;34m|\x1b[0m
+\x1b[1;34m1 |\x1b[0m (x)
+  \x1b[1;34m|\x1b[0m  \x1b[1;31m^ here\x1b[0m
+");
+}
+
+#[test]
+fn runtime_error_with_span_and_context() {
+    let (sources, file) = sources("(console/print (format x))");
+    let err = anyhow!("Var `x` is undefined");
+    let err = with_span(err, Some(find(&sources, file, "x")));
+    // only the innermost span is kept
+    let err = with_span(err, Some(find(&sources, file, "(format x)")));
+    let err = Err::<(), _>(err).context("In file `test.eka`").unwrap_err();
+
+    assert_eq!(render_error(&err, &sources, false), "\
+error: Var `x` is undefined
+ --> test.eka:1:24
+  |
+1 | (console/print (format x))
+  |                        ^
+  |
+  = note: In file `test.eka`
+");
+}
+
+#[test]
+fn several_diagnostics_in_one_error() {
+    let (sources, file) = sources("(def a :int \"s\")\n");
+    let err = anyhow!(Diagnostics(vec![
+        Diagnostic::error("First").with_label(find(&sources, file, "a"), ""),
+        Diagnostic::error("Second").with_label(find(&sources, file, "\"s\""), ""),
+    ]));
+
+    assert_eq!(render_error(&err, &sources, false), "\
+error: First
+ --> test.eka:1:6
+  |
+1 | (def a :int \"s\")
+  |      ^
+
+error: Second
+ --> test.eka:1:13
+  |
+1 | (def a :int \"s\")
+  |             ^^^
+");
+}
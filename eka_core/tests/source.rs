@@ -0,0 +1,43 @@
+//! Tests for source files, spans, and their line and column numbers.
+
+
+use eka_core::source::*;
+
+
+#[test]
+fn line_and_col() {
+    let mut sources = SourceMap::default();
+    let file = sources.add("test.eka", "(def a 1)\n\t(def é \"ü\") b\n");
+    let source = &sources[file];
+
+    assert_eq!(source.line_col(0), (1, 1));
+    assert_eq!(source.line_col(5), (1, 6));
+    assert_eq!(source.line_col(10), (2, 1));
+
+    // columns count chars, so the multi-byte `é` and `ü` are one column each
+    let b = source.source.find(" b").unwrap() + 1;
+    assert_eq!(source.line_col(b), (2, 14));
+
+    assert_eq!(source.line(1), Some("(def a 1)"));
+    assert_eq!(source.line(2), Some("\t(def é \"ü\") b"));
+    assert_eq!(source.line(4), None);
+    assert_eq!(source.line(0), None);
+}
+
+#[test]
+fn spans() {
+    let mut sources = SourceMap::default();
+    let first = sources.add("first.eka", "(def a 1)\r\n(def b 2)\r\n");
+    let second = sources.add("<string>", "b");
+
+    let span = sources.span(first, 16, 17);
+    assert_eq!(span, Span {file: first, start: 16, end: 17, line: 2, col: 6});
+    assert_eq!(sources[first].line(span.line), Some("(def b 2)"));
+    assert_eq!(span.display(&sources).to_string(), "first.eka:2:6");
+
+    let span = sources.span(second, 0, 1);
+    assert_eq!(span.display(&sources).to_string(), "<string>:1:1");
+
+    // a span from another source map has no name
+    assert_eq!(span.display(&SourceMap::default()).to_string(), "<unknown>:1:1");
+}
@@ -0,0 +1,35 @@
+#!/bin/sh
+# Run the GC benchmark from the working tree against the heap of an older revision, then against the
+# working tree. The old revision needs the `Gc` API the benchmark uses, so use revisions from
+# GC statistics onwards.
+#
+#     eka_interp_treewalk/benches/compare.sh <revision> [count]
+#
+# For example, to compare the heap before and after slab allocation at 20k objects, pass the parent
+# of the commit that added slab allocation:
+#
+#     eka_interp_treewalk/benches/compare.sh <slab-commit>^ 20000
+set -e
+
+if [ -z "$1" ]; then
+    echo "Usage: $0 <revision> [count]" >&2
+    exit 1
+fi
+rev=$1
+count=${2:-200000}
+
+root=$(git rev-parse --show-toplevel)
+old=$(mktemp -d)
+trap 'git -C "$root" worktree remove --force "$old"' EXIT
+
+git -C "$root" worktree add --detach "$old" "$rev" >/dev/null
+mkdir -p "$old/eka_interp_treewalk/benches"
+cp "$root/eka_interp_treewalk/benches/gc.rs" "$old/eka_interp_treewalk/benches/gc.rs"
+if ! grep -q '^\[\[bench\]\]' "$old/eka_interp_treewalk/Cargo.toml"; then
+    printf '\n\n[[bench]]\nname = "gc"\nharness = false\n' >> "$old/eka_interp_treewalk/Cargo.toml"
+fi
+
+echo "== $rev"
+(cd "$old" && cargo bench -q -p eka_interp_treewalk --bench gc -- "$count")
+echo "== working tree"
+(cd "$root" && cargo bench -q -p eka_interp_treewalk --bench gc -- "$count")
@@ -0,0 +1,183 @@
+//! Allocation and tracing throughput of the GC. Run with `cargo bench -p eka_interp_treewalk`, or
+//! `cargo bench -p eka_interp_treewalk -- 20000` to change how many objects are allocated.
+//!
+//! This uses plain `Instant` timing instead of a benchmark framework so it has no dependencies.
+//! Each benchmark is run a few times and the fastest run is reported.
+//!
+//! `benches/compare.sh <revision> [count]` runs this file against an older heap and the working
+//! tree, so changes to the heap can be compared on the same benchmarks.
+
+
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    time::{
+        Duration,
+        Instant,
+    },
+    hint::black_box,
+    env::args,
+};
+use eka_core::{
+    interpreter::{
+        object::*,
+        GcTrait,
+        Primitive,
+    },
+    ast::{
+        Ident,
+        Interner,
+    },
+};
+use eka_interp_treewalk::data::{
+    Gc,
+    DataRef,
+};
+
+
+type BenchGc = Gc<BenchBundle>;
+
+
+eka_core::bundle_object_types! {
+    bundle BenchBundle where GC = BenchGc {
+        Leaf: Leaf,
+        Node: Node,
+    }
+}
+
+
+#[derive(Debug)]
+pub struct Leaf(#[allow(dead_code)] u64);
+impl Object<BenchGc> for Leaf {
+    type ObjectBundle = BenchBundle;
+
+    fn get(&self, _: Ident, _: &Interner)->Result<Primitive<BenchGc, BenchBundle>> {
+        bail!("Leaf has no fields");
+    }
+    fn set(&mut self, _: Ident, _: Primitive<BenchGc, BenchBundle>, _: &Interner)->Result<()> {
+        bail!("Leaf has no fields");
+    }
+    fn call(&mut self, _: Vec<Primitive<BenchGc, BenchBundle>>, _: &Interner, _: &mut BenchGc)->Result<CallReturn<BenchGc, BenchBundle>> {
+        bail!("Cannot call Leaf");
+    }
+    fn method(&mut self, _: Ident, _: Vec<Primitive<BenchGc, BenchBundle>>, _: &Interner, _: &mut BenchGc)->Result<CallReturn<BenchGc, BenchBundle>> {
+        bail!("Leaf has no methods");
+    }
+    fn trace(&self, _: &mut BenchGc) {}
+}
+
+#[derive(Debug)]
+pub struct Node(Vec<DataRef<BenchBundle>>);
+impl Object<BenchGc> for Node {
+    type ObjectBundle = BenchBundle;
+
+    fn get(&self, _: Ident, _: &Interner)->Result<Primitive<BenchGc, BenchBundle>> {
+        bail!("Node has no fields");
+    }
+    fn set(&mut self, _: Ident, _: Primitive<BenchGc, BenchBundle>, _: &Interner)->Result<()> {
+        bail!("Node has no fields");
+    }
+    fn call(&mut self, _: Vec<Primitive<BenchGc, BenchBundle>>, _: &Interner, _: &mut BenchGc)->Result<CallReturn<BenchGc, BenchBundle>> {
+        bail!("Cannot call Node");
+    }
+    fn method(&mut self, _: Ident, _: Vec<Primitive<BenchGc, BenchBundle>>, _: &Interner, _: &mut BenchGc)->Result<CallReturn<BenchGc, BenchBundle>> {
+        bail!("Node has no methods");
+    }
+    fn trace(&self, tracer: &mut BenchGc) {
+        for child in self.0.iter() {
+            tracer.trace(child.clone());
+        }
+    }
+}
+
+
+/// Run `f` a few times and print the fastest run as time per item.
+fn bench<F: FnMut()->usize>(name: &str, mut f: F) {
+    let mut best = Duration::MAX;
+    let mut items = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        items = f();
+        best = best.min(start.elapsed());
+    }
+
+    let per_item = best.as_nanos() as f64 / items as f64;
+    println!("{name:<32} {best:>12.3?} {per_item:>10.1} ns/item ({items} items)");
+}
+
+/// Allocate short-lived objects while the GC collects in the background.
+fn alloc_garbage(count: usize) {
+    bench("alloc garbage", ||{
+        let mut gc = BenchGc::new();
+        for i in 0..count {
+            drop(black_box(gc.alloc(Leaf(i as u64).into())));
+        }
+        count
+    });
+}
+
+/// Allocate objects that stay alive, so nothing can be recycled. Each object references the one
+/// before it, and a rooted holder references the newest, so every one of them is promoted out of
+/// the nursery.
+fn alloc_live(count: usize) {
+    bench("alloc live", ||{
+        let mut gc = BenchGc::new();
+        let first = gc.alloc(Leaf(0).into());
+        let mut holder = gc.alloc(Node(vec![first]).into());
+        black_box(gc.root(holder.clone()));
+        for _ in 1..count {
+            let BenchBundle::Node(slot) = &*holder else {unreachable!()};
+            let next = gc.alloc(Node(vec![slot.0[0].clone()]).into());
+
+            let BenchBundle::Node(slot) = &mut *holder else {unreachable!()};
+            slot.0[0] = next;
+            gc.write_barrier(&holder);
+        }
+        count
+    });
+}
+
+/// Fully collect a rooted tree where every node has `WIDTH` children and there are at least
+/// `count` leaves.
+fn trace_tree(count: usize) {
+    const WIDTH: usize = 8;
+
+    let mut gc = BenchGc::new();
+    gc.set_collect_on_alloc(false);
+
+    let mut leaves = 1;
+    while leaves < count {
+        leaves *= WIDTH;
+    }
+    let mut level = (0..leaves)
+        .map(|i|gc.alloc(Leaf(i as u64).into()))
+        .collect::<Vec<_>>();
+    while level.len() > 1 {
+        level = level.chunks(WIDTH)
+            .map(|chunk|gc.alloc(Node(chunk.to_vec()).into()))
+            .collect();
+    }
+    let root = gc.root(level.pop().unwrap());
+    let count = gc.stats().live();
+
+    bench("trace tree (full collection)", ||{
+        gc.collect_full();
+        count
+    });
+
+    drop(root);
+}
+
+
+fn main() {
+    // `cargo bench` passes `--bench`, so take the first number
+    let count = args()
+        .find_map(|arg|arg.parse::<usize>().ok())
+        .unwrap_or(200_000);
+
+    alloc_garbage(count * 5);
+    alloc_live(count);
+    trace_tree(count);
+}
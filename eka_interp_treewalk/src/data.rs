@@ -1,4 +1,7 @@
-use rustc_hash::FxHashSet;
+use rustc_hash::{
+    FxHashSet,
+    FxHashMap,
+};
 use anyhow::{
     Result,
     bail,
@@ -23,35 +26,39 @@ use std::{
         DerefMut,
     },
     marker::PhantomData,
+    time::{
+        Duration,
+        Instant,
+    },
     cell::Cell,
+    rc::{
+        Rc,
+        Weak,
+    },
     ptr::NonNull,
     mem,
-    thread_local,
 };
 use eka_core::{
     interpreter::{
         object::*,
         GcTrait,
         Primitive,
+        native::check_arity,
     },
     ast::{
         Ident,
         Interner,
     },
-    misc::FxIndexSet,
 };
 
 
-thread_local! {
-    static GC_WORKLOAD: Cell<GcWorkload> = Cell::new(GcWorkload {
-        traces: 100,
-        mark_dead: 10,
-        gc_when_no_dead: true,
-    });
-}
+/// The shared slot of a weak reference. The GC clears it when the target dies.
+type WeakSlot<O> = Cell<Option<NonNull<DataBox<O>>>>;
 
 
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub enum GcState {
+    #[default]
     MarkRoots,
     Trace,
     MarkDead,
@@ -88,6 +95,7 @@ impl<O: ObjectBundle<Gc<O>>> Deref for DataRef<O> {
     }
 }
 impl<O: ObjectBundle<Gc<O>>> DerefMut for DataRef<O> {
+    /// Storing a reference through this needs a [`Gc::write_barrier`] afterwards.
     fn deref_mut(&mut self)->&mut O {
         &mut self.get_box_mut().data
     }
@@ -97,14 +105,39 @@ impl<O: ObjectBundle<Gc<O>>> DataRef<O> {
     pub fn root(self, gc: &mut Gc<O>)->Option<RootDataRef<O>> {
         gc.root(self.clone())
     }
+
+    #[inline]
+    pub fn downgrade(&self, gc: &mut Gc<O>)->WeakDataRef<O> {
+        gc.downgrade(self)
+    }
 }
 impl<O: ObjectBundle<Gc<O>>> DataRef<O> {
     fn get_box_mut(&mut self)->&mut DataBox<O> {
+        self.check_canary();
         unsafe {self.0.as_mut()}
     }
     fn get_box(&self)->&DataBox<O> {
+        self.check_canary();
         unsafe {self.0.as_ref()}
     }
+
+    /// The address of the object. Only unique among live objects; a recycled object keeps the
+    /// address of the dead one.
+    #[inline]
+    pub fn addr(&self)->usize {
+        self.0.as_ptr() as usize
+    }
+
+    #[inline(always)]
+    fn check_canary(&self) {
+        // SAFETY: Freed boxes are never deallocated in stress mode, so this is always readable.
+        #[cfg(feature = "gc_stress")]
+        assert!(
+            unsafe {self.0.as_ref()}.canary == LIVE_CANARY,
+            "Use of a freed object at {:?}",
+            self.0,
+        );
+    }
 }
 
 #[must_use]
@@ -120,41 +153,263 @@ impl<O: ObjectBundle<Gc<O>>> DerefMut for RootDataRef<O> {
         &mut self.0
     }
 }
+impl<O: ObjectBundle<Gc<O>>> RootDataRef<O> {
+    /// A plain reference to the rooted object, e.g. to pass it to a script.
+    #[inline]
+    pub fn data_ref(&self)->DataRef<O> {
+        self.0.clone()
+    }
+}
+
+/// A reference that does not keep the object alive. It is cleared when the object dies, so it is
+/// safe to hold in Rust code across collections.
+pub struct WeakDataRef<O: ObjectBundle<Gc<O>>>(Rc<WeakSlot<O>>);
+impl<O: ObjectBundle<Gc<O>>> Debug for WeakDataRef<O> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if self.is_alive() {
+            write!(f, "<Weak>")
+        } else {
+            write!(f, "<Weak (dead)>")
+        }
+    }
+}
+impl<O: ObjectBundle<Gc<O>>> Clone for WeakDataRef<O> {
+    fn clone(&self)->Self {
+        WeakDataRef(self.0.clone())
+    }
+}
+impl<O: ObjectBundle<Gc<O>>> WeakDataRef<O> {
+    #[inline]
+    pub fn is_alive(&self)->bool {
+        self.0.get().is_some()
+    }
+
+    /// Get a strong reference if the object is still alive.
+    #[inline]
+    pub fn upgrade(&self, gc: &mut Gc<O>)->Option<DataRef<O>> {
+        gc.upgrade(self)
+    }
+}
+
+/// Written to every live `DataBox` in stress mode. Freed boxes are poisoned, so a freed box never
+/// has it.
+#[cfg(feature = "gc_stress")]
+const LIVE_CANARY: u64 = 0x1ABE11ED_CA11AB1E;
+/// The byte freed boxes are overwritten with in stress mode.
+#[cfg(feature = "gc_stress")]
+const POISON: u8 = 0xDE;
+
+/// The color of an object, stored in its header. Black and white objects use `A` and `B`, and
+/// which one means black flips every cycle, so every black object becomes white at once. Objects in
+/// the nursery are `Young` and have no color until they are promoted.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mark {
+    A,
+    B,
+    Grey,
+    Dead,
+    Young,
+}
+impl Mark {
+    /// Swap `A` and `B`.
+    #[inline]
+    fn flip(self)->Self {
+        match self {
+            Mark::A=>Mark::B,
+            Mark::B=>Mark::A,
+            m=>m,
+        }
+    }
+}
 
 struct DataBox<O: ObjectBundle<Gc<O>>> {
+    #[cfg(feature = "gc_stress")]
+    canary: u64,
+    mark: Cell<Mark>,
+    /// The index in `Gc::objects` while the object is live and old
+    index: Cell<usize>,
+    /// Set while the object is in the dirty list
+    dirty: Cell<bool>,
+    /// Set while the object is in the remembered set
+    remembered: Cell<bool>,
     data: O,
 }
 
-#[derive(Copy, Clone)]
+/// Something that holds references to objects outside of the GC. See [`Gc::add_root_provider`].
+pub trait RootProvider<O: ObjectBundle<Gc<O>>> {
+    /// Call `mark` with every object that is still in use.
+    fn provide_roots(&self, mark: &mut dyn FnMut(&DataRef<O>));
+}
+
+/// How much work each GC increment does. Each [`Gc`] has its own.
+#[derive(Debug, Copy, Clone)]
 pub struct GcWorkload {
-    traces: usize,
-    mark_dead: usize,
-    gc_when_no_dead: bool,
+    /// The max objects traced per increment
+    pub traces: usize,
+    /// The max objects marked dead per increment
+    pub mark_dead: usize,
+    /// Run an increment when allocating with no dead objects to recycle
+    pub gc_when_no_dead: bool,
+    /// The young objects allocated before a minor collection runs. With `0`, objects are allocated
+    /// straight into the old space.
+    pub nursery_size: usize,
+}
+impl Default for GcWorkload {
+    fn default()->Self {
+        GcWorkload {
+            traces: 100,
+            mark_dead: 10,
+            gc_when_no_dead: true,
+            nursery_size: 256,
+        }
+    }
+}
+
+/// Statistics about the GC. The set sizes and state are updated after every allocation and
+/// increment.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GcStats {
+    /// Objects allocated in a new slab slot
+    pub allocations: u64,
+    /// Allocations that reused a dead object
+    pub recycles: u64,
+    /// Objects dropped and deallocated
+    pub frees: u64,
+    pub white: usize,
+    pub grey: usize,
+    pub black: usize,
+    pub dead: usize,
+    /// Objects in the nursery
+    pub young: usize,
+    pub state: GcState,
+    /// Completed GC cycles
+    pub cycles: u64,
+    pub minor_collections: u64,
+    /// Young objects that survived a minor collection and moved to the old space
+    pub promotions: u64,
+    pub increments: u64,
+    pub last_increment: Duration,
+    pub total_time: Duration,
+}
+impl GcStats {
+    /// The count of objects that are not dead.
+    #[inline]
+    pub fn live(&self)->usize {
+        self.white + self.grey + self.black + self.young
+    }
 }
 
-/// An object form of GcWorkload that can be used in the interpreter.
+/// An object form of [`GcWorkload`] that can be used in the interpreter. It is bound to the GC it
+/// was created with, and also has read-only fields for that GC's [`GcStats`].
 #[derive(Debug)]
 pub struct GcWorkloadObject<O: ObjectBundle<Gc<O>>> {
     mark_dead: Ident,
     traces: Ident,
     gc_when_no_dead: Ident,
+    nursery_size: Ident,
+    stat_idents: GcStatIdents,
+    workload: Rc<Cell<GcWorkload>>,
+    stats: Rc<Cell<GcStats>>,
     _phantom: PhantomData<fn()->O>,
 }
 impl<O: ObjectBundle<Gc<O>>> GcWorkloadObject<O> {
-    pub fn new(interner: &mut Interner)->Self {
+    pub fn new(interner: &mut Interner, gc: &Gc<O>)->Self {
         GcWorkloadObject {
             mark_dead: interner.intern("markDead"),
             traces: interner.intern("traces"),
             gc_when_no_dead: interner.intern("gcWhenNoDead"),
+            nursery_size: interner.intern("nurserySize"),
+            stat_idents: GcStatIdents {
+                allocations: interner.intern("allocations"),
+                recycles: interner.intern("recycles"),
+                frees: interner.intern("frees"),
+                live: interner.intern("live"),
+                white: interner.intern("white"),
+                grey: interner.intern("grey"),
+                black: interner.intern("black"),
+                dead: interner.intern("dead"),
+                young: interner.intern("young"),
+                state: interner.intern("state"),
+                cycles: interner.intern("cycles"),
+                minor_collections: interner.intern("minorCollections"),
+                promotions: interner.intern("promotions"),
+                increments: interner.intern("increments"),
+                last_increment_us: interner.intern("lastIncrementUs"),
+                total_time_us: interner.intern("totalTimeUs"),
+                state_names: [
+                    interner.intern("markRoots"),
+                    interner.intern("trace"),
+                    interner.intern("markDead"),
+                ],
+            },
+            workload: gc.workload.clone(),
+            stats: gc.stats.clone(),
             _phantom: PhantomData,
         }
     }
+
+    fn get_stat(&self, name: Ident)->Option<Primitive<Gc<O>, O>> {
+        let stats = self.stats.get();
+        let idents = &self.stat_idents;
+
+        let num = |n: u64|Some(Primitive::Number(n as i64));
+        let us = |d: Duration|Some(Primitive::Float(d.as_secs_f64() * 1_000_000.0));
+
+        if name == idents.allocations {return num(stats.allocations)}
+        if name == idents.recycles {return num(stats.recycles)}
+        if name == idents.frees {return num(stats.frees)}
+        if name == idents.live {return num(stats.live() as u64)}
+        if name == idents.white {return num(stats.white as u64)}
+        if name == idents.grey {return num(stats.grey as u64)}
+        if name == idents.black {return num(stats.black as u64)}
+        if name == idents.dead {return num(stats.dead as u64)}
+        if name == idents.young {return num(stats.young as u64)}
+        if name == idents.cycles {return num(stats.cycles)}
+        if name == idents.minor_collections {return num(stats.minor_collections)}
+        if name == idents.promotions {return num(stats.promotions)}
+        if name == idents.increments {return num(stats.increments)}
+        if name == idents.last_increment_us {return us(stats.last_increment)}
+        if name == idents.total_time_us {return us(stats.total_time)}
+        if name == idents.state {
+            let state = match stats.state {
+                GcState::MarkRoots=>idents.state_names[0],
+                GcState::Trace=>idents.state_names[1],
+                GcState::MarkDead=>idents.state_names[2],
+            };
+            return Some(Primitive::Keyword(state));
+        }
+
+        return None;
+    }
+}
+
+/// The names of the read-only stat fields on [`GcWorkloadObject`].
+#[derive(Debug)]
+struct GcStatIdents {
+    allocations: Ident,
+    recycles: Ident,
+    frees: Ident,
+    live: Ident,
+    white: Ident,
+    grey: Ident,
+    black: Ident,
+    dead: Ident,
+    young: Ident,
+    state: Ident,
+    cycles: Ident,
+    minor_collections: Ident,
+    promotions: Ident,
+    increments: Ident,
+    last_increment_us: Ident,
+    total_time_us: Ident,
+    /// The keywords for each [`GcState`]
+    state_names: [Ident; 3],
 }
 impl<O: ObjectBundle<Gc<O>>> Object<Gc<O>> for GcWorkloadObject<O> {
     type ObjectBundle = O;
 
     fn get(&self, name: Ident, _: &Interner)->Result<Primitive<Gc<O>, O>> {
-        let wl = GC_WORKLOAD.get();
+        let wl = self.workload.get();
 
         if name == self.mark_dead {
             return Ok(Primitive::Number(wl.mark_dead as i64));
@@ -165,33 +420,53 @@ impl<O: ObjectBundle<Gc<O>>> Object<Gc<O>> for GcWorkloadObject<O> {
         if name == self.gc_when_no_dead {
             return Ok(Primitive::Bool(wl.gc_when_no_dead));
         }
+        if name == self.nursery_size {
+            return Ok(Primitive::Number(wl.nursery_size as i64));
+        }
+        if let Some(stat) = self.get_stat(name) {
+            return Ok(stat);
+        }
 
         bail!("No field with the given name on GcWorkload");
     }
 
-    fn set(&mut self, name: Ident, data: Primitive<Gc<O>, O>, _: &Interner)->Result<()> {
-        let mut wl = GC_WORKLOAD.get();
+    fn set(&mut self, name: Ident, data: Primitive<Gc<O>, O>, interner: &Interner)->Result<()> {
+        let mut wl = self.workload.get();
 
         if name == self.mark_dead {
             match data {
                 Primitive::Number(n)=>wl.mark_dead = n as usize,
                 _=>bail!("GcWorkload.mark_dead is a Number"),
             }
-            GC_WORKLOAD.set(wl);
+            self.workload.set(wl);
+            return Ok(());
         }
         if name == self.traces {
             match data {
                 Primitive::Number(n)=>wl.traces = n as usize,
                 _=>bail!("GcWorkload.traces is a Number"),
             }
-            GC_WORKLOAD.set(wl);
+            self.workload.set(wl);
+            return Ok(());
         }
         if name == self.gc_when_no_dead {
             match data {
                 Primitive::Bool(b)=>wl.gc_when_no_dead = b,
                 _=>bail!("GcWorkload.gc_when_no_dead is a Bool"),
             }
-            GC_WORKLOAD.set(wl);
+            self.workload.set(wl);
+            return Ok(());
+        }
+        if name == self.nursery_size {
+            match data {
+                Primitive::Number(n)=>wl.nursery_size = n as usize,
+                _=>bail!("GcWorkload.nursery_size is a Number"),
+            }
+            self.workload.set(wl);
+            return Ok(());
+        }
+        if self.get_stat(name).is_some() {
+            bail!("GcWorkload.{} is read-only", interner.get(name));
         }
 
         bail!("No field with the given name on GcWorkload");
@@ -208,12 +483,180 @@ impl<O: ObjectBundle<Gc<O>>> Object<Gc<O>> for GcWorkloadObject<O> {
     fn trace(&self, _: &mut Gc<O>) {}
 }
 
+/// A weak reference object for scripts. `(w/get)` returns the object or `#N` if it died, and
+/// `(w/alive?)` checks if it is still alive.
+#[derive(Debug)]
+pub struct WeakObject<O: ObjectBundle<Gc<O>>> {
+    weak: WeakDataRef<O>,
+    get_ident: Ident,
+    alive_ident: Ident,
+}
+impl<O: ObjectBundle<Gc<O>>> WeakObject<O> {
+    pub fn new(weak: WeakDataRef<O>, interner: &mut Interner)->Self {
+        WeakObject {
+            weak,
+            get_ident: interner.intern("get"),
+            alive_ident: interner.intern("alive?"),
+        }
+    }
+}
+impl<O: ObjectBundle<Gc<O>>> Object<Gc<O>> for WeakObject<O> {
+    type ObjectBundle = O;
+
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        Some("Weak")
+    }
+
+    fn get(&self, _: Ident, _: &Interner)->Result<Primitive<Gc<O>, O>> {
+        bail!("There are no fields on Weak");
+    }
+    fn set(&mut self, _: Ident, _: Primitive<Gc<O>, O>, _: &Interner)->Result<()> {
+        bail!("There are no fields on Weak");
+    }
+
+    fn call(&mut self, _: Vec<Primitive<Gc<O>, O>>, _: &Interner, _: &mut Gc<O>)->Result<CallReturn<Gc<O>, O>> {
+        bail!("Cannot call Weak");
+    }
+
+    fn method(&mut self, name: Ident, args: Vec<Primitive<Gc<O>, O>>, _: &Interner, gc: &mut Gc<O>)->Result<CallReturn<Gc<O>, O>> {
+        if name == self.get_ident {
+            check_arity("Weak.get", 0, args.len())?;
+            let data = self.weak.upgrade(gc)
+                .map(Primitive::Data)
+                .unwrap_or(Primitive::None);
+
+            return Ok(CallReturn::Data(data));
+        }
+        if name == self.alive_ident {
+            check_arity("Weak.alive?", 0, args.len())?;
+            return Ok(CallReturn::Data(Primitive::Bool(self.weak.is_alive())));
+        }
+
+        bail!("No method with the given name on Weak");
+    }
+
+    /// Weak references are not traced, so they don't keep anything alive.
+    fn trace(&self, _: &mut Gc<O>) {}
+}
+
+/// A native function that creates a [`WeakObject`] for its argument. Register it as a global to
+/// let scripts create weak references with `(weak obj)`.
+pub fn make_weak<O>(mut args: Vec<Primitive<Gc<O>, O>>, interner: &mut Interner, gc: &mut Gc<O>)->Result<CallReturn<Gc<O>, O>>
+where
+    O: ObjectBundle<Gc<O>> + From<WeakObject<O>>,
+{
+    check_arity("weak", 1, args.len())?;
+    match args.pop().unwrap() {
+        Primitive::Data(dr)=>{
+            let weak = gc.downgrade(&dr);
+            let obj = WeakObject::new(weak, interner);
+
+            return Ok(CallReturn::Data(Primitive::Data(gc.alloc(obj.into()))));
+        },
+        p=>bail!("`weak` expected Data, but got {}", p.type_name()),
+    }
+}
+
+/// The objects in each slab chunk.
+const SLAB_CHUNK: usize = 256;
+
+/// Allocates `DataBox`es in chunks of [`SLAB_CHUNK`]. Every object in a bundle is the same size, so
+/// a single size class is enough. Freed slots are reused, and the chunks are only deallocated when
+/// the slab is dropped.
+struct Slab<O: ObjectBundle<Gc<O>>> {
+    chunks: Vec<NonNull<DataBox<O>>>,
+    free: Vec<NonNull<DataBox<O>>>,
+}
+impl<O: ObjectBundle<Gc<O>>> Slab<O> {
+    fn new()->Self {
+        Slab {
+            chunks: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Get an uninitialized slot.
+    fn alloc(&mut self)->NonNull<DataBox<O>> {
+        if let Some(ptr) = self.free.pop() {
+            return ptr;
+        }
+
+        let layout = Self::chunk_layout();
+        // SAFETY: We are allocating via the global allocator...
+        let raw_ptr = unsafe {global_alloc(layout)};
+        // ... then we assert the allocation is not null
+        let chunk = NonNull::new(raw_ptr)
+            .expect("Could not allocate")
+            .cast::<DataBox<O>>();
+        self.chunks.push(chunk);
+
+        // hand out the slots in address order
+        for i in (1..SLAB_CHUNK).rev() {
+            // SAFETY: `i` is in bounds of the chunk
+            self.free.push(unsafe {chunk.add(i)});
+        }
+
+        return chunk;
+    }
+
+    /// Return a slot whose contents were already dropped. Stress mode never reuses slots.
+    #[inline]
+    #[cfg_attr(feature = "gc_stress", allow(dead_code))]
+    fn release(&mut self, ptr: NonNull<DataBox<O>>) {
+        self.free.push(ptr);
+    }
+
+    fn chunk_layout()->Layout {
+        Layout::array::<DataBox<O>>(SLAB_CHUNK).expect("Slab chunk is too large")
+    }
+}
+impl<O: ObjectBundle<Gc<O>>> Drop for Slab<O> {
+    /// Deallocates every chunk. The objects in them must already be dropped.
+    fn drop(&mut self) {
+        let layout = Self::chunk_layout();
+        for chunk in self.chunks.drain(..) {
+            // SAFETY: The chunk was allocated with the same layout, and the GC drops every object
+            // before the slab.
+            unsafe {global_dealloc(chunk.as_ptr().cast::<u8>(), layout)};
+        }
+    }
+}
+
 pub struct Gc<O: ObjectBundle<Gc<O>>> {
-    white: FxIndexSet<DataRef<O>>,
-    grey: FxIndexSet<DataRef<O>>,
-    black: FxIndexSet<DataRef<O>>,
+    slab: Slab<O>,
+    /// Every object that is not dead. Each object stores its index in its header.
+    objects: Vec<DataRef<O>>,
+    /// The objects waiting to be traced
+    grey: Vec<DataRef<O>>,
+    /// Dead objects waiting to be recycled
+    dead: Vec<DataRef<O>>,
+    /// The young objects allocated since the last minor collection
+    nursery: Vec<DataRef<O>>,
+    /// The old objects that got new references since the last minor collection
+    remembered: Vec<DataRef<O>>,
+    /// The black objects that got new references since they were traced this cycle
+    dirty: Vec<DataRef<O>>,
     roots: FxHashSet<DataRef<O>>,
-    dead: FxIndexSet<DataRef<O>>,
+    /// The mark black objects have this cycle. White objects have the other one.
+    black_mark: Mark,
+    white_count: usize,
+    black_count: usize,
+    /// The next index in `objects` to check for white objects while marking dead.
+    sweep: usize,
+    /// The weak references to each object. They are cleared when the object dies.
+    weak_refs: FxHashMap<DataRef<O>, Vec<Weak<WeakSlot<O>>>>,
+    root_providers: Vec<Rc<dyn RootProvider<O>>>,
+    /// Shared with every [`GcWorkloadObject`] so scripts can change it.
+    workload: Rc<Cell<GcWorkload>>,
+    /// Shared with every [`GcWorkloadObject`] so scripts can read it.
+    stats: Rc<Cell<GcStats>>,
+    /// Run minor collections and increments during allocation.
+    collect_on_alloc: bool,
+    /// Set by [`Gc::step_for`] so single increments also stop when the budget runs out.
+    deadline: Option<Instant>,
+    /// When set, tracing an object collects its references here instead of shading them.
+    children: Option<Vec<DataRef<O>>>,
     state: GcState,
 }
 impl<O: ObjectBundle<Self>> Debug for Gc<O> {
@@ -224,15 +667,133 @@ impl<O: ObjectBundle<Self>> Debug for Gc<O> {
 impl<O: ObjectBundle<Gc<O>>> Gc<O> {
     pub fn new()->Self {
         Gc {
-            white: FxIndexSet::default(),
-            grey: FxIndexSet::default(),
-            black: FxIndexSet::default(),
+            slab: Slab::new(),
+            objects: Vec::new(),
+            grey: Vec::new(),
+            dead: Vec::new(),
+            nursery: Vec::new(),
+            remembered: Vec::new(),
+            dirty: Vec::new(),
             roots: FxHashSet::default(),
-            dead: FxIndexSet::default(),
+            black_mark: Mark::A,
+            white_count: 0,
+            black_count: 0,
+            sweep: 0,
+            weak_refs: FxHashMap::default(),
+            root_providers: Vec::new(),
+            workload: Rc::new(Cell::new(GcWorkload::default())),
+            stats: Rc::new(Cell::new(GcStats::default())),
+            collect_on_alloc: true,
+            deadline: None,
+            children: None,
             state: GcState::MarkRoots,
         }
     }
 
+    #[inline]
+    pub fn workload(&self)->GcWorkload {
+        self.workload.get()
+    }
+
+    #[inline]
+    pub fn set_workload(&mut self, workload: GcWorkload) {
+        self.workload.set(workload);
+    }
+
+    #[inline]
+    pub fn stats(&self)->GcStats {
+        self.stats.get()
+    }
+
+    /// Update the stats and refresh the set sizes and state.
+    fn update_stats<F: FnOnce(&mut GcStats)>(&self, f: F) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+
+        stats.white = self.white_count;
+        stats.grey = self.grey.len();
+        stats.black = self.black_count;
+        stats.dead = self.dead.len();
+        stats.young = self.nursery.len();
+        stats.state = self.state;
+
+        self.stats.set(stats);
+    }
+
+    #[inline]
+    fn white_mark(&self)->Mark {
+        self.black_mark.flip()
+    }
+
+    #[inline]
+    fn is_black(&self, dr: &DataRef<O>)->bool {
+        dr.get_box().mark.get() == self.black_mark
+    }
+
+    #[inline]
+    fn is_white(&self, dr: &DataRef<O>)->bool {
+        dr.get_box().mark.get() == self.white_mark()
+    }
+
+    /// The name of the object's color.
+    pub(crate) fn color_name(&self, dr: &DataRef<O>)->&'static str {
+        match dr.get_box().mark.get() {
+            Mark::Grey=>"grey",
+            Mark::Dead=>"dead",
+            Mark::Young=>"young",
+            m if m == self.black_mark=>"black",
+            _=>"white",
+        }
+    }
+
+    /// Create a weak reference to the object.
+    pub fn downgrade(&mut self, dr: &DataRef<O>)->WeakDataRef<O> {
+        let slot = Rc::new(Cell::new(Some(dr.0)));
+        let weaks = self.weak_refs.entry(dr.clone()).or_default();
+
+        // forget the weak refs that were dropped
+        weaks.retain(|w|w.strong_count() > 0);
+        weaks.push(Rc::downgrade(&slot));
+
+        return WeakDataRef(slot);
+    }
+
+    /// Get a strong reference from a weak one if the object is still alive. An old object is marked
+    /// grey so it can't be collected during the current cycle.
+    pub fn upgrade(&mut self, weak: &WeakDataRef<O>)->Option<DataRef<O>> {
+        let dr = DataRef(weak.0.get()?);
+        self.shade(dr.clone());
+
+        return Some(dr);
+    }
+
+    /// Clear every weak reference to the object.
+    fn clear_weak_refs(&mut self, dr: &DataRef<O>) {
+        if let Some(weaks) = self.weak_refs.remove(dr) {
+            for weak in weaks {
+                if let Some(slot) = weak.upgrade() {
+                    slot.set(None);
+                }
+            }
+        }
+    }
+
+    /// Clear the weak references to every white object. This runs before the sweep starts, because
+    /// the white objects are dead from then on, and upgrading one during the sweep would revive it
+    /// after the objects it references may have been freed.
+    fn clear_white_weak_refs(&mut self) {
+        let white = self.weak_refs.keys()
+            .filter(|dr|self.is_white(dr))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for dr in white.iter() {
+            self.clear_weak_refs(dr);
+        }
+    }
+
+    /// Keep the object alive until the root is passed to [`Gc::unroot`]. Returns `None` if it is
+    /// already rooted.
     pub fn root(&mut self, dr: DataRef<O>)->Option<RootDataRef<O>> {
         if self.roots.contains(&dr) {
             return None;
@@ -247,44 +808,234 @@ impl<O: ObjectBundle<Gc<O>>> Gc<O> {
         drop(dr);
     }
 
+    /// Allocate an object. With a nursery, the object is young and only survives the next minor
+    /// collection if it is reachable from a root or an old object by then. The returned reference
+    /// does not keep the object alive, so [`Gc::root`] it to hold it across allocations.
     pub fn alloc(&mut self, data: O)->DataRef<O> {
+        // collect everything that is unreachable right now, so objects that are not rooted or
+        // referenced between allocations are found quickly.
+        #[cfg(feature = "gc_stress")]
+        self.collect_full();
+
+        let wl = self.workload.get();
+        let collect = self.collect_on_alloc && !cfg!(feature = "gc_stress");
+        if collect && !self.nursery.is_empty() && self.nursery.len() >= wl.nursery_size {
+            // the old space grew by the promoted objects, so do enough major work to keep up
+            let promoted = self.collect_minor();
+            for _ in 0..=promoted / wl.traces.max(1) {
+                self.gc_inc();
+            }
+        }
+
         // short-circuit if there is already a dead object.
-        if let Some(mut dr) = self.dead.swap_remove_index(0) {
-            dr.get_box_mut()
-                .data
-                .recycle_insert(data);
+        let recycled = !self.dead.is_empty();
+        let dr = if let Some(mut dr) = self.dead.pop() {
+            let db = dr.get_box_mut();
+            db.dirty.set(false);
+            db.remembered.set(false);
+            db.data.recycle_insert(data);
+
+            dr
+        } else {
+            let db = DataBox {
+                #[cfg(feature = "gc_stress")]
+                canary: LIVE_CANARY,
+                mark: Cell::new(Mark::Grey),
+                index: Cell::new(0),
+                dirty: Cell::new(false),
+                remembered: Cell::new(false),
+                data,
+            };
 
-            return dr;
+            let nn_ptr = self.slab.alloc();
+            // SAFETY: We are initializing the memory
+            unsafe {
+                nn_ptr.write(db);
+            }
+
+            DataRef(nn_ptr)
+        };
+
+        if wl.nursery_size > 0 {
+            dr.get_box().mark.set(Mark::Young);
+            self.nursery.push(dr.clone());
+        } else {
+            self.insert_grey(dr.clone());
         }
+        self.update_stats(|s|if recycled {
+            s.recycles += 1;
+        } else {
+            s.allocations += 1;
+        });
 
-        let db = DataBox {data};
+        if collect && self.dead.len() == 0 && wl.gc_when_no_dead {
+            self.gc_inc();
+        }
 
-        let layout = Layout::new::<DataBox<O>>();
+        return dr;
+    }
 
-        // SAFETY: We are allocating via the global allocator...
-        let raw_ptr = unsafe {global_alloc(layout)};
-        // ... then we assert the allocation is not null
-        let nn_ptr = NonNull::new(raw_ptr)
-            .expect("Could not allocate")
-            .cast::<DataBox<O>>();
+    /// Collect the nursery. Young objects reachable from a root or from an object in the
+    /// remembered set are promoted to the old space as grey, and every other young object dies.
+    /// Returns the count of promoted objects.
+    pub fn collect_minor(&mut self)->usize {
+        let start = Instant::now();
+        let mut worklist = Vec::new();
+
+        for root in self.roots.iter() {
+            worklist.push(root.clone());
+        }
+        for provider in self.root_providers.iter() {
+            provider.provide_roots(&mut |dr|worklist.push(dr.clone()));
+        }
 
-        // SAFETY: We are initializing the memory
-        unsafe {
-            nn_ptr.write(db);
+        let remembered = mem::take(&mut self.remembered);
+        for dr in remembered {
+            dr.get_box().remembered.set(false);
+            worklist.extend(self.children(&dr));
         }
 
-        let dr = DataRef(nn_ptr);
-        self.grey.insert(dr.clone());
+        // promote everything young that is reachable from the worklist
+        let mut promoted = 0;
+        while let Some(dr) = worklist.pop() {
+            if dr.get_box().mark.get() != Mark::Young {
+                continue;
+            }
 
-        if self.dead.len() == 0 && GC_WORKLOAD.get().gc_when_no_dead {
-            self.gc_inc();
+            self.insert_grey(dr.clone());
+            promoted += 1;
+            worklist.extend(self.children(&dr));
+        }
+
+        // everything still young is unreachable
+        let mut nursery = mem::take(&mut self.nursery);
+        let mut frees = 0;
+        for mut dr in nursery.drain(..) {
+            if dr.get_box().mark.get() != Mark::Young {
+                continue;
+            }
+            dr.get_box().mark.set(Mark::Dead);
+
+            self.clear_weak_refs(&dr);
+            dr.finalize();
+            if dr.can_recycle() {
+                self.dead.push(dr);
+            } else {
+                // SAFETY: The object is unreachable and no longer in any of the sets.
+                unsafe {self.free(dr)};
+                frees += 1;
+            }
+        }
+        self.nursery = nursery;
+
+        let elapsed = start.elapsed();
+        self.update_stats(|s|{
+            s.minor_collections += 1;
+            s.promotions += promoted as u64;
+            s.frees += frees;
+            s.last_increment = elapsed;
+            s.total_time += elapsed;
+        });
+
+        #[cfg(feature = "gc_stress")]
+        self.verify();
+
+        return promoted;
+    }
+
+    /// Add a new or recycled object to the live objects as grey.
+    fn insert_grey(&mut self, dr: DataRef<O>) {
+        let db = dr.get_box();
+        db.mark.set(Mark::Grey);
+        db.index.set(self.objects.len());
+
+        self.objects.push(dr.clone());
+        self.grey.push(dr);
+    }
+
+    /// Remove the object at `index` from the live objects. The last object takes its place.
+    fn remove_object(&mut self, index: usize)->DataRef<O> {
+        let dr = self.objects.swap_remove(index);
+        if let Some(moved) = self.objects.get(index) {
+            moved.get_box().index.set(index);
         }
 
         return dr;
     }
 
+    /// Collect the nursery, finish the current cycle, then run a complete cycle so every object
+    /// that is dead right now is finalized. Dead objects are kept for recycling; use
+    /// [`Gc::free_dead`] to free them.
+    pub fn collect_full(&mut self) {
+        self.collect_minor();
+        if !matches!(self.state, GcState::MarkRoots) {
+            self.finish_cycle();
+        }
+        // objects allocated while marking dead are still grey and would survive one more cycle
+        if !self.grey.is_empty() {
+            self.finish_cycle();
+        }
+        self.finish_cycle();
+    }
+
+    /// Free every dead object waiting to be recycled.
+    pub fn free_dead(&mut self) {
+        let dead = mem::take(&mut self.dead);
+        let count = dead.len() as u64;
+        for dr in dead {
+            // SAFETY: Dead objects are unreachable and no longer in any of the sets.
+            unsafe {self.free(dr)};
+        }
+        self.update_stats(|s|s.frees += count);
+    }
+
+    /// Enable or disable collecting during allocation. When disabled, collection only happens when
+    /// the host calls [`Gc::collect_minor`], [`Gc::gc_inc`], [`Gc::step_for`], or
+    /// [`Gc::collect_full`].
+    #[inline]
+    pub fn set_collect_on_alloc(&mut self, enabled: bool) {
+        self.collect_on_alloc = enabled;
+    }
+
+    /// Collect the nursery, then run increments until `budget` is used up or the current cycle
+    /// finishes, whichever comes first. Increments stop tracing or marking dead objects once the
+    /// budget is used up, so the budget is only overrun by the minor collection and the work of a
+    /// single object. Returns the count of increments run.
+    pub fn step_for(&mut self, budget: Duration)->usize {
+        let deadline = Instant::now() + budget;
+        self.deadline = Some(deadline);
+        let mut count = 0;
+
+        self.collect_minor();
+
+        loop {
+            self.gc_inc();
+            count += 1;
+
+            if matches!(self.state, GcState::MarkRoots) || Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        self.deadline = None;
+        return count;
+    }
+
+    /// Run increments until the GC is back at the start of a cycle.
+    fn finish_cycle(&mut self) {
+        loop {
+            self.gc_inc();
+            if matches!(self.state, GcState::MarkRoots) {
+                break;
+            }
+        }
+    }
+
     pub fn gc_inc(&mut self) {
         use GcState::*;
+        let start = Instant::now();
+        let mut cycle_done = false;
+
         match self.state {
             MarkRoots=>{
                 self.mark_roots();
@@ -293,52 +1044,179 @@ impl<O: ObjectBundle<Gc<O>>> Gc<O> {
             Trace=>{
                 self.trace();
                 if self.grey.is_empty() {
-                    self.state = MarkDead;
+                    // The roots and black objects may have changed since the cycle started, so
+                    // scan them again before anything is marked dead.
+                    self.mark_roots();
+                    self.shade_dirty();
+                    if self.grey.is_empty() {
+                        self.clear_white_weak_refs();
+                        self.state = MarkDead;
+                    }
                 }
             },
             MarkDead=>{
                 self.mark_dead();
-                if self.white.is_empty() {
-                    mem::swap(&mut self.white, &mut self.black);
+                if self.white_count == 0 {
+                    // every black object becomes white for the next cycle
+                    self.black_mark = self.white_mark();
+                    self.white_count = mem::take(&mut self.black_count);
+                    self.sweep = 0;
                     self.state = MarkRoots;
+                    cycle_done = true;
                 }
             },
         }
+
+        let elapsed = start.elapsed();
+        self.update_stats(|s|{
+            s.increments += 1;
+            s.cycles += cycle_done as u64;
+            s.last_increment = elapsed;
+            s.total_time += elapsed;
+        });
+
+        #[cfg(feature = "gc_stress")]
+        self.verify();
     }
 
+    /// Register something that holds references outside of the GC, like an interpreter's scopes.
+    /// It is asked for its roots every time the roots are marked.
+    pub fn add_root_provider(&mut self, provider: Rc<dyn RootProvider<O>>) {
+        self.root_providers.push(provider);
+    }
+
+    /// Shade the roots. Young objects are not part of the cycle, so everything they reference is
+    /// shaded too.
     fn mark_roots(&mut self) {
+        let roots = self.roots.iter().cloned().collect::<Vec<_>>();
+        for root in roots {
+            self.shade(root);
+        }
+
+        let providers = self.root_providers.clone();
+        for provider in providers.iter() {
+            provider.provide_roots(&mut |dr|self.shade(dr.clone()));
+        }
+
+        for i in 0..self.nursery.len() {
+            let dr = self.nursery[i].clone();
+            for child in self.children(&dr) {
+                self.shade(child);
+            }
+        }
+    }
+
+    /// Get the objects the given object references without changing any colors.
+    pub fn children(&mut self, dr: &DataRef<O>)->Vec<DataRef<O>> {
+        let old = self.children.replace(Vec::new());
+        dr.get_box().data.trace(self);
+
+        return mem::replace(&mut self.children, old).unwrap_or_default();
+    }
+
+    /// Every object that is not dead along with the name of its color.
+    pub(crate) fn live_objects(&self)->impl Iterator<Item = (&DataRef<O>, &'static str)> {
+        self.objects.iter()
+            .chain(self.nursery.iter())
+            .map(|dr|(dr, self.color_name(dr)))
+    }
+
+    /// Call `f` with every root, including the ones from root providers.
+    pub(crate) fn for_each_root<F: FnMut(&DataRef<O>)>(&self, mut f: F) {
         for root in self.roots.iter() {
-            self.grey.insert(root.clone());
-            self.white.swap_remove(root);
+            f(root);
+        }
+        for provider in self.root_providers.iter() {
+            provider.provide_roots(&mut f);
+        }
+    }
+
+    /// Call after storing a reference in the object, e.g. through `DerefMut`. If the object was
+    /// already traced this cycle, the GC traces it again before anything is marked dead. Old
+    /// objects are also added to the remembered set, since they may now reference young objects.
+    ///
+    /// [`Object::set`](eka_core::interpreter::object::Object::set) calls from scripts already do
+    /// this.
+    pub fn write_barrier(&mut self, dr: &DataRef<O>) {
+        let db = dr.get_box();
+        if matches!(db.mark.get(), Mark::Young | Mark::Dead) {
+            return;
+        }
+
+        if !db.remembered.replace(true) {
+            self.remembered.push(dr.clone());
+        }
+        if self.state == GcState::Trace && self.is_black(dr) && !db.dirty.replace(true) {
+            self.dirty.push(dr.clone());
         }
     }
 
+    /// Move every black object in the dirty list back to grey.
+    fn shade_dirty(&mut self) {
+        for dr in mem::take(&mut self.dirty) {
+            dr.get_box().dirty.set(false);
+            if self.is_black(&dr) {
+                dr.get_box().mark.set(Mark::Grey);
+                self.grey.push(dr);
+                self.black_count -= 1;
+            }
+        }
+    }
+
+    /// Mark a white object grey. Grey, black, and young objects are left alone.
+    fn shade(&mut self, dr: DataRef<O>) {
+        if !self.is_white(&dr) {
+            debug_assert!(dr.get_box().mark.get() != Mark::Dead, "Shaded a dead object");
+            return;
+        }
+
+        dr.get_box().mark.set(Mark::Grey);
+        self.white_count -= 1;
+        self.grey.push(dr);
+    }
+
     fn trace(&mut self) {
-        let wl = GC_WORKLOAD.get();
+        let wl = self.workload.get();
         let mut count = 0;
 
         // for every item in the grey list, trace it and mark it black
-        while let Some(dr) = self.grey.shift_remove_index(0) {
-            self.black.insert(dr.clone());
-            dr.get_box().data.trace(self);
+        while let Some(dr) = self.grey.pop() {
+            let db = dr.get_box();
+            db.mark.set(self.black_mark);
+            self.black_count += 1;
+            db.data.trace(self);
             
             count += 1;
-            if count > wl.traces {
+            if count >= wl.traces || self.past_deadline() {
                 break;
             }
         }
     }
 
     fn mark_dead(&mut self) {
-        let wl = GC_WORKLOAD.get();
+        let wl = self.workload.get();
         let mut count = 0;
 
         // for every white item left (now dead) check if it needs immediate dropping and do so.
         // Otherwise put it in the dead list for recycling later.
-        while let Some(mut dr) = self.white.swap_remove_index(0) {
+        while self.white_count > 0 && self.sweep < self.objects.len() {
+            if !self.is_white(&self.objects[self.sweep]) {
+                self.sweep += 1;
+                continue;
+            }
+
+            // the last object is moved to `sweep`, so it is checked next
+            let mut dr = self.remove_object(self.sweep);
+            self.white_count -= 1;
+            dr.get_box().mark.set(Mark::Dead);
+            if dr.get_box().remembered.replace(false) {
+                self.remembered.retain(|r|*r != dr);
+            }
+
+            self.clear_weak_refs(&dr);
             dr.finalize();
             if dr.can_recycle() {
-                self.dead.insert(dr);
+                self.dead.push(dr);
             } else {
                 unsafe {
                     self.cleanup_single_dead(dr);
@@ -346,28 +1224,153 @@ impl<O: ObjectBundle<Gc<O>>> Gc<O> {
             }
 
             count += 1;
-            if count > wl.mark_dead {
+            if count >= wl.mark_dead || self.past_deadline() {
                 break;
             }
         }
     }
 
-    unsafe fn cleanup_single_dead(&self, dr: DataRef<O>) {
+    #[inline]
+    fn past_deadline(&self)->bool {
+        self.deadline.is_some_and(|d|Instant::now() >= d)
+    }
+
+    unsafe fn cleanup_single_dead(&mut self, dr: DataRef<O>) {
         // Assert we are not included in any of the lists
-        debug_assert!(!self.white.contains(&dr));
-        debug_assert!(!self.grey.contains(&dr));
-        debug_assert!(!self.black.contains(&dr));
+        debug_assert!(dr.get_box().mark.get() == Mark::Dead);
         debug_assert!(!self.roots.contains(&dr));
-        debug_assert!(!self.dead.contains(&dr));
 
+        unsafe {self.free(dr)};
+        self.update_stats(|s|s.frees += 1);
+    }
+
+    /// Drop the object and return its slot to the slab. In stress mode, the memory is poisoned and
+    /// never reused, so any later use of the object panics instead of reading another object.
+    unsafe fn free(&mut self, dr: DataRef<O>) {
         // SAFETY: We are cleaning up initialized memory
         unsafe {dr.0.drop_in_place()};
 
-        let layout = Layout::new::<DataBox<O>>();
-        let raw_ptr = dr.0.as_ptr().cast::<u8>();
-        // SAFETY: We are deallocating memory that we are reasonably sure has no pointers outside
-        // of here. Of course, we can't be sure of that, so this function is marked unsafe.
-        unsafe {global_dealloc(raw_ptr, layout)};
+        // SAFETY: The box was just dropped and nothing reads it except the canary check.
+        #[cfg(feature = "gc_stress")]
+        unsafe {dr.0.cast::<u8>().write_bytes(POISON, size_of::<DataBox<O>>())};
+
+        #[cfg(not(feature = "gc_stress"))]
+        self.slab.release(dr.0);
+    }
+}
+/// The heap verifier for stress mode.
+#[cfg(feature = "gc_stress")]
+impl<O: ObjectBundle<Gc<O>>> Gc<O> {
+    /// Check the tri-color invariants and panic if any are broken:
+    /// - Each object has exactly one color, and the grey, dead, and nursery lists match the marks
+    /// - No black object references a white one unless it is dirty and will be traced again
+    /// - No old object references a young one unless it is in the remembered set
+    /// - Roots are never dead, and are never white while dead objects are being marked
+    pub fn verify(&mut self) {
+        let (mut white, mut grey, mut black) = (0, 0, 0);
+        for (i, dr) in self.objects.iter().enumerate() {
+            dr.check_canary();
+            let db = dr.get_box();
+            assert_eq!(db.index.get(), i, "GC object {:?} has the wrong index", dr.0);
+            match db.mark.get() {
+                Mark::Dead=>panic!("GC object {:?} is both live and dead", dr.0),
+                Mark::Young=>panic!("GC object {:?} is both old and young", dr.0),
+                Mark::Grey=>grey += 1,
+                m if m == self.black_mark=>black += 1,
+                _=>white += 1,
+            }
+        }
+        assert_eq!(white, self.white_count, "GC white count is wrong");
+        assert_eq!(black, self.black_count, "GC black count is wrong");
+
+        let mut grey_list = FxHashSet::default();
+        for dr in self.grey.iter() {
+            assert!(dr.get_box().mark.get() == Mark::Grey, "GC object {:?} is queued but not grey", dr.0);
+            assert!(grey_list.insert(dr.clone()), "GC object {:?} is queued twice", dr.0);
+        }
+        assert_eq!(grey, self.grey.len(), "GC grey objects are not all queued");
+
+        for dr in self.dead.iter() {
+            dr.check_canary();
+            assert!(dr.get_box().mark.get() == Mark::Dead, "GC object {:?} is recyclable but not dead", dr.0);
+        }
+
+        for dr in self.nursery.iter() {
+            dr.check_canary();
+            assert!(dr.get_box().mark.get() == Mark::Young, "GC object {:?} is in the nursery but not young", dr.0);
+        }
+        for dr in self.dirty.iter() {
+            dr.check_canary();
+            assert!(dr.get_box().dirty.get(), "GC object {:?} is in the dirty list but not flagged", dr.0);
+        }
+        for dr in self.remembered.iter() {
+            dr.check_canary();
+            let db = dr.get_box();
+            assert!(db.remembered.get(), "GC object {:?} is in the remembered set but not flagged", dr.0);
+            assert!(
+                !matches!(db.mark.get(), Mark::Dead | Mark::Young),
+                "GC object {:?} is in the remembered set but not old",
+                dr.0,
+            );
+        }
+
+        let old = self.objects.clone();
+        for dr in old {
+            let db = dr.get_box();
+            let check_white = self.is_black(&dr) && !db.dirty.get();
+            let remembered = db.remembered.get();
+            for child in self.children(&dr) {
+                assert!(
+                    !(check_white && self.is_white(&child)),
+                    "Black GC object {:?} references white object {:?}",
+                    dr.0,
+                    child.0,
+                );
+                assert!(
+                    remembered || child.get_box().mark.get() != Mark::Young,
+                    "Old GC object {:?} references young object {:?} but is not remembered",
+                    dr.0,
+                    child.0,
+                );
+            }
+        }
+
+        let marking_dead = self.state == GcState::MarkDead;
+        self.for_each_root(|root|{
+            root.check_canary();
+            let mark = root.get_box().mark.get();
+            assert!(mark != Mark::Dead, "GC root {:?} is dead", root.0);
+            assert!(
+                !(marking_dead && self.is_white(root)),
+                "GC root {:?} is white while marking dead objects",
+                root.0,
+            );
+        });
+    }
+}
+impl<O: ObjectBundle<Gc<O>>> Drop for Gc<O> {
+    /// Finalizes and frees every object, including the ones waiting to be recycled. Any `DataRef`
+    /// still held outside of the GC is dangling after this.
+    fn drop(&mut self) {
+        let mut live = mem::take(&mut self.objects);
+        live.append(&mut self.nursery);
+        self.grey.clear();
+        self.roots.clear();
+        self.remembered.clear();
+        self.dirty.clear();
+
+        // finalize everything before dropping anything, so finalizers never see freed objects
+        for dr in live.iter() {
+            self.clear_weak_refs(dr);
+            dr.clone().finalize();
+        }
+
+        for dr in live {
+            // SAFETY: The object is no longer in any of the sets and we are being dropped.
+            unsafe {self.free(dr)};
+        }
+
+        self.free_dead();
     }
 }
 impl<O: ObjectBundle<Gc<O>>> GcTrait<O> for Gc<O> {
@@ -378,15 +1381,11 @@ impl<O: ObjectBundle<Gc<O>>> GcTrait<O> for Gc<O> {
     }
 
     fn trace(&mut self, ptr: DataRef<O>) {
-        // don't insert if its in the black list already
-        if self.black.contains(&ptr) {
-            // assert the ptr is NOT in the white list or grey list
-            debug_assert!(!self.white.contains(&ptr));
-            debug_assert!(!self.grey.contains(&ptr));
+        if let Some(children) = self.children.as_mut() {
+            children.push(ptr);
             return;
         }
 
-        self.white.swap_remove(&ptr);
-        self.grey.insert(ptr);
+        self.shade(ptr);
     }
 }
@@ -4,81 +4,173 @@ use anyhow::{
     bail,
 };
 use misc_utils::Stack;
-use std::mem;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    mem,
+};
 use eka_core::{
     interpreter::{
         object::*,
+        native::*,
         Primitive,
     },
     ast::*,
+    source::with_span,
 };
 use data::*;
 
 
 pub mod data;
+pub mod snapshot;
+
 
+/// Everything the interpreter holds that can reference GC objects. The GC scans it for roots at
+/// the start of each cycle and again before it marks anything dead.
+struct Scopes<O: ObjectBundle<Gc<O>>> {
+    global: IdentMap<Primitive<Gc<O>, O>>,
+    vars: Stack<IdentMap<Primitive<Gc<O>, O>>>,
+    /// The var stacks of the functions that called the current one.
+    callers: Vec<Stack<IdentMap<Primitive<Gc<O>, O>>>>,
+    /// Values that are in use, but not stored in a var, like args that are still being evaluated.
+    temps: Vec<Primitive<Gc<O>, O>>,
+}
+impl<O: ObjectBundle<Gc<O>>> RootProvider<O> for RefCell<Scopes<O>> {
+    fn provide_roots(&self, mark: &mut dyn FnMut(&DataRef<O>)) {
+        let scopes = self.borrow();
 
+        let mut mark_prim = |p: &Primitive<Gc<O>, O>|if let Primitive::Data(dr) = p {
+            mark(dr);
+        };
+
+        scopes.global.values().for_each(&mut mark_prim);
+        for stack in scopes.callers.iter().chain([&scopes.vars]) {
+            for scope in stack.iter() {
+                scope.values().for_each(&mut mark_prim);
+            }
+        }
+        scopes.temps.iter().for_each(&mut mark_prim);
+    }
+}
+
+
+/// Runs the AST directly. The interner isn't owned by the interpreter, so the same one can be used
+/// to parse more source into the stores between runs. Every method that needs it takes it.
 pub struct Interpreter<O: ObjectBundle<Gc<O>>> {
-    pub interner: Interner,
     gc: Gc<O>,
-    global_scope: IdentMap<Primitive<Gc<O>, O>>,
-    vars: Stack<IdentMap<Primitive<Gc<O>, O>>>,
+    scopes: Rc<RefCell<Scopes<O>>>,
+    /// The count of roots in the `ExprStore` that have already been run.
+    ran_roots: usize,
 }
-impl<O: ObjectBundle<Gc<O>>> Interpreter<O> {
-    pub fn new(interner: Interner)->Self {
-        let mut i = Interpreter {
-            interner,
-            gc: Gc::new(),
-            global_scope: IdentMap::default(),
+impl<O: ObjectBundle<Gc<O>> + 'static> Interpreter<O> {
+    /// Create an interpreter with the builtin functions defined. Their names are interned into
+    /// `interner`.
+    pub fn new(interner: &mut Interner)->Self {
+        let scopes = Rc::new(RefCell::new(Scopes {
+            global: IdentMap::default(),
             vars: Stack::new(),
+            callers: Vec::new(),
+            temps: Vec::new(),
+        }));
+        let mut gc = Gc::new();
+        gc.add_root_provider(scopes.clone());
+
+        let mut i = Interpreter {
+            gc,
+            scopes,
+            ran_roots: 0,
         };
 
         use eka_core::interpreter::builtins;
 
-        i.def_global_str("+", Primitive::NativeFn(builtins::add));
-        i.def_global_str("-", Primitive::NativeFn(builtins::sub));
-        i.def_global_str("*", Primitive::NativeFn(builtins::mul));
-        i.def_global_str("/", Primitive::NativeFn(builtins::div));
-        i.def_global_str("format", Primitive::NativeFn(builtins::format));
+        i.def_global_str("+", Primitive::NativeFn(builtins::add), interner);
+        i.def_global_str("-", Primitive::NativeFn(builtins::sub), interner);
+        i.def_global_str("*", Primitive::NativeFn(builtins::mul), interner);
+        i.def_global_str("/", Primitive::NativeFn(builtins::div), interner);
+        i.def_global_str("format", Primitive::NativeFn(builtins::format), interner);
+        i.def_global_str("doc", Primitive::NativeFn(builtins::doc), interner);
 
         return i;
     }
 
-    pub fn run(&mut self, store: &ExprStore, funcs: &FunctionStore)->Result<Primitive<Gc<O>, O>> {
+    /// Run the roots added to the store since the last run, in order. Globals defined by earlier
+    /// runs are still defined. Returns the value of the last root run.
+    ///
+    /// A root that fails is still counted as run, so the next run starts after it.
+    pub fn run_new_roots(&mut self, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        let roots = store.iter_roots()
+            .skip(self.ran_roots)
+            .copied()
+            .collect::<Vec<_>>();
+
         let mut last = Primitive::None;
-        for root in store.iter_roots() {
-            last = self.run_expr(*root, store, funcs)?;
+        for root in roots {
+            self.ran_roots += 1;
+            last = self.run_expr(root, store, funcs, interner)?;
         }
 
         return Ok(last);
     }
 
-    pub fn run_expr(&mut self, id: ExprId, store: &ExprStore, funcs: &FunctionStore)->Result<Primitive<Gc<O>, O>> {
+    /// Mark every root in the store as run without running them. Used to throw away source that
+    /// failed to parse or type check.
+    #[inline]
+    pub fn skip_new_roots(&mut self, store: &ExprStore) {
+        self.ran_roots = store.root_count();
+    }
+
+    /// The count of roots that have been run or skipped.
+    #[inline]
+    pub fn ran_roots(&self)->usize {
+        self.ran_roots
+    }
+
+    /// Run the expression. Errors get the span of the innermost expression that failed.
+    pub fn run_expr(&mut self, id: ExprId, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        return self.run_expr_inner(id, store, funcs, interner)
+            .map_err(|e|with_span(e, store.span(id)));
+    }
+
+    fn run_expr_inner(&mut self, id: ExprId, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
         use Expr::*;
         match &store[id] {
             Begin(block)=>{
-                let mut last = Primitive::None;
-                self.vars.push(IdentMap::default());
+                let mut last = Ok(Primitive::None);
+                self.scopes.borrow_mut().vars.push(IdentMap::default());
                 for id in block.iter() {
-                    last = self.run_expr(*id, store, funcs)?;
+                    last = self.run_expr(*id, store, funcs, interner);
+                    if last.is_err() {
+                        break;
+                    }
                 }
-                self.vars.pop();
+                // pop the scope even on errors, or later top level defs would go in it
+                self.scopes.borrow_mut().vars.pop();
 
-                Ok(last)
+                last
             },
 
-            DefVar(name, expr)=>{
-                let val = self.run_expr(*expr, store, funcs)?;
+            DefVar(name, ty, expr)=>{
+                let val = self.run_expr(*expr, store, funcs, interner)?;
+                if let Some(ty) = ty {
+                    if !val.matches_type(*ty, interner) {
+                        bail!(
+                            "Var `{}` is annotated as {}, but got {}",
+                            interner.get(*name),
+                            ty.name(interner),
+                            val.type_name(),
+                        );
+                    }
+                }
                 self.def_var(*name, val);
 
                 Ok(Primitive::None)
             },
             SetVar(name, expr)=>{
-                let val = self.run_expr(*expr, store, funcs)?;
-                self.set_var(*name, val)?;
+                let val = self.run_expr(*expr, store, funcs, interner)?;
+                self.set_var(*name, val, interner)?;
                 Ok(Primitive::None)
             },
-            GetVar(name)=>return self.get_var(*name),
+            GetVar(name)=>return self.get_var(*name, interner),
 
             Cond{branches,default}=>{
                 todo!();
@@ -92,44 +184,50 @@ impl<O: ObjectBundle<Gc<O>>> Interpreter<O> {
 
             Call(lhs, raw_args)=>{
                 match &store[*lhs] {
-                    Expr::GetPath(p)=>return self.call_path(p, raw_args, store, funcs),
+                    Expr::GetPath(p)=>return self.call_path(p, raw_args, store, funcs, interner),
                     _=>{},
                 }
 
-                let lhs = self.run_expr(*lhs, store, funcs)?;
-                let mut args = Vec::new();
-                for arg in raw_args {
-                    args.push(self.run_expr(*arg, store, funcs)?);
-                }
+                let temps = self.temps_len();
+                let ret = self.run_expr(*lhs, store, funcs, interner)
+                    .and_then(|lhs|{
+                        self.push_temp(lhs.clone());
+                        let args = self.eval_args(raw_args, store, funcs, interner)?;
+                        self.call(lhs, args, store, funcs, interner)
+                    });
+                self.truncate_temps(temps);
 
-                match lhs {
-                    Primitive::Fn(id)=>return self.call_function(id, args, store, funcs),
-                    Primitive::Data(mut d)=>{
-                        let ret = d.call(args, &self.interner, &mut self.gc)?;
-                        return self.object_return_thing(ret, store, funcs);
-                    },
-                    Primitive::NativeFn(f)=>{
-                        let ret = f(args, &mut self.interner, &mut self.gc)?;
-                        return self.object_return_thing(ret, store, funcs);
-                    },
-                    _=>bail!("Cannot call primitive type"),
-                }
+                return ret;
             },
 
             Method(_lhs, _name, _args)=>todo!(),
 
             GetPath(path)=>{
-                let (lhs, name) = self.resolve_path(path)?;
+                let (lhs, name) = self.resolve_path(path, interner)?;
                 match lhs {
-                    Primitive::Data(d)=>return d.get(name, &self.interner),
+                    Primitive::Data(d)=>return d.get(name, interner),
                     _=>bail!("Cannot get a field on primitive type"),
                 }
             },
             SetPath{path,data}=>{
-                let (lhs, name) = self.resolve_path(path)?;
-                let val = self.run_expr(*data, store, funcs)?;
+                let (lhs, name) = self.resolve_path(path, interner)?;
+
+                let temps = self.temps_len();
+                self.push_temp(lhs.clone());
+                let val = self.run_expr(*data, store, funcs, interner);
+                self.truncate_temps(temps);
+
                 match lhs {
-                    Primitive::Data(mut d)=>return d.set(name, val, &self.interner).map(|_|Primitive::None),
+                    Primitive::Data(mut d)=>{
+                        let val = val?;
+                        let stores_ref = matches!(val, Primitive::Data(_));
+                        d.set(name, val, interner)?;
+                        if stores_ref {
+                            self.gc.write_barrier(&d);
+                        }
+
+                        return Ok(Primitive::None);
+                    },
                     _=>bail!("Cannot set a field on primitive type"),
                 }
             },
@@ -142,6 +240,55 @@ impl<O: ObjectBundle<Gc<O>>> Interpreter<O> {
             Bool(b)=>Ok(Primitive::Bool(*b)),
             Keyword(i)=>Ok(Primitive::Keyword(*i)),
             None=>Ok(Primitive::None),
+
+            Module(..)|Import(..)|Export(_)=>bail!("Modules have to be resolved by a module loader before they are run"),
+
+            Error=>bail!("Cannot run source that failed to parse"),
+        }
+    }
+
+    /// Call any callable primitive with the args.
+    pub fn call(&mut self, callee: Primitive<Gc<O>, O>, args: Vec<Primitive<Gc<O>, O>>, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        // the callee and args are only owned by us during the call, so keep them alive
+        let temps = self.temps_len();
+        self.push_temp(callee.clone());
+        self.scopes.borrow_mut().temps.extend(args.iter().cloned());
+
+        let ret = self.call_inner(callee, args, store, funcs, interner);
+        self.truncate_temps(temps);
+
+        return ret;
+    }
+
+    /// Convert the args and call the callee with them. Each arg is kept alive from when it is
+    /// converted, so allocating the later ones can't free it.
+    pub fn call_with_args<A: IntoArgs<Gc<O>, O>>(&mut self, callee: Primitive<Gc<O>, O>, args: A, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        let temps = self.temps_len();
+        self.push_temp(callee.clone());
+
+        let scopes = &self.scopes;
+        let ret = args.into_args(&mut self.gc, &mut |arg|scopes.borrow_mut().temps.push(arg.clone()))
+            .and_then(|args|self.call(callee, args, store, funcs, interner));
+        self.truncate_temps(temps);
+
+        return ret;
+    }
+
+    fn call_inner(&mut self, callee: Primitive<Gc<O>, O>, args: Vec<Primitive<Gc<O>, O>>, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        match callee {
+            Primitive::Fn(id)=>return self.call_function(id, args, store, funcs, interner),
+            Primitive::Data(mut d)=>{
+                let (passed_refs, allocs) = (has_refs(&args), self.alloc_count());
+                let ret = d.call(args, interner, &mut self.gc);
+                self.call_barrier(&d, passed_refs, allocs);
+
+                return self.object_return_thing(ret?, store, funcs, interner);
+            },
+            Primitive::NativeFn(f)=>{
+                let ret = f(args, interner, &mut self.gc)?;
+                return self.object_return_thing(ret, store, funcs, interner);
+            },
+            _=>bail!("Cannot call primitive type"),
         }
     }
 
@@ -149,64 +296,164 @@ impl<O: ObjectBundle<Gc<O>>> Interpreter<O> {
         self.gc.alloc(obj)
     }
 
+    #[inline]
+    pub fn gc(&self)->&Gc<O> {
+        &self.gc
+    }
+
+    /// Create a `GcWorkload` object that reads the stats of this interpreter's GC.
+    pub fn gc_workload_object(&self, interner: &mut Interner)->GcWorkloadObject<O> {
+        GcWorkloadObject::new(interner, &self.gc)
+    }
+
+    #[inline]
+    pub fn gc_mut(&mut self)->&mut Gc<O> {
+        &mut self.gc
+    }
+
     #[inline(always)]
-    fn call_path(&mut self, path: &[Ident], raw_args: &[ExprId], store: &ExprStore, funcs: &FunctionStore)->Result<Primitive<Gc<O>, O>> {
-        let (lhs, name) = self.resolve_path(path)?;
+    fn call_path(&mut self, path: &[Ident], raw_args: &[ExprId], store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
+        let (lhs, name) = self.resolve_path(path, interner)?;
+
+        let temps = self.temps_len();
+        self.push_temp(lhs.clone());
+        let ret = self.eval_args(raw_args, store, funcs, interner)
+            .and_then(|args|match lhs {
+                Primitive::Data(mut d)=>{
+                    let (passed_refs, allocs) = (has_refs(&args), self.alloc_count());
+                    let ret = d.method(name, args, interner, &mut self.gc);
+                    self.call_barrier(&d, passed_refs, allocs);
+
+                    self.object_return_thing(ret?, store, funcs, interner)
+                },
+                _=>bail!("Cannot call a method on primitive type"),
+            });
+        self.truncate_temps(temps);
+
+        return ret;
+    }
+
+    /// An object can only store references to the objects it is passed or allocates, so a call on
+    /// one only needs the write barrier if it was passed any or the GC allocated during it.
+    #[inline]
+    fn call_barrier(&mut self, d: &DataRef<O>, passed_refs: bool, allocs: u64) {
+        if passed_refs || self.alloc_count() != allocs {
+            self.gc.write_barrier(d);
+        }
+    }
 
+    #[inline]
+    fn alloc_count(&self)->u64 {
+        let stats = self.gc.stats();
+        stats.allocations + stats.recycles
+    }
+
+    /// Evaluate the args and keep them alive as temps. Callers have to truncate the temps when they
+    /// are done with the args.
+    fn eval_args(&mut self, raw_args: &[ExprId], store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Vec<Primitive<Gc<O>, O>>> {
         let mut args = Vec::new();
         for arg in raw_args {
-            args.push(self.run_expr(*arg, store, funcs)?);
+            let val = self.run_expr(*arg, store, funcs, interner)?;
+            self.push_temp(val.clone());
+            args.push(val);
         }
 
-        match lhs {
-            Primitive::Data(mut d)=>{
-                let ret = d.method(name, args, &self.interner, &mut self.gc)?;
-                return self.object_return_thing(ret, store, funcs);
-            },
-            _=>bail!("Cannot call a method on primitive type"),
-        }
+        return Ok(args);
+    }
+
+    #[inline]
+    fn push_temp(&self, val: Primitive<Gc<O>, O>) {
+        self.scopes.borrow_mut().temps.push(val);
     }
 
-    fn object_return_thing(&mut self, ret: CallReturn<Gc<O>, O>, store: &ExprStore, funcs: &FunctionStore)->Result<Primitive<Gc<O>, O>> {
+    #[inline]
+    fn temps_len(&self)->usize {
+        self.scopes.borrow().temps.len()
+    }
+
+    #[inline]
+    fn truncate_temps(&self, len: usize) {
+        self.scopes.borrow_mut().temps.truncate(len);
+    }
+
+    fn object_return_thing(&mut self, ret: CallReturn<Gc<O>, O>, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
         match ret {
-            CallReturn::CallFn(id, args)=>return self.call_function(id, args, store, funcs),
+            CallReturn::CallFn(id, args)=>return self.call_function(id, args, store, funcs, interner),
             CallReturn::Data(val)=>return Ok(val),
+            CallReturn::Doc(id)=>match &funcs[id].doc {
+                Some(doc)=>return Ok(Primitive::String(doc.clone())),
+                None=>return Ok(Primitive::None),
+            },
         }
     }
 
-    fn call_function(&mut self, id: FnId, args: Vec<Primitive<Gc<O>, O>>, store: &ExprStore, funcs: &FunctionStore)->Result<Primitive<Gc<O>, O>> {
-        let old_vars = mem::replace(&mut self.vars, Stack::new());
-        self.vars.push(IdentMap::default());
-
+    fn call_function(&mut self, id: FnId, args: Vec<Primitive<Gc<O>, O>>, store: &ExprStore, funcs: &FunctionStore, interner: &mut Interner)->Result<Primitive<Gc<O>, O>> {
         let function = &funcs[id];
 
         if function.params.len() != args.len() {
             bail!("Expected {} args, but got {}", function.params.len(), args.len());
         }
 
-        for (param, data) in function.params.iter().zip(args) {
-            self.def_var(*param, data);
+        for ((param, ty), data) in function.params.iter().zip(&function.param_types).zip(&args) {
+            if let Some(ty) = ty {
+                if !data.matches_type(*ty, interner) {
+                    bail!(
+                        "Param `{}` of `{}` expected {}, but got {}",
+                        interner.get(*param),
+                        interner.get(function.name),
+                        ty.name(interner),
+                        data.type_name(),
+                    );
+                }
+            }
         }
 
-        self.vars.push(IdentMap::default());
-        let ret = self.run_expr(function.block, store, funcs)?;
+        {
+            let mut scopes = self.scopes.borrow_mut();
+            let old_vars = mem::replace(&mut scopes.vars, Stack::new());
+            scopes.callers.push(old_vars);
+
+            let mut param_scope = IdentMap::default();
+            for (param, data) in function.params.iter().zip(args) {
+                param_scope.insert(*param, data);
+            }
+            scopes.vars.push(param_scope);
+            scopes.vars.push(IdentMap::default());
+        }
 
-        self.vars = old_vars;
+        let ret = self.run_expr(function.block, store, funcs, interner);
+
+        {
+            let mut scopes = self.scopes.borrow_mut();
+            scopes.vars = scopes.callers.pop().unwrap();
+        }
+        let ret = ret?;
+
+        if let Some(ty) = function.ret_type {
+            if !ret.matches_type(ty, interner) {
+                bail!(
+                    "`{}` should return {}, but returned {}",
+                    interner.get(function.name),
+                    ty.name(interner),
+                    ret.type_name(),
+                );
+            }
+        }
 
         return Ok(ret);
     }
 
     /// Resolves a path EXCEPT the last item, which it returns.
-    fn resolve_path(&self, path: &[Ident])->Result<(Primitive<Gc<O>, O>, Ident)> {
+    fn resolve_path(&self, path: &[Ident], interner: &Interner)->Result<(Primitive<Gc<O>, O>, Ident)> {
         let mut path_iter = path.iter();
         let mut next_name = *path_iter.next().unwrap();
-        let mut data = self.get_var(next_name)?;
+        let mut data = self.get_var(next_name, interner)?;
         next_name = *path_iter.next().unwrap();
 
         loop {
             if let Some(name) = path_iter.next() {
                 match data {
-                    Primitive::Data(d)=>data = d.get(next_name, &self.interner)?,
+                    Primitive::Data(d)=>data = d.get(next_name, interner)?,
                     _=>bail!("Cannot get field on primitive type"),
                 }
                 next_name = *name;
@@ -217,58 +464,93 @@ impl<O: ObjectBundle<Gc<O>>> Interpreter<O> {
         return Ok((data, next_name));
     }
 
-    pub fn get_var(&self, name: Ident)->Result<Primitive<Gc<O>, O>> {
-        for scope in self.vars.iter() {
+    pub fn get_var(&self, name: Ident, interner: &Interner)->Result<Primitive<Gc<O>, O>> {
+        let scopes = self.scopes.borrow();
+        for scope in scopes.vars.iter() {
             if let Some(var) = scope.get(&name) {
                 return Ok(var.clone());
             }
         }
 
-        return self.global_scope.get(&name)
+        return scopes.global.get(&name)
             .cloned()
-            .ok_or(anyhow!("Var `{}` is undefined", self.interner.get(name)));
+            .ok_or(anyhow!("Var `{}` is undefined", interner.get(name)));
     }
 
     #[inline]
     pub fn def_global(&mut self, name: Ident, data: Primitive<Gc<O>, O>) {
-        self.global_scope.insert(name, data);
+        self.scopes.borrow_mut().global.insert(name, data);
     }
 
     #[inline]
-    pub fn def_global_str(&mut self, name: &str, data: Primitive<Gc<O>, O>) {
-        let name = self.interner.intern(name);
-        self.global_scope.insert(name, data);
+    pub fn def_global_str(&mut self, name: &str, data: Primitive<Gc<O>, O>, interner: &mut Interner) {
+        let name = interner.intern(name);
+        self.def_global(name, data);
+    }
+
+    /// Allocate a [`NativeFnObject`] for the closure and define it as a global. The closure's
+    /// argument and return types are converted automatically.
+    pub fn def_native_fn<Args, F>(&mut self, name: &str, func: F, interner: &mut Interner)
+    where
+        F: IntoNativeFn<Gc<O>, O, Args>,
+        O: From<NativeFnObject<Gc<O>, O>>,
+    {
+        let dr = self.gc.alloc(func.into_native_fn(name).into());
+        self.def_global_str(name, Primitive::Data(dr), interner);
     }
 
     pub fn def_var(&mut self, name: Ident, data: Primitive<Gc<O>, O>) {
+        let mut scopes = self.scopes.borrow_mut();
+
         // global scope
-        if self.vars.len() == 0 {
-            self.global_scope.insert(name, data);
+        if scopes.vars.len() == 0 {
+            scopes.global.insert(name, data);
             return;
         }
 
-        self.vars.last_mut().unwrap().insert(name, data);
+        scopes.vars.last_mut().unwrap().insert(name, data);
     }
 
-    pub fn set_var(&mut self, name: Ident, data: Primitive<Gc<O>, O>)->Result<()> {
+    pub fn set_var(&mut self, name: Ident, data: Primitive<Gc<O>, O>, interner: &Interner)->Result<()> {
+        let mut scopes = self.scopes.borrow_mut();
+
         // global scope
-        if self.vars.len() == 0 {
-            let var = self.global_scope.get_mut(&name)
-                .ok_or(anyhow!("Cannot set undefined var `{}`", self.interner.get(name)))?;
+        if scopes.vars.len() == 0 {
+            let var = scopes.global.get_mut(&name)
+                .ok_or(anyhow!("Cannot set undefined var `{}`", interner.get(name)))?;
             *var = data;
 
             return Ok(());
         }
 
-        if let Some(var) = self.vars.last_mut().unwrap().get_mut(&name) {
+        if let Some(var) = scopes.vars.last_mut().unwrap().get_mut(&name) {
             *var = data;
             return Ok(());
         } else {
-            bail!("Cannot set undefined var `{}`", self.interner.get(name));
+            bail!("Cannot set undefined var `{}`", interner.get(name));
         }
     }
 }
 
+impl<O: ObjectBundle<Gc<O>> + 'static> eka_core::interpreter::Interpreter<O> for Interpreter<O> {
+    type Gc = Gc<O>;
+
+    /// Runs only the roots added since the last run. See [`Interpreter::run_new_roots`].
+    fn run(&mut self,
+        exprs: &ExprStore,
+        funcs: &FunctionStore,
+        interner: &mut Interner,
+    )->Result<Primitive<Gc<O>, O>> {
+        self.run_new_roots(exprs, funcs, interner)
+    }
+}
+
+/// Whether any of the args is an object.
+#[inline]
+fn has_refs<O: ObjectBundle<Gc<O>>>(args: &[Primitive<Gc<O>, O>])->bool {
+    args.iter().any(|arg|matches!(arg, Primitive::Data(_)))
+}
+
 #[derive(Debug)]
 pub struct Closure<O: ObjectBundle<Gc<O>>> {
     id: FnId,
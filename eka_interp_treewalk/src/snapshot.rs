@@ -0,0 +1,234 @@
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult,
+    Write,
+};
+use eka_core::interpreter::object::*;
+use crate::data::*;
+
+
+/// A copy of the live object graph at one point in time. Objects are identified by their address,
+/// which is stable while the object is alive.
+#[derive(Debug, Clone, Default)]
+pub struct HeapSnapshot {
+    pub objects: Vec<ObjectSnapshot>,
+    /// The addresses of every root, including the ones from root providers
+    pub roots: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectSnapshot {
+    pub addr: usize,
+    /// The name of the bundle variant
+    pub variant: &'static str,
+    /// See [`Object::type_name`]
+    pub type_name: Option<String>,
+    pub color: &'static str,
+    /// The addresses of the objects it references
+    pub refs: Vec<usize>,
+    /// The addresses of the roots it can be reached from
+    pub reached_by: Vec<usize>,
+}
+
+/// The difference between two snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    /// Objects only in the newer snapshot
+    pub added: Vec<usize>,
+    /// Objects only in the older snapshot
+    pub removed: Vec<usize>,
+    /// The object count of each variant as `(variant, old, new)`. Only variants where the count
+    /// changed are included.
+    pub counts: Vec<(&'static str, usize, usize)>,
+}
+impl Display for SnapshotDiff {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        writeln!(f, "+{} -{} objects", self.added.len(), self.removed.len())?;
+        for (variant, old, new) in self.counts.iter() {
+            let change = *new as isize - *old as isize;
+            writeln!(f, "    {variant}: {old} -> {new} ({change:+})")?;
+        }
+
+        Ok(())
+    }
+}
+
+
+impl HeapSnapshot {
+    /// Take a snapshot of every object that is not dead. Finding which roots reach each object
+    /// walks the graph once per root, so this can be slow on large heaps.
+    pub fn new<O: ObjectBundle<Gc<O>>>(gc: &mut Gc<O>)->Self {
+        let live = gc.live_objects()
+            .map(|(dr, color)|(dr.clone(), color))
+            .collect::<Vec<_>>();
+
+        let mut roots = Vec::new();
+        gc.for_each_root(|dr|roots.push(dr.clone()));
+
+        let mut objects = Vec::with_capacity(live.len());
+        let mut index = FxHashMap::default();
+        for (dr, color) in live {
+            index.insert(dr.addr(), objects.len());
+            objects.push(ObjectSnapshot {
+                addr: dr.addr(),
+                variant: dr.variant_name(),
+                type_name: dr.type_name().map(String::from),
+                color,
+                refs: gc.children(&dr).iter().map(DataRef::addr).collect(),
+                reached_by: Vec::new(),
+            });
+        }
+
+        let mut root_addrs = roots.iter().map(DataRef::addr).collect::<Vec<_>>();
+        root_addrs.sort_unstable();
+        root_addrs.dedup();
+
+        for root in root_addrs.iter().copied() {
+            let mut seen = FxHashSet::default();
+            let mut stack = vec![root];
+            while let Some(addr) = stack.pop() {
+                if !seen.insert(addr) {
+                    continue;
+                }
+                let Some(&i) = index.get(&addr) else {continue};
+
+                objects[i].reached_by.push(root);
+                stack.extend(objects[i].refs.iter().copied());
+            }
+        }
+
+        return HeapSnapshot {
+            objects,
+            roots: root_addrs,
+        };
+    }
+
+    /// Compare this snapshot to a newer one.
+    pub fn diff(&self, newer: &HeapSnapshot)->SnapshotDiff {
+        let old = self.objects.iter()
+            .map(|o|(o.addr, o.variant))
+            .collect::<FxHashSet<_>>();
+        let new = newer.objects.iter()
+            .map(|o|(o.addr, o.variant))
+            .collect::<FxHashSet<_>>();
+
+        // a recycled object keeps its address, so the variant is part of its identity
+        let added = newer.objects.iter()
+            .filter(|o|!old.contains(&(o.addr, o.variant)))
+            .map(|o|o.addr)
+            .collect();
+        let removed = self.objects.iter()
+            .filter(|o|!new.contains(&(o.addr, o.variant)))
+            .map(|o|o.addr)
+            .collect();
+
+        let mut counts = FxHashMap::<&'static str, (usize, usize)>::default();
+        for obj in self.objects.iter() {
+            counts.entry(obj.variant).or_default().0 += 1;
+        }
+        for obj in newer.objects.iter() {
+            counts.entry(obj.variant).or_default().1 += 1;
+        }
+        let mut counts = counts.into_iter()
+            .filter(|(_, (old, new))|old != new)
+            .map(|(variant, (old, new))|(variant, old, new))
+            .collect::<Vec<_>>();
+        counts.sort_unstable_by_key(|(variant, ..)|*variant);
+
+        return SnapshotDiff {added, removed, counts};
+    }
+
+    /// Export the snapshot as JSON in the form
+    /// `{"roots": [addr], "objects": [{"addr", "variant", "type", "color", "refs", "reachedBy"}]}`.
+    pub fn to_json(&self)->String {
+        let mut out = String::from("{\n  \"roots\": ");
+        write_addr_list(&mut out, &self.roots);
+        out.push_str(",\n  \"objects\": [");
+
+        for (i, obj) in self.objects.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("\n    {\"addr\": ");
+            write!(out, "{}", obj.addr).unwrap();
+            out.push_str(", \"variant\": ");
+            write_json_str(&mut out, obj.variant);
+            out.push_str(", \"type\": ");
+            match &obj.type_name {
+                Some(name)=>write_json_str(&mut out, name),
+                None=>out.push_str("null"),
+            }
+            out.push_str(", \"color\": ");
+            write_json_str(&mut out, obj.color);
+            out.push_str(", \"refs\": ");
+            write_addr_list(&mut out, &obj.refs);
+            out.push_str(", \"reachedBy\": ");
+            write_addr_list(&mut out, &obj.reached_by);
+            out.push('}');
+        }
+
+        out.push_str("\n  ]\n}\n");
+        return out;
+    }
+
+    /// Export the snapshot as a Graphviz DOT graph. Roots are drawn with a double border.
+    pub fn to_dot(&self)->String {
+        let roots = self.roots.iter().copied().collect::<FxHashSet<_>>();
+        let mut out = String::from("digraph heap {\n    node [shape=box];\n");
+
+        for obj in self.objects.iter() {
+            let label = match &obj.type_name {
+                Some(name)=>format!("{} ({name})", obj.variant),
+                None=>obj.variant.to_string(),
+            };
+            write!(out, "    n{} [label=", obj.addr).unwrap();
+            write_json_str(&mut out, &format!("{label}\n{:#x}", obj.addr));
+            if roots.contains(&obj.addr) {
+                out.push_str(", peripheries=2");
+            }
+            out.push_str("];\n");
+        }
+        for obj in self.objects.iter() {
+            for r in obj.refs.iter() {
+                writeln!(out, "    n{} -> n{r};", obj.addr).unwrap();
+            }
+        }
+
+        out.push_str("}\n");
+        return out;
+    }
+}
+
+
+fn write_addr_list(out: &mut String, addrs: &[usize]) {
+    out.push('[');
+    for (i, addr) in addrs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write!(out, "{addr}").unwrap();
+    }
+    out.push(']');
+}
+
+/// Write a quoted string with JSON escapes. DOT accepts the same escapes for the ones we emit.
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"'=>out.push_str("\\\""),
+            '\\'=>out.push_str("\\\\"),
+            '\n'=>out.push_str("\\n"),
+            '\r'=>out.push_str("\\r"),
+            '\t'=>out.push_str("\\t"),
+            c if (c as u32) < 0x20=>write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c=>out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,139 @@
+//! The object bundle and helpers shared by the GC tests.
+#![allow(dead_code)]
+
+
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    cell::Cell,
+    rc::Rc,
+};
+use eka_core::{
+    interpreter::{
+        object::*,
+        GcTrait,
+        Primitive,
+    },
+    ast::{
+        Ident,
+        Interner,
+    },
+};
+use eka_interp_treewalk::data::{
+    Gc,
+    DataRef,
+    RootDataRef,
+    GcWorkload,
+};
+
+
+pub type TestGc = Gc<TestBundle>;
+
+
+eka_core::bundle_object_types! {
+    bundle TestBundle where GC = TestGc {
+        Node: Node,
+    }
+}
+
+
+/// An object with a value and references to other objects. It sets `dropped` when it is dropped,
+/// so tests can see when the GC frees it.
+#[derive(Debug, Default)]
+pub struct Node {
+    pub value: i64,
+    pub children: Vec<DataRef<TestBundle>>,
+    pub dropped: Option<Rc<Cell<bool>>>,
+}
+impl Drop for Node {
+    fn drop(&mut self) {
+        if let Some(dropped) = &self.dropped {
+            dropped.set(true);
+        }
+    }
+}
+impl Object<TestGc> for Node {
+    type ObjectBundle = TestBundle;
+
+    #[inline]
+    fn type_name(&self)->Option<&str> {
+        Some("Node")
+    }
+
+    fn get(&self, _: Ident, _: &Interner)->Result<Primitive<TestGc, TestBundle>> {
+        Ok(Primitive::Number(self.value))
+    }
+    fn set(&mut self, _: Ident, _: Primitive<TestGc, TestBundle>, _: &Interner)->Result<()> {
+        bail!("Node fields can't be set from scripts");
+    }
+    fn call(&mut self, _: Vec<Primitive<TestGc, TestBundle>>, _: &Interner, _: &mut TestGc)->Result<CallReturn<TestGc, TestBundle>> {
+        bail!("Cannot call Node");
+    }
+    fn method(&mut self, _: Ident, _: Vec<Primitive<TestGc, TestBundle>>, _: &Interner, _: &mut TestGc)->Result<CallReturn<TestGc, TestBundle>> {
+        bail!("Node has no methods");
+    }
+    fn trace(&self, tracer: &mut TestGc) {
+        for child in self.children.iter() {
+            tracer.trace(child.clone());
+        }
+    }
+}
+
+
+/// A workload without a nursery that only collects when the test asks it to.
+pub fn manual_workload()->GcWorkload {
+    GcWorkload {
+        traces: 100,
+        mark_dead: 10,
+        gc_when_no_dead: false,
+        nursery_size: 0,
+    }
+}
+
+/// A GC that only collects when the test asks it to.
+pub fn manual_gc()->TestGc {
+    let mut gc = TestGc::new();
+    gc.set_workload(manual_workload());
+    gc.set_collect_on_alloc(false);
+
+    return gc;
+}
+
+pub fn node(gc: &mut TestGc, value: i64, children: Vec<DataRef<TestBundle>>)->DataRef<TestBundle> {
+    gc.alloc(Node {value, children, dropped: None}.into())
+}
+
+/// A node with a flag that is set when the node is dropped.
+pub fn tracked_node(gc: &mut TestGc, value: i64)->(DataRef<TestBundle>, Rc<Cell<bool>>) {
+    let dropped = Rc::new(Cell::new(false));
+    let dr = gc.alloc(Node {value, children: Vec::new(), dropped: Some(dropped.clone())}.into());
+
+    return (dr, dropped);
+}
+
+/// The value of a node. Takes the object so both `DataRef`s and `RootDataRef`s can be passed.
+pub fn value(obj: &TestBundle)->i64 {
+    match obj {
+        TestBundle::Node(n)=>n.value,
+    }
+}
+
+/// Storing references through this needs a write barrier; see [`push_child`].
+pub fn node_mut(obj: &mut TestBundle)->&mut Node {
+    match obj {
+        TestBundle::Node(n)=>n,
+    }
+}
+
+/// Add a child to the rooted node and run the write barrier.
+pub fn push_child(gc: &mut TestGc, holder: &mut RootDataRef<TestBundle>, child: DataRef<TestBundle>) {
+    node_mut(holder).children.push(child);
+    gc.write_barrier(&holder.data_ref());
+}
+
+/// The count of live `Node`s. Dead objects waiting to be recycled are not counted.
+pub fn live(gc: &TestGc)->usize {
+    gc.stats().live()
+}
@@ -0,0 +1,276 @@
+//! Tests for collections, the GC stats, and the workload. Stress mode collects on every allocation,
+//! so these only run without it; `stress.rs` has the tests for it.
+#![cfg(not(feature = "gc_stress"))]
+
+
+mod common;
+
+use std::time::Duration;
+use eka_core::{
+    interpreter::{
+        object::Object,
+        Primitive,
+    },
+    ast::Interner,
+};
+use eka_interp_treewalk::data::{
+    GcState,
+    GcWorkload,
+    GcWorkloadObject,
+    RootDataRef,
+};
+use common::*;
+
+
+#[test]
+fn stats_after_full_collection() {
+    let mut gc = manual_gc();
+    let kept = node(&mut gc, 0, Vec::new());
+    let _root = gc.root(kept).unwrap();
+    for i in 1..=10 {
+        let _ = node(&mut gc, i, Vec::new());
+    }
+
+    let stats = gc.stats();
+    assert_eq!(stats.allocations, 11);
+    assert_eq!(stats.live(), 11);
+    assert_eq!(stats.cycles, 0);
+
+    gc.collect_full();
+    let stats = gc.stats();
+    assert_eq!(stats.live(), 1);
+    assert_eq!(stats.dead, 10);
+    assert_eq!(stats.frees, 0);
+    assert_eq!(stats.grey, 0);
+    assert_eq!(stats.state, GcState::MarkRoots);
+    assert!(stats.cycles >= 1);
+    assert!(stats.increments >= stats.cycles);
+    assert!(stats.total_time >= stats.last_increment);
+
+    // dead objects are recycled before new ones are allocated
+    let _ = node(&mut gc, 11, Vec::new());
+    let stats = gc.stats();
+    assert_eq!(stats.recycles, 1);
+    assert_eq!(stats.allocations, 11);
+    assert_eq!(stats.dead, 9);
+
+    gc.free_dead();
+    let stats = gc.stats();
+    assert_eq!(stats.frees, 9);
+    assert_eq!(stats.dead, 0);
+}
+
+#[test]
+fn stats_after_minor_collection() {
+    let mut gc = manual_gc();
+    gc.set_workload(GcWorkload {
+        nursery_size: 100,
+        ..manual_workload()
+    });
+
+    let kept = node(&mut gc, 0, Vec::new());
+    let _root = gc.root(kept).unwrap();
+    for i in 1..=10 {
+        let _ = node(&mut gc, i, Vec::new());
+    }
+    assert_eq!(gc.stats().young, 11);
+
+    assert_eq!(gc.collect_minor(), 1);
+    let stats = gc.stats();
+    assert_eq!(stats.minor_collections, 1);
+    assert_eq!(stats.promotions, 1);
+    assert_eq!(stats.young, 0);
+    assert_eq!(stats.live(), 1);
+    assert_eq!(stats.dead, 10);
+}
+
+#[test]
+fn gc_when_no_dead_with_nursery() {
+    for gc_when_no_dead in [false, true] {
+        let mut gc = manual_gc();
+        gc.set_collect_on_alloc(true);
+        gc.set_workload(GcWorkload {
+            gc_when_no_dead,
+            nursery_size: 100,
+            ..manual_workload()
+        });
+
+        // nothing dies before the nursery is full, so only the flag runs increments
+        for i in 0..10 {
+            let _ = node(&mut gc, i, Vec::new());
+        }
+        let stats = gc.stats();
+        assert_eq!(stats.minor_collections, 0);
+        assert_eq!(stats.increments, if gc_when_no_dead {10} else {0});
+    }
+}
+
+#[test]
+fn per_frame_garbage_is_collected_by_minor_collections() {
+    let mut gc = manual_gc();
+    gc.set_workload(GcWorkload {
+        nursery_size: 1000,
+        ..manual_workload()
+    });
+    let holder = node(&mut gc, -1, Vec::new());
+    let mut holder = gc.root(holder).unwrap();
+
+    // each frame makes a lot of garbage and keeps one object, then collects the nursery
+    for frame in 0..100 {
+        for i in 0..50 {
+            let _ = node(&mut gc, i, Vec::new());
+        }
+        let kept = node(&mut gc, frame, Vec::new());
+        push_child(&mut gc, &mut holder, kept);
+        gc.collect_minor();
+    }
+
+    // the garbage never reached the old space, and its slots were reused by the next frames
+    let stats = gc.stats();
+    assert_eq!(stats.cycles, 0);
+    assert_eq!(stats.increments, 0);
+    assert_eq!(stats.promotions, 101);
+    assert_eq!(stats.live(), 101);
+    assert_eq!(stats.dead, 50);
+    assert!(stats.allocations <= 101 + 50, "{stats:?}");
+}
+
+/// A GC with a rooted node referencing `count` nodes. A full collection has run, so every object
+/// is white at the start of the next cycle.
+fn wide_heap(count: i64)->(TestGc, RootDataRef<TestBundle>) {
+    let mut gc = manual_gc();
+    let children = (0..count)
+        .map(|i|node(&mut gc, i, Vec::new()))
+        .collect();
+    let root = node(&mut gc, -1, children);
+    let root = gc.root(root).unwrap();
+    gc.collect_full();
+
+    return (gc, root);
+}
+
+#[test]
+fn increments_respect_the_workload() {
+    let (mut gc, _root) = wide_heap(1000);
+    gc.set_workload(GcWorkload {
+        traces: 10,
+        ..manual_workload()
+    });
+
+    // marking the roots shades the root node, then each increment traces a few objects
+    gc.gc_inc();
+    assert_eq!(gc.stats().state, GcState::Trace);
+    gc.gc_inc();
+    let stats = gc.stats();
+    assert!(stats.black <= 10, "{stats:?}");
+    gc.gc_inc();
+    let stats = gc.stats();
+    assert!(stats.black <= 20, "{stats:?}");
+    assert!(stats.grey > 900, "{stats:?}");
+}
+
+#[test]
+fn step_for_respects_the_deadline() {
+    let (mut gc, _root) = wide_heap(20_000);
+    gc.set_workload(GcWorkload {
+        traces: usize::MAX,
+        ..manual_workload()
+    });
+
+    // without any budget a step stops after the first object
+    let cycles = gc.stats().cycles;
+    assert_eq!(gc.step_for(Duration::ZERO), 1);
+    assert_eq!(gc.step_for(Duration::ZERO), 1);
+    let stats = gc.stats();
+    assert_eq!(stats.state, GcState::Trace);
+    assert!(stats.grey > 19_000, "{stats:?}");
+    assert_eq!(stats.cycles, cycles);
+
+    // a big enough budget finishes the cycle
+    gc.step_for(Duration::from_secs(60));
+    let stats = gc.stats();
+    assert_eq!(stats.state, GcState::MarkRoots);
+    assert_eq!(stats.cycles, cycles + 1);
+    assert_eq!(stats.live(), 20_001);
+}
+
+#[test]
+fn write_barrier_keeps_stored_objects_alive() {
+    let mut gc = manual_gc();
+    gc.set_workload(GcWorkload {
+        traces: 1,
+        ..manual_workload()
+    });
+
+    let children = (0..100)
+        .map(|i|node(&mut gc, i, Vec::new()))
+        .collect();
+    let holder = node(&mut gc, -1, children);
+    let mut holder = gc.root(holder).unwrap();
+    let (white, dropped) = tracked_node(&mut gc, 1000);
+    let white_root = gc.root(white.clone()).unwrap();
+
+    // both survive, and start the next cycle white
+    gc.collect_full();
+    gc.unroot(white_root);
+
+    // mark the roots, then trace the holder so it is black while most of its children are grey
+    gc.gc_inc();
+    gc.gc_inc();
+    assert_eq!(gc.stats().state, GcState::Trace);
+
+    // the only reference to the white object is now in a black one
+    push_child(&mut gc, &mut holder, white.clone());
+    while gc.stats().state != GcState::MarkRoots {
+        gc.gc_inc();
+    }
+    gc.free_dead();
+    assert!(!dropped.get());
+    assert_eq!(value(&white), 1000);
+
+    gc.collect_full();
+    gc.free_dead();
+    assert!(!dropped.get());
+    assert_eq!(live(&gc), 102);
+
+    // once it isn't referenced, it is collected
+    node_mut(&mut holder).children.pop();
+    gc.collect_full();
+    gc.free_dead();
+    assert!(dropped.get());
+}
+
+#[test]
+fn gcs_have_independent_workloads() {
+    let mut a = manual_gc();
+    let mut b = manual_gc();
+    a.set_workload(GcWorkload {
+        traces: 5,
+        ..manual_workload()
+    });
+    assert_eq!(a.workload().traces, 5);
+    assert_eq!(b.workload().traces, 100);
+
+    // the script object is bound to the GC it was made with
+    let mut interner = Interner::default();
+    let traces = interner.intern("traces");
+    let mut b_object = GcWorkloadObject::new(&mut interner, &b);
+    b_object.set(traces, Primitive::Number(7), &interner).unwrap();
+    assert_eq!(a.workload().traces, 5);
+    assert_eq!(b.workload().traces, 7);
+
+    // and so are the stats
+    let kept = node(&mut a, 0, Vec::new());
+    let _root = a.root(kept).unwrap();
+    for i in 0..10 {
+        let _ = node(&mut b, i, Vec::new());
+    }
+    a.collect_full();
+    assert_eq!(a.stats().live(), 1);
+    assert_eq!(a.stats().allocations, 1);
+    assert_eq!(b.stats().live(), 10);
+    assert_eq!(b.stats().cycles, 0);
+
+    let live = interner.intern("live");
+    assert!(matches!(b_object.get(live, &interner), Ok(Primitive::Number(10))));
+}
@@ -0,0 +1,71 @@
+//! Tests for heap snapshots and their diffs.
+#![cfg(not(feature = "gc_stress"))]
+
+
+mod common;
+
+use eka_interp_treewalk::snapshot::HeapSnapshot;
+use common::*;
+
+
+#[test]
+fn snapshot_of_known_heap() {
+    let mut gc = manual_gc();
+    let child = node(&mut gc, 1, Vec::new());
+    let holder = node(&mut gc, 0, vec![child.clone()]);
+    let holder_addr = holder.addr();
+    let _root = gc.root(holder).unwrap();
+    let _ = node(&mut gc, 2, Vec::new());
+    gc.collect_full();
+    gc.free_dead();
+
+    let snapshot = HeapSnapshot::new(&mut gc);
+    assert_eq!(snapshot.roots, [holder_addr]);
+    assert_eq!(snapshot.objects.len(), 2);
+
+    let holder_obj = snapshot.objects.iter().find(|o|o.addr == holder_addr).unwrap();
+    assert_eq!(holder_obj.variant, "Node");
+    assert_eq!(holder_obj.type_name.as_deref(), Some("Node"));
+    assert_eq!(holder_obj.refs, [child.addr()]);
+
+    let child_obj = snapshot.objects.iter().find(|o|o.addr == child.addr()).unwrap();
+    assert!(child_obj.refs.is_empty());
+    assert_eq!(child_obj.reached_by, [holder_addr]);
+}
+
+#[test]
+fn diff_of_known_heap() {
+    let mut gc = manual_gc();
+    let old_child = node(&mut gc, 1, Vec::new());
+    let old_addr = old_child.addr();
+    let holder = node(&mut gc, 0, vec![old_child]);
+    let mut holder = gc.root(holder).unwrap();
+    gc.collect_full();
+    gc.free_dead();
+    let before = HeapSnapshot::new(&mut gc);
+
+    // replace the child, so one object is added and one is removed
+    let new_child = node(&mut gc, 2, Vec::new());
+    node_mut(&mut holder).children = vec![new_child.clone()];
+    gc.write_barrier(&holder.data_ref());
+    gc.collect_full();
+    gc.free_dead();
+    let after = HeapSnapshot::new(&mut gc);
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.added, [new_child.addr()]);
+    assert_eq!(diff.removed, [old_addr]);
+    assert!(diff.counts.is_empty());
+    assert_eq!(diff.to_string(), "+1 -1 objects\n");
+
+    // growing the heap changes the counts
+    let extra = node(&mut gc, 3, Vec::new());
+    push_child(&mut gc, &mut holder, extra);
+    let grown = HeapSnapshot::new(&mut gc);
+
+    let diff = after.diff(&grown);
+    assert_eq!(diff.added.len(), 1);
+    assert!(diff.removed.is_empty());
+    assert_eq!(diff.counts, [("Node", 2, 3)]);
+    assert_eq!(diff.to_string(), "+1 -0 objects\n    Node: 2 -> 3 (+1)\n");
+}
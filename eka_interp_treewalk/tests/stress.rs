@@ -0,0 +1,67 @@
+//! Tests for the `gc_stress` feature. They only build with it enabled:
+//!
+//! ```text
+//! cargo test -p eka_interp_treewalk --features gc_stress
+//! ```
+#![cfg(feature = "gc_stress")]
+
+
+mod common;
+
+use common::*;
+
+
+#[test]
+fn every_alloc_collects() {
+    let mut gc = manual_gc();
+    let (_, dropped) = tracked_node(&mut gc, 1);
+    let cycles = gc.stats().cycles;
+
+    // the unreferenced node is found dead by the next allocation, which recycles it
+    let _ = node(&mut gc, 2, Vec::new());
+    let stats = gc.stats();
+    assert!(dropped.get());
+    assert_eq!(stats.recycles, 1);
+    assert!(stats.cycles > cycles);
+}
+
+#[test]
+fn rooted_graph_survives() {
+    let mut gc = manual_gc();
+    let holder = node(&mut gc, -1, Vec::new());
+    let mut holder = gc.root(holder).unwrap();
+
+    // every allocation and mutation happens in the middle of a collection
+    for i in 0..200 {
+        let child = node(&mut gc, i, Vec::new());
+        push_child(&mut gc, &mut holder, child);
+        let _ = node(&mut gc, -1, Vec::new());
+        if i % 3 == 0 {
+            let _ = node_mut(&mut holder).children.remove(0);
+        }
+    }
+    gc.verify();
+
+    let values = node_mut(&mut holder).children
+        .iter()
+        .map(|c|value(c))
+        .collect::<Vec<_>>();
+    assert_eq!(values.len(), 133);
+    assert!(values.iter().all(|v|*v >= 0));
+
+    gc.collect_full();
+    gc.free_dead();
+    gc.verify();
+    assert_eq!(live(&gc), 134);
+}
+
+#[test]
+#[should_panic(expected = "Use of a freed object")]
+fn use_after_free_panics() {
+    let mut gc = manual_gc();
+    let dr = node(&mut gc, 1, Vec::new());
+    gc.collect_full();
+    gc.free_dead();
+
+    value(&dr);
+}
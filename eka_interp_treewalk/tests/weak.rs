@@ -0,0 +1,70 @@
+//! Tests for weak references.
+
+
+mod common;
+
+#[cfg(not(feature = "gc_stress"))]
+use eka_interp_treewalk::data::GcState;
+use common::*;
+
+
+/// Run increments until the GC reaches `state`.
+#[cfg(not(feature = "gc_stress"))]
+fn step_until(gc: &mut TestGc, state: GcState) {
+    while gc.stats().state != state {
+        gc.gc_inc();
+    }
+}
+
+
+#[test]
+fn weak_refs_are_cleared() {
+    let mut gc = manual_gc();
+    let dead = node(&mut gc, 1, Vec::new());
+    let weak = gc.downgrade(&dead);
+    let alive = node(&mut gc, 2, Vec::new());
+    let alive_weak = gc.downgrade(&alive);
+    let _root = gc.root(alive).unwrap();
+
+    gc.collect_full();
+    assert!(!weak.is_alive());
+    assert!(weak.upgrade(&mut gc).is_none());
+
+    let alive = alive_weak.upgrade(&mut gc).unwrap();
+    assert_eq!(value(&alive), 2);
+}
+
+// stress mode collects the garbage as it is allocated, so the sweep is never long
+#[test]
+#[cfg(not(feature = "gc_stress"))]
+fn upgrade_during_sweep() {
+    let mut gc = manual_gc();
+    let child = node(&mut gc, 1, Vec::new());
+    let parent = node(&mut gc, 2, vec![child]);
+    let weak = gc.downgrade(&parent);
+    let alive = node(&mut gc, 3, Vec::new());
+    let alive_weak = gc.downgrade(&alive);
+    let _root = gc.root(alive).unwrap();
+
+    // enough garbage that the sweep takes many increments
+    for i in 0..100 {
+        let _ = node(&mut gc, i, Vec::new());
+    }
+
+    // new objects start grey, so they only become white in the next cycle
+    step_until(&mut gc, GcState::MarkDead);
+    step_until(&mut gc, GcState::MarkRoots);
+    step_until(&mut gc, GcState::MarkDead);
+
+    // the parent is dead from the start of the sweep, even though it hasn't been freed yet
+    assert!(weak.upgrade(&mut gc).is_none());
+    gc.gc_inc();
+    assert_eq!(gc.stats().state, GcState::MarkDead);
+    assert!(weak.upgrade(&mut gc).is_none());
+    assert!(alive_weak.upgrade(&mut gc).is_some());
+
+    step_until(&mut gc, GcState::MarkRoots);
+    gc.free_dead();
+    assert_eq!(live(&gc), 1);
+    assert!(alive_weak.is_alive());
+}
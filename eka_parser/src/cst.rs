@@ -0,0 +1,327 @@
+//! A lossless concrete syntax tree. Every byte of the source is in exactly one token, including
+//! whitespace and comments, so [`CstNode::text`] gives back the exact source. Brackets become
+//! nodes, and everything else is a token in the node of the innermost bracket around it.
+//!
+//! The tree is built even for broken source: unclosed brackets end at the end of the source, stray
+//! closing brackets are tokens in the node they appear in, and source the lexer rejects becomes
+//! [`CstTokenKind::Error`] tokens. The [`Parser`](crate::Parser) lowers the tree into `Expr`s, and
+//! tools like formatters can use it directly.
+
+
+use logos::Logos;
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+use crate::lexer::*;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstTokenKind<'a> {
+    Whitespace,
+    /// A comment, including the `;`
+    Comment,
+    /// Source the lexer could not make a token from
+    Error(LexerError),
+    Token(Token<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstToken<'a> {
+    pub kind: CstTokenKind<'a>,
+    pub text: &'a str,
+    /// The byte offset of the token in the source
+    pub offset: usize,
+}
+impl<'a> CstToken<'a> {
+    /// Whitespace and comments
+    #[inline]
+    pub fn is_trivia(&self)->bool {
+        matches!(self.kind, CstTokenKind::Whitespace|CstTokenKind::Comment)
+    }
+
+    #[inline]
+    pub fn end(&self)->usize {
+        self.offset + self.text.len()
+    }
+
+    /// The text of the comment after `;;;` if it is a doc comment.
+    pub fn doc_text(&self)->Option<&'a str> {
+        match self.kind {
+            CstTokenKind::Comment=>{
+                let line = self.text.strip_prefix(";;;")?;
+                Some(line.strip_prefix(' ').unwrap_or(line).trim_end())
+            },
+            _=>None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole source
+    Root,
+    /// `(...)`
+    Paren,
+    /// `[...]`
+    Vector,
+    /// `{...}`
+    Squiggle,
+}
+impl NodeKind {
+    fn open(tok: &Token)->Option<Self> {
+        match tok {
+            Token::Paren(Start)=>Some(NodeKind::Paren),
+            Token::Vector(Start)=>Some(NodeKind::Vector),
+            Token::Squiggle(Start)=>Some(NodeKind::Squiggle),
+            _=>None,
+        }
+    }
+
+    fn close(tok: &Token)->Option<Self> {
+        match tok {
+            Token::Paren(End)=>Some(NodeKind::Paren),
+            Token::Vector(End)=>Some(NodeKind::Vector),
+            Token::Squiggle(End)=>Some(NodeKind::Squiggle),
+            _=>None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstElement<'a> {
+    Node(CstNode<'a>),
+    Token(CstToken<'a>),
+}
+impl<'a> CstElement<'a> {
+    #[inline]
+    pub fn is_trivia(&self)->bool {
+        match self {
+            CstElement::Node(_)=>false,
+            CstElement::Token(tok)=>tok.is_trivia(),
+        }
+    }
+
+    pub fn start(&self)->usize {
+        match self {
+            CstElement::Node(node)=>node.start(),
+            CstElement::Token(tok)=>tok.offset,
+        }
+    }
+
+    pub fn end(&self)->usize {
+        match self {
+            CstElement::Node(node)=>node.end(),
+            CstElement::Token(tok)=>tok.end(),
+        }
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            CstElement::Node(node)=>node.write_text(out),
+            CstElement::Token(tok)=>out.push_str(tok.text),
+        }
+    }
+}
+
+/// A bracketed form, or the root of the tree. The brackets are the first and last children.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode<'a> {
+    pub kind: NodeKind,
+    pub children: Vec<CstElement<'a>>,
+}
+impl<'a> CstNode<'a> {
+    /// Build the tree for the source. This never fails, see the [module docs](self).
+    pub fn parse(source: &'a str)->CstNode<'a> {
+        let mut lexer = Token::lexer_with_extras(source, LexerOptions {keep_comments: true});
+        let mut stack = vec![CstNode {kind: NodeKind::Root, children: Vec::new()}];
+        let mut last_end = 0;
+
+        let push = |stack: &mut Vec<CstNode<'a>>, elem|stack.last_mut().unwrap().children.push(elem);
+
+        while let Some(res) = lexer.next() {
+            let span = lexer.span();
+            if span.start > last_end {
+                push(&mut stack, CstElement::Token(CstToken {
+                    kind: CstTokenKind::Whitespace,
+                    text: &source[last_end..span.start],
+                    offset: last_end,
+                }));
+            }
+            last_end = span.end;
+
+            let kind = match res {
+                Ok(Token::Comment(_))=>CstTokenKind::Comment,
+                Ok(tok)=>CstTokenKind::Token(tok),
+                Err(e)=>CstTokenKind::Error(e),
+            };
+            let tok = CstToken {
+                kind,
+                text: lexer.slice(),
+                offset: span.start,
+            };
+
+            let CstTokenKind::Token(inner) = &tok.kind else {
+                push(&mut stack, CstElement::Token(tok));
+                continue;
+            };
+
+            if let Some(kind) = NodeKind::open(inner) {
+                stack.push(CstNode {
+                    kind,
+                    children: vec![CstElement::Token(tok)],
+                });
+            } else if NodeKind::close(inner).is_some_and(|k|k == stack.last().unwrap().kind) {
+                let mut node = stack.pop().unwrap();
+                node.children.push(CstElement::Token(tok));
+                push(&mut stack, CstElement::Node(node));
+            } else {
+                // stray closing brackets stay where they are
+                push(&mut stack, CstElement::Token(tok));
+            }
+        }
+
+        if last_end < source.len() {
+            push(&mut stack, CstElement::Token(CstToken {
+                kind: CstTokenKind::Whitespace,
+                text: &source[last_end..],
+                offset: last_end,
+            }));
+        }
+
+        // unclosed nodes end at the end of the source
+        while stack.len() > 1 {
+            let node = stack.pop().unwrap();
+            push(&mut stack, CstElement::Node(node));
+        }
+
+        return stack.pop().unwrap();
+    }
+
+    /// The exact source of the node.
+    pub fn text(&self)->String {
+        let mut out = String::new();
+        self.write_text(&mut out);
+        return out;
+    }
+
+    fn write_text(&self, out: &mut String) {
+        for child in self.children.iter() {
+            child.write_text(out);
+        }
+    }
+
+    pub fn start(&self)->usize {
+        self.children.first().map(|c|c.start()).unwrap_or(0)
+    }
+
+    pub fn end(&self)->usize {
+        self.children.last().map(|c|c.end()).unwrap_or(0)
+    }
+
+    /// The opening bracket. Only the root doesn't have one.
+    pub fn open(&self)->Option<&CstToken<'a>> {
+        if self.kind == NodeKind::Root {
+            return None;
+        }
+        match self.children.first() {
+            Some(CstElement::Token(tok))=>Some(tok),
+            _=>None,
+        }
+    }
+
+    /// The closing bracket, or `None` if the node is never closed.
+    pub fn close(&self)->Option<&CstToken<'a>> {
+        if self.kind == NodeKind::Root || self.children.len() < 2 {
+            return None;
+        }
+        match self.children.last() {
+            Some(CstElement::Token(tok @ CstToken {kind: CstTokenKind::Token(t), ..}))
+                if NodeKind::close(t) == Some(self.kind)=>Some(tok),
+            _=>None,
+        }
+    }
+
+    /// The children that aren't trivia or the brackets of this node.
+    pub fn items(&self)->impl Iterator<Item = &CstElement<'a>> {
+        let skip = self.open().is_some() as usize;
+        let take = self.children.len() - skip - self.close().is_some() as usize;
+
+        self.children.iter()
+            .skip(skip)
+            .take(take)
+            .filter(|c|!c.is_trivia())
+    }
+
+    /// Every token in the tree in source order.
+    pub fn tokens(&self)->Vec<&CstToken<'a>> {
+        let mut out = Vec::new();
+        self.collect_tokens(&mut out);
+        return out;
+    }
+
+    fn collect_tokens<'b>(&'b self, out: &mut Vec<&'b CstToken<'a>>) {
+        for child in self.children.iter() {
+            match child {
+                CstElement::Node(node)=>node.collect_tokens(out),
+                CstElement::Token(tok)=>out.push(tok),
+            }
+        }
+    }
+
+    /// The lexer errors in the tree with their byte ranges.
+    pub fn errors(&self)->Vec<(LexerError, usize, usize)> {
+        self.tokens()
+            .into_iter()
+            .filter_map(|tok|match &tok.kind {
+                CstTokenKind::Error(e)=>Some((e.clone(), tok.offset, tok.end())),
+                _=>None,
+            })
+            .collect()
+    }
+
+    /// Find the doc comments. A doc comment is a run of lines starting with `;;;`, and it documents
+    /// the token right after it. Returns the doc text keyed by the byte offset of that token.
+    pub fn doc_comments(&self)->FxHashMap<usize, String> {
+        let mut docs = FxHashMap::default();
+        let mut lines = Vec::new();
+
+        for tok in self.tokens() {
+            match (&tok.kind, tok.doc_text()) {
+                (CstTokenKind::Whitespace, _)=>{},
+                (_, Some(line))=>lines.push(line),
+                // a normal comment between the doc comment and the item ends the doc comment
+                (CstTokenKind::Comment, None)=>lines.clear(),
+                _=>if !lines.is_empty() {
+                    docs.insert(tok.offset, lines.join("\n"));
+                    lines.clear();
+                },
+            }
+        }
+
+        return docs;
+    }
+
+    /// Print the tree with one element per line, for debugging and tests.
+    pub fn debug_tree(&self)->String {
+        let mut out = String::new();
+        self.write_debug(&mut out, 0);
+        return out;
+    }
+
+    fn write_debug(&self, out: &mut String, depth: usize) {
+        writeln!(out, "{}{:?}@{}..{}", "  ".repeat(depth), self.kind, self.start(), self.end()).unwrap();
+        for child in self.children.iter() {
+            match child {
+                CstElement::Node(node)=>node.write_debug(out, depth + 1),
+                CstElement::Token(tok)=>{
+                    let kind = match &tok.kind {
+                        CstTokenKind::Whitespace=>"Whitespace".to_string(),
+                        CstTokenKind::Comment=>"Comment".to_string(),
+                        CstTokenKind::Error(e)=>format!("Error({e})"),
+                        CstTokenKind::Token(t)=>format!("{t:?}"),
+                    };
+                    writeln!(out, "{}{kind}@{}..{} {:?}", "  ".repeat(depth + 1), tok.offset, tok.end(), tok.text).unwrap();
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Pretty-prints source into a canonical layout. The formatter works on the [`CstNode`], so
+//! comments are kept where they were written.
+//!
+//! `defn`, `begin`, and `cond` keep their head on the first line and put each part of their body
+//! on its own line, indented once. Other forms are printed on one line if they fit in the width
+//! and have no comments in them. Otherwise they put each argument on its own line, indented once,
+//! or fill the lines with as many arguments as fit if they are all tokens. Vectors and maps that
+//! don't fit put each item on its own line, aligned after the bracket.
+//!
+//! Quotes are always printed right before what they quote.
+//!
+//! Blank lines between forms are kept, but a run of them becomes one. Strings are rewritten with
+//! canonical escapes. Raw strings and multi-line strings are kept as they are written, because
+//! their layout is part of their text.
+
+
+use anyhow::Result;
+use std::{
+    borrow::Cow,
+    mem,
+};
+use eka_core::source::SourceMap;
+use crate::{
+    lexer::*,
+    cst::*,
+    Parser,
+    ParserData,
+};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    /// The column forms should fit in
+    pub width: usize,
+    /// The spaces to indent bodies and broken argument lists by
+    pub indent: usize,
+}
+impl Default for FormatOptions {
+    fn default()->Self {
+        FormatOptions {
+            width: 100,
+            indent: 4,
+        }
+    }
+}
+
+
+/// Format the source. Only source that parses can be formatted. The syntax errors are returned as
+/// diagnostics, and the source is added to `sources` as `name` so they can be rendered.
+///
+/// Formatting is idempotent: formatting the output again gives the same output.
+pub fn format_source(name: &str, source: &str, sources: &mut SourceMap, options: &FormatOptions)->Result<String> {
+    let data = ParserData {
+        sources: mem::take(sources),
+        any_object_type: true,
+        ..ParserData::default()
+    };
+    let mut parser = Parser::new_with_file(name, source, data);
+    let res = parser.parse();
+    let formatted = format_cst(parser.cst(), options);
+    *sources = parser.finish().sources;
+
+    res?;
+
+    return Ok(formatted);
+}
+
+/// Format a syntax tree without checking that it parses. Broken trees are formatted as well as
+/// their brackets allow.
+pub fn format_cst(root: &CstNode, options: &FormatOptions)->String {
+    Formatter {options}.root(root)
+}
+
+/// Rewrite the text of a string with canonical escapes. Interpolations are kept as they are.
+pub fn normalize_string(parts: &[StringPart])->String {
+    let mut out = String::from("\"");
+    for part in parts {
+        match part {
+            StringPart::Literal(text)=>{
+                let mut chars = text.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\'=>out.push_str("\\\\"),
+                        '"'=>out.push_str("\\\""),
+                        '\n'=>out.push_str("\\n"),
+                        '\t'=>out.push_str("\\t"),
+                        '\r'=>out.push_str("\\r"),
+                        '\0'=>out.push_str("\\0"),
+                        // only `${` starts an interpolation
+                        '$' if chars.peek() == Some(&'{')=>out.push_str("\\$"),
+                        c if c.is_control()=>out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                        c=>out.push(c),
+                    }
+                }
+            },
+            StringPart::Expr{source,..}=>{
+                out.push_str("${");
+                out.push_str(source);
+                out.push('}');
+            },
+        }
+    }
+    out.push('"');
+
+    return out;
+}
+
+
+/// An item or comment in a node, with the layout we keep from the source.
+enum Entry<'b, 'a> {
+    Item {
+        elem: &'b CstElement<'a>,
+        /// The count of `'` before the item. They are always printed right before it.
+        quotes: usize,
+        /// A comment after the item on the same line
+        comment: Option<&'a str>,
+        blank_before: bool,
+    },
+    /// A comment on its own line
+    Comment {
+        text: &'a str,
+        blank_before: bool,
+    },
+}
+impl<'b, 'a> Entry<'b, 'a> {
+    fn blank_before(&self)->bool {
+        match self {
+            Entry::Item{blank_before,..}|Entry::Comment{blank_before,..}=>*blank_before,
+        }
+    }
+
+    fn has_comment(&self)->bool {
+        !matches!(self, Entry::Item{comment: None, ..})
+    }
+}
+
+fn entries<'b, 'a>(node: &'b CstNode<'a>)->Vec<Entry<'b, 'a>> {
+    let skip = node.open().is_some() as usize;
+    let take = node.children.len() - skip - node.close().is_some() as usize;
+
+    let mut out = Vec::new();
+    let mut newlines = 0;
+    // the first quote waiting for the item it quotes, the count of quotes, and `blank_before`
+    let mut quotes = None;
+    for child in node.children.iter().skip(skip).take(take) {
+        let blank_before = newlines > 1 && !out.is_empty();
+        match child {
+            CstElement::Token(CstToken {kind: CstTokenKind::Whitespace, text, ..})=>{
+                newlines += text.matches('\n').count();
+                continue;
+            },
+            CstElement::Token(CstToken {kind: CstTokenKind::Comment, text, ..})=>{
+                push_quotes(&mut out, quotes.take());
+                let text = text.trim_end();
+                match out.last_mut() {
+                    Some(Entry::Item {comment: comment @ None, ..}) if newlines == 0=>*comment = Some(text),
+                    _=>out.push(Entry::Comment {text, blank_before}),
+                }
+            },
+            elem if is_quote(elem)=>match &mut quotes {
+                Some((_, count, _))=>*count += 1,
+                None=>quotes = Some((elem, 1, blank_before)),
+            },
+            elem=>{
+                let (quotes, blank_before) = match quotes.take() {
+                    Some((_, count, blank))=>(count, blank),
+                    None=>(0, blank_before),
+                };
+                out.push(Entry::Item {elem, quotes, comment: None, blank_before});
+            },
+        }
+        newlines = 0;
+    }
+    push_quotes(&mut out, quotes);
+
+    return out;
+}
+
+/// Quotes without an item after them are kept as the items.
+fn push_quotes<'b, 'a>(out: &mut Vec<Entry<'b, 'a>>, quotes: Option<(&'b CstElement<'a>, usize, bool)>) {
+    if let Some((elem, count, blank_before)) = quotes {
+        out.push(Entry::Item {elem, quotes: count - 1, comment: None, blank_before});
+    }
+}
+
+fn width(text: &str)->usize {
+    text.chars().count()
+}
+
+/// The column after the last line of `text` if it starts at `col`.
+fn end_col(text: &str, col: usize)->usize {
+    match text.rfind('\n') {
+        Some(i)=>width(&text[i + 1..]),
+        None=>col + width(text),
+    }
+}
+
+fn token_text<'a>(tok: &CstToken<'a>)->Cow<'a, str> {
+    match &tok.kind {
+        CstTokenKind::Token(Token::String(parts)) if tok.text.starts_with('"') && !tok.text.starts_with("\"\"\"")=>{
+            normalize_string(parts).into()
+        },
+        _=>tok.text.into(),
+    }
+}
+
+fn is_quote(elem: &CstElement)->bool {
+    matches!(elem, CstElement::Token(CstToken {kind: CstTokenKind::Token(Token::Quote), ..}))
+}
+
+fn ident<'a>(elem: &CstElement<'a>)->Option<&'a str> {
+    match elem {
+        CstElement::Token(CstToken {kind: CstTokenKind::Token(Token::Ident(name)), ..})=>Some(name),
+        _=>None,
+    }
+}
+
+
+struct Formatter<'o> {
+    options: &'o FormatOptions,
+}
+impl<'o> Formatter<'o> {
+    fn root(&self, root: &CstNode)->String {
+        let mut out = String::new();
+        for entry in entries(root) {
+            if !out.is_empty() {
+                out.push('\n');
+                if entry.blank_before() {
+                    out.push('\n');
+                }
+            }
+            self.entry(&mut out, &entry, 0);
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+
+        return out;
+    }
+
+    /// Print an entry that starts at `col`.
+    fn entry(&self, out: &mut String, entry: &Entry, col: usize) {
+        match entry {
+            Entry::Item{elem, quotes, comment,..}=>{
+                out.push_str(&"'".repeat(*quotes));
+                out.push_str(&self.elem(elem, col + quotes));
+                if let Some(comment) = comment {
+                    out.push(' ');
+                    out.push_str(comment);
+                }
+            },
+            Entry::Comment{text,..}=>out.push_str(text),
+        }
+    }
+
+    fn elem(&self, elem: &CstElement, col: usize)->String {
+        match elem {
+            CstElement::Node(node)=>self.node(node, col),
+            CstElement::Token(tok)=>token_text(tok).into_owned(),
+        }
+    }
+
+    /// The element on one line, or `None` if it has comments or multi-line tokens.
+    fn flat(&self, elem: &CstElement)->Option<String> {
+        match elem {
+            CstElement::Token(tok)=>{
+                let text = token_text(tok);
+                (!text.contains('\n')).then(||text.into_owned())
+            },
+            CstElement::Node(node)=>self.flat_node(node),
+        }
+    }
+
+    /// An item with its quotes on one line, or `None` for comments and items with them.
+    fn flat_entry(&self, entry: &Entry)->Option<String> {
+        let Entry::Item{elem, quotes, comment: None, ..} = entry else {
+            return None;
+        };
+
+        return Some("'".repeat(*quotes) + &self.flat(elem)?);
+    }
+
+    fn flat_node(&self, node: &CstNode)->Option<String> {
+        if node.kind == NodeKind::Paren && node.items().next().and_then(ident).is_some_and(is_body_form) {
+            return None;
+        }
+
+        let mut out = node.open().map(|t|t.text).unwrap_or("").to_string();
+        for (i, entry) in entries(node).iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(&self.flat_entry(entry)?);
+        }
+        out.push_str(node.close().map(|t|t.text).unwrap_or(""));
+
+        return Some(out);
+    }
+
+    fn node(&self, node: &CstNode, col: usize)->String {
+        if let Some(flat) = self.flat_node(node) {
+            if col + width(&flat) <= self.options.width {
+                return flat;
+            }
+        }
+
+        let entries = entries(node);
+        let indent = self.options.indent;
+        match node.kind {
+            NodeKind::Paren=>match node.items().next().and_then(ident) {
+                Some("defn")=>self.block(node, &entries, defn_header(&entries), col, col + indent),
+                Some("cond")=>self.cond(node, &entries, col),
+                Some("def"|"set"|"module")=>self.block(node, &entries, 2, col, col + indent),
+                // a comment on the head would end up before the arguments
+                _ if entries.first().is_some_and(|e|!e.has_comment()) && is_atoms(&entries[1..])=>{
+                    self.fill(node, &entries, col)
+                },
+                _=>self.block(node, &entries, 1, col, col + indent),
+            },
+            // items after the first are aligned with it
+            _=>self.block(node, &entries, 1, col, col + 1),
+        }
+    }
+
+    /// Print up to `header` entries after the open bracket, then each of the rest on its own line
+    /// at `body_col`.
+    fn block(&self, node: &CstNode, entries: &[Entry], header: usize, col: usize, body_col: usize)->String {
+        let mut out = node.open().map(|t|t.text).unwrap_or("").to_string();
+        let (header, body) = self.header(&mut out, entries, header, col);
+
+        let mut ends_in_comment = header.last().is_some_and(Entry::has_comment);
+        for entry in body {
+            self.new_line(&mut out, entry.blank_before(), body_col);
+            self.entry(&mut out, entry, body_col);
+            ends_in_comment = entry.has_comment();
+        }
+
+        self.close(&mut out, node, ends_in_comment, col);
+
+        return out;
+    }
+
+    /// Fill the lines after the head with as many arguments as fit. Only used when the arguments
+    /// are all tokens, since a list of numbers one per line is hard to read.
+    fn fill(&self, node: &CstNode, entries: &[Entry], col: usize)->String {
+        let body_col = col + self.options.indent;
+        let mut out = node.open().map(|t|t.text).unwrap_or("").to_string();
+        let (_, body) = self.header(&mut out, entries, 1, col);
+
+        let mut line_col = end_col(&out, col);
+        for entry in body {
+            let text = self.flat_entry(entry).expect("Fill is only used for tokens without comments");
+            if line_col + 1 + width(&text) > self.options.width {
+                self.new_line(&mut out, false, body_col);
+                line_col = body_col;
+            } else {
+                out.push(' ');
+                line_col += 1;
+            }
+            out.push_str(&text);
+            line_col += width(&text);
+        }
+
+        self.close(&mut out, node, false, col);
+
+        return out;
+    }
+
+    /// `cond` puts each branch on its own line, with the body after the condition if it fits.
+    /// Otherwise the body goes on the next line, indented again.
+    fn cond(&self, node: &CstNode, entries: &[Entry], col: usize)->String {
+        let body_col = col + self.options.indent;
+        let mut out = node.open().map(|t|t.text).unwrap_or("").to_string();
+        let (_, body) = self.header(&mut out, entries, 1, col);
+
+        let mut ends_in_comment = false;
+        let mut i = 0;
+        while i < body.len() {
+            let entry = &body[i];
+            self.new_line(&mut out, entry.blank_before(), body_col);
+            self.entry(&mut out, entry, body_col);
+            ends_in_comment = entry.has_comment();
+            i += 1;
+
+            // the body of the branch
+            let (Entry::Item{comment: None, ..}, Some(next @ Entry::Item{elem, quotes, comment, ..})) = (entry, body.get(i)) else {
+                continue;
+            };
+            if next.blank_before() {
+                continue;
+            }
+            let col = end_col(&out, 0);
+            match self.flat(elem).map(|flat|"'".repeat(*quotes) + &flat) {
+                Some(flat) if col + 1 + width(&flat) <= self.options.width=>{
+                    out.push(' ');
+                    out.push_str(&flat);
+                    if let Some(comment) = comment {
+                        out.push(' ');
+                        out.push_str(comment);
+                    }
+                },
+                _=>{
+                    self.new_line(&mut out, false, body_col + self.options.indent);
+                    self.entry(&mut out, next, body_col + self.options.indent);
+                },
+            }
+            ends_in_comment = next.has_comment();
+            i += 1;
+        }
+
+        self.close(&mut out, node, ends_in_comment, col);
+
+        return out;
+    }
+
+    /// Print the first `count` entries on the line of the open bracket. Comments end the header
+    /// early. Returns the header and the rest of the entries.
+    fn header<'e, 'b, 'a>(&self, out: &mut String, entries: &'e [Entry<'b, 'a>], count: usize, col: usize)
+        ->(&'e [Entry<'b, 'a>], &'e [Entry<'b, 'a>])
+    {
+        let mut line_col = col + width(out);
+        let mut len = 0;
+        for entry in entries.iter().take(count) {
+            let Entry::Item{comment,..} = entry else {break};
+            if len > 0 {
+                out.push(' ');
+                line_col += 1;
+            }
+            let start = out.len();
+            self.entry(out, entry, line_col);
+            line_col = end_col(&out[start..], line_col);
+            len += 1;
+            if comment.is_some() {
+                break;
+            }
+        }
+
+        return entries.split_at(len);
+    }
+
+    fn new_line(&self, out: &mut String, blank: bool, col: usize) {
+        out.push('\n');
+        if blank {
+            out.push('\n');
+        }
+        out.push_str(&" ".repeat(col));
+    }
+
+    /// A comment runs to the end of the line, so the bracket goes on the next line after one.
+    fn close(&self, out: &mut String, node: &CstNode, after_comment: bool, col: usize) {
+        if after_comment {
+            self.new_line(out, false, col);
+        }
+        out.push_str(node.close().map(|t|t.text).unwrap_or(""));
+    }
+}
+
+/// Forms that always put their body on its own lines.
+fn is_body_form(name: &str)->bool {
+    matches!(name, "defn"|"begin"|"cond"|"module")
+}
+
+/// If the entries are all tokens on one line without comments or blank lines between them.
+fn is_atoms(entries: &[Entry])->bool {
+    entries.iter().all(|e|match e {
+        Entry::Item{elem: CstElement::Token(tok), comment: None, blank_before: false, ..}=>!token_text(tok).contains('\n'),
+        _=>false,
+    })
+}
+
+/// The number of entries on the first line of a `defn`: everything up to the params and return
+/// type.
+fn defn_header(entries: &[Entry])->usize {
+    let params = entries.iter()
+        .position(|e|matches!(e, Entry::Item{elem: CstElement::Node(CstNode {kind: NodeKind::Vector, ..}), ..}));
+    let Some(params) = params else {
+        return 2;
+    };
+    match entries.get(params + 1) {
+        Some(Entry::Item{elem: CstElement::Token(CstToken {kind: CstTokenKind::Token(Token::Keyword(_)), ..}), ..})=>{
+            params + 2
+        },
+        _=>params + 1,
+    }
+}
@@ -6,18 +6,32 @@ use std::{
         Result as FmtResult,
     },
     error::Error,
+    iter::Peekable,
+    str::CharIndices,
 };
 use logos::{
+    Filter,
     Lexer,
     Logos,
 };
+use eka_core::{
+    diagnostics::Diagnostic,
+    source::{
+        FileId,
+        SourceMap,
+    },
+};
 
 pub use StartOrEnd::*;
 
 
+const ESCAPES_HELP: &str = "the valid escapes are `\\\\`, `\\n`, `\\t`, `\\r`, `\\0`, `\\\"`, `\\$`, `\\x41`, and `\\u{1F600}`";
+
+
 #[derive(Debug, Logos, PartialEq, Clone)]
 #[logos(skip "[ \t\r\n]")]
 #[logos(error = LexerError)]
+#[logos(extras = LexerOptions)]
 pub enum Token<'a> {
     #[regex("[^/:\"';#0-9\\\\()\\[\\]{} \t\r\n][^/\"';\\\\()\\[\\]{} \t\r\n]*")]
     Ident(&'a str),
@@ -28,13 +42,23 @@ pub enum Token<'a> {
     #[regex(":[^\"';\\\\()\\[\\]{} \t\r\n]*", |l|{&l.slice()[1..]})]
     Keyword(&'a str),
 
+    /// Decimal, or hex, octal, and binary with a `0x`, `0o`, or `0b` prefix. Digits can be
+    /// separated with `_`.
     #[regex("[0-9][0-9_]*", parse_num)]
-    #[regex("-[0-9_]+", parse_num_neg)]
+    #[regex("-[0-9][0-9_]*", parse_num)]
+    #[regex("0[xob][0-9a-zA-Z_]*", parse_num)]
+    #[regex("-0[xob][0-9a-zA-Z_]*", parse_num)]
     Number(i64),
 
-    #[regex("[0-9]+\\.[0-9]+", parse_float)]
-    #[regex("-[0-9]+\\.[0-9]+", parse_float_neg)]
-    #[regex("[0-9]+\\.", invalid_float)] 
+    #[regex("[0-9][0-9_]*\\.[0-9_]*", parse_float)]
+    #[regex("-[0-9][0-9_]*\\.[0-9_]*", parse_float)]
+    #[regex("[0-9][0-9_]*(\\.[0-9_]*)?[eE][+-]?[0-9][0-9_]*", parse_float)]
+    #[regex("-[0-9][0-9_]*(\\.[0-9_]*)?[eE][+-]?[0-9][0-9_]*", parse_float)]
+    #[regex("[0-9][0-9_]*(\\.[0-9_]*)?[eE][+-]?", invalid_float)]
+    #[regex("-[0-9][0-9_]*(\\.[0-9_]*)?[eE][+-]?", invalid_float)]
+    #[token("inf", |_|f64::INFINITY)]
+    #[token("-inf", |_|f64::NEG_INFINITY)]
+    #[token("nan", |_|f64::NAN)]
     Float(f64),
 
     #[regex("#[a-zA-Z]+")]
@@ -42,7 +66,10 @@ pub enum Token<'a> {
 
     #[token("\\space", |_|' ')]
     #[token("\\newline", |_|'\n')]
-    #[token("\\tab", |_|'\n')]
+    #[token("\\tab", |_|'\t')]
+    #[token("\\return", |_|'\r')]
+    #[token("\\nul", |_|'\0')]
+    #[regex("\\\\u\\{[^}\\s]*\\}", parse_unicode_char)]
     #[token("\\", parse_char)]
     Char(char),
 
@@ -61,19 +88,60 @@ pub enum Token<'a> {
     #[token("'")]
     Quote,
 
-    /// TODO: implement a proper string parser
-    #[regex("\"", parse_string)]
-    String(String),
+    /// A string split into its literal text and interpolated expressions. Plain strings have at
+    /// most one literal part. See [`literal`].
+    #[token("\"", parse_string)]
+    #[token("\"\"\"", parse_text_block)]
+    #[regex("r#*\"", parse_raw_string)]
+    String(Vec<StringPart<'a>>),
 
-    #[regex(";[^\n]*")]
+    /// Only produced when [`LexerOptions::keep_comments`] is set. Otherwise comments are skipped
+    /// like whitespace.
+    #[regex(";[^\n]*", comment)]
     Comment(&'a str),
 
+    /// Source the lexer rejected. The lexer never makes this; the parser puts it in place of
+    /// the source so the lexer error can be reported with the parser errors.
+    Error,
+
     EOF,
 }
 impl<'a> TokenTrait for Token<'a> {
     fn eof()->Self {Self::EOF}
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart<'a> {
+    /// Text with the escapes already processed
+    Literal(String),
+    /// The source of an interpolated expression like `${hp}`, and where it starts in the file
+    Expr {
+        source: &'a str,
+        offset: usize,
+    },
+}
+
+/// The text of a string without interpolations, or `None` if it has any.
+pub fn literal(parts: &[StringPart])->Option<String> {
+    let mut out = String::new();
+    for part in parts {
+        match part {
+            StringPart::Literal(s)=>out.push_str(s),
+            StringPart::Expr{..}=>return None,
+        }
+    }
+
+    return Some(out);
+}
+
+/// Options for the lexer, set with `Token::lexer_with_extras`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LexerOptions {
+    /// Produce [`Token::Comment`] instead of skipping comments. The parser doesn't want them, but
+    /// tools that keep the source layout do.
+    pub keep_comments: bool,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum StartOrEnd {
     Start,
@@ -87,8 +155,17 @@ pub enum LexerError {
     FloatOverflow,
     UnexpectedEof,
     InvalidFloat,
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+    },
+    MissingDigits,
     InvalidToken,
     InvalidStringEscape(char),
+    InvalidHexEscape,
+    InvalidUnicodeEscape,
+    UnterminatedString,
+    UnterminatedInterpolation,
 }
 impl Default for LexerError {
     fn default()->Self {
@@ -96,6 +173,43 @@ impl Default for LexerError {
     }
 }
 impl Error for LexerError {}
+impl LexerError {
+    /// A diagnostic for this error at the given byte range of the file.
+    pub fn diagnostic(&self, sources: &SourceMap, file: FileId, start: usize, end: usize)->Diagnostic {
+        let span = sources.span(file, start, end);
+        let diag = Diagnostic::error(self.to_string());
+
+        use LexerError::*;
+        return match self {
+            EmptyPathSegment=>diag.with_label(span, "a path segment is empty")
+                .with_help("paths look like `a/b/c`"),
+            IntegerOverflow=>diag.with_label(span, "does not fit in a 64 bit integer"),
+            FloatOverflow=>diag.with_label(span, "does not fit in a 64 bit float")
+                .with_help("use `inf` for infinity"),
+            UnexpectedEof=>diag.with_label(span, "the source ends here"),
+            InvalidFloat=>diag.with_label(span, "missing digits in the exponent")
+                .with_help("write the exponent like `1e-3`"),
+            InvalidDigit{radix, ..}=>diag.with_label(span, format!("in this {} literal", radix_name(*radix))),
+            MissingDigits=>diag.with_label(span, "missing digits after the prefix")
+                .with_help("write the number like `0xff`, `0o17`, or `0b1010`"),
+            InvalidToken=>diag.with_label(span, "not a valid token"),
+            InvalidStringEscape(_)=>diag.with_label(span, "in this string")
+                .with_help(ESCAPES_HELP),
+            InvalidHexEscape=>diag.with_label(span, "in this string")
+                .with_help("hex escapes are 2 digits up to `\\x7f`, like `\\x41`"),
+            InvalidUnicodeEscape=>diag.with_label(span, "in this string")
+                .with_help("unicode escapes are 1 to 6 hex digits naming a char, like `\\u{1F600}`"),
+            UnterminatedString=>{
+                // point at the opening quote, since the rest is the whole file
+                let open = sources.span(file, start, start + 1);
+                diag.with_label(open, "this string is never closed")
+            },
+            UnterminatedInterpolation=>diag.with_label(span, "in this string")
+                .with_help("interpolations look like `${expr}`, and `\\$` is a literal `$`"),
+        };
+    }
+}
+
 impl Display for LexerError {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
         use LexerError::*;
@@ -106,8 +220,14 @@ impl Display for LexerError {
             FloatOverflow=>write!(f, "Float overflow"),
             UnexpectedEof=>write!(f, "Unexpected EOF"),
             InvalidFloat=>write!(f, "Invalid Float"),
+            InvalidDigit{digit, radix}=>write!(f, "Invalid digit `{digit}` in {} literal", radix_name(*radix)),
+            MissingDigits=>write!(f, "Number has no digits"),
             InvalidToken=>write!(f, "Invalid Token"),
             InvalidStringEscape(c)=>write!(f, "Invalid String escape: `\\{c}`"),
+            InvalidHexEscape=>write!(f, "Invalid hex escape"),
+            InvalidUnicodeEscape=>write!(f, "Invalid unicode escape"),
+            UnterminatedString=>write!(f, "Unterminated string"),
+            UnterminatedInterpolation=>write!(f, "Unterminated interpolation in string"),
         }
     }
 }
@@ -186,6 +306,15 @@ fn parse_path<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<Vec<&'a str>, LexerErr
     }
 }
 
+#[inline]
+fn comment<'a>(lex: &mut Lexer<'a, Token<'a>>)->Filter<&'a str> {
+    if lex.extras.keep_comments {
+        Filter::Emit(lex.slice())
+    } else {
+        Filter::Skip
+    }
+}
+
 #[inline]
 fn invalid_float<'a>(_: &mut Lexer<'a, Token<'a>>)->Result<f64, LexerError> {
     Err(LexerError::InvalidFloat)
@@ -196,31 +325,42 @@ fn parse_num<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<i64, LexerError> {
     parse_num_inner(lex.slice())
 }
 
-#[inline]
-fn parse_num_neg<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<i64, LexerError> {
-    parse_num_inner(&lex.slice()[1..]).map(|o|o * -1)
-}
-
-#[inline]
+/// Parse an integer with an optional `-` and radix prefix.
 fn parse_num_inner(s: &str)->Result<i64, LexerError> {
-    let mut acc = 0i64;
-
-    for c in s.chars() {
-        match c {
-            '0'..='9'=>{
-                if let Some(shifted) = acc.checked_mul(10) {
-                    acc = shifted;
-                } else {
-                    return Err(LexerError::IntegerOverflow);
-                }
-                acc += ((c as u8) - b'0') as i64;
-            },
-            '_'=>{},
-            _=>unreachable!(),
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s)=>(true, s),
+        None=>(false, s),
+    };
+    let (radix, digits) = match s.get(..2) {
+        Some("0x")=>(16, &s[2..]),
+        Some("0o")=>(8, &s[2..]),
+        Some("0b")=>(2, &s[2..]),
+        _=>(10, s),
+    };
+
+    // accumulate the magnitude, which goes one past `i64::MAX` for `i64::MIN`
+    let mut acc = 0u64;
+    let mut any_digits = false;
+    for c in digits.chars() {
+        if c == '_' {
+            continue;
         }
+        let digit = c.to_digit(radix).ok_or(LexerError::InvalidDigit {digit: c, radix})?;
+        acc = acc.checked_mul(radix as u64)
+            .and_then(|acc|acc.checked_add(digit as u64))
+            .ok_or(LexerError::IntegerOverflow)?;
+        any_digits = true;
     }
 
-    return Ok(acc);
+    if !any_digits {
+        return Err(LexerError::MissingDigits);
+    }
+
+    if neg {
+        return 0i64.checked_sub_unsigned(acc).ok_or(LexerError::IntegerOverflow);
+    } else {
+        return i64::try_from(acc).map_err(|_|LexerError::IntegerOverflow);
+    }
 }
 
 #[inline]
@@ -228,14 +368,25 @@ fn parse_float<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<f64, LexerError> {
     parse_float_inner(lex.slice())
 }
 
-#[inline]
-fn parse_float_neg<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<f64, LexerError> {
-    parse_float_inner(&lex.slice()[1..]).map(|o|o * -1.0)
+fn parse_float_inner(s: &str)->Result<f64, LexerError> {
+    let s = s.replace('_', "");
+    let f: f64 = lexical::parse(&s).map_err(|_|LexerError::InvalidFloat)?;
+
+    // very small numbers round to 0, but numbers too big to store are an error
+    if f.is_infinite() {
+        return Err(LexerError::FloatOverflow);
+    }
+
+    return Ok(f);
 }
 
-#[inline]
-fn parse_float_inner(s: &str)->Result<f64, LexerError> {
-    lexical::parse(s).map_err(|_|LexerError::FloatOverflow)
+fn radix_name(radix: u32)->&'static str {
+    match radix {
+        2=>"binary",
+        8=>"octal",
+        16=>"hex",
+        _=>"decimal",
+    }
 }
 
 fn parse_char<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<char, LexerError> {
@@ -244,38 +395,255 @@ fn parse_char<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<char, LexerError> {
     return Ok(c);
 }
 
-fn parse_string<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<String, LexerError> {
-    let mut invalid = None;
-    let mut s = String::new();
-    let mut bytes = 0;
-    let mut escape = false;
+/// A char like `\u{1F600}`.
+fn parse_unicode_char<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<char, LexerError> {
+    let slice = lex.slice();
+    let digits = &slice[3..(slice.len() - 1)];
+    if digits.is_empty() || digits.len() > 6 || !digits.chars().all(|c|c.is_ascii_hexdigit()) {
+        return Err(LexerError::InvalidUnicodeEscape);
+    }
 
-    for c in lex.remainder().chars() {
-        bytes += c.len_utf8();
-
-        if escape {
-            escape = false;
-            match c {
-                '\\'=>s.push('\\'),
-                'n'=>s.push('\n'),
-                't'=>s.push('\t'),
-                'r'=>s.push('\r'),
-                '"'=>s.push('"'),
-                _=>invalid = Some(c),
-            }
-        } else {
-            match c {
-                '\\'=>escape = true,
-                '"'=>break,
-                _=>s.push(c),
-            }
+    return u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(LexerError::InvalidUnicodeEscape);
+}
+
+/// Lex the rest of a string started by `"`.
+fn parse_string<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<Vec<StringPart<'a>>, LexerError> {
+    let rest = lex.remainder();
+    let offset = lex.span().end;
+    let end = match find_string_end(rest, "\"", true) {
+        Ok(end)=>end,
+        Err(e)=>{
+            lex.bump(rest.len());
+            return Err(e);
+        },
+    };
+    lex.bump(end + 1);
+
+    return string_parts(&rest[..end], offset, None);
+}
+
+/// Lex the rest of a multi-line string started by `"""`. The line after the opening quotes and
+/// the line with the closing quotes are not part of the string if they are blank, and the
+/// indentation shared by every line is removed, so the string can be indented with the code.
+fn parse_text_block<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<Vec<StringPart<'a>>, LexerError> {
+    let rest = lex.remainder();
+    let mut offset = lex.span().end;
+    let end = match find_string_end(rest, "\"\"\"", true) {
+        Ok(end)=>end,
+        Err(e)=>{
+            lex.bump(rest.len());
+            return Err(e);
+        },
+    };
+    lex.bump(end + 3);
+
+    let mut content = &rest[..end];
+    if let Some(first_line) = content.find('\n') {
+        if content[..first_line].trim().is_empty() {
+            content = &content[(first_line + 1)..];
+            offset += first_line + 1;
         }
     }
-    lex.bump(bytes);
+    if let Some(last_line) = content.rfind('\n') {
+        if content[(last_line + 1)..].trim().is_empty() {
+            content = &content[..last_line];
+        }
+    }
+    let content = content.strip_suffix('\r').unwrap_or(content);
+
+    let indent = content.lines()
+        .filter(|line|!line.trim().is_empty())
+        .map(|line|line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+
+    // the first line has no newline before it, so its indentation is removed here
+    let first_indent = content.len() - content.trim_start_matches([' ', '\t']).len();
+    let skip = first_indent.min(indent);
+
+    return string_parts(&content[skip..], offset + skip, Some(indent));
+}
+
+/// Lex the rest of a raw string started by `r"` or `r#"`. Raw strings have no escapes or
+/// interpolation, and end at a `"` followed by as many `#` as they started with.
+fn parse_raw_string<'a>(lex: &mut Lexer<'a, Token<'a>>)->Result<Vec<StringPart<'a>>, LexerError> {
+    let hashes = lex.slice().len() - 2;
+    let close = format!("\"{}", "#".repeat(hashes));
+    let rest = lex.remainder();
+
+    let Some(end) = rest.find(&close) else {
+        lex.bump(rest.len());
+        return Err(LexerError::UnterminatedString);
+    };
+    lex.bump(end + close.len());
+
+    if end == 0 {
+        return Ok(Vec::new());
+    }
+    return Ok(vec![StringPart::Literal(rest[..end].to_string())]);
+}
 
-    if let Some(c) = invalid {
-        return Err(LexerError::InvalidStringEscape(c));
+/// Find the byte index of the `close` delimiter that ends the string, skipping escapes and
+/// interpolated expressions. Only escapes are skipped without `interpolate`.
+fn find_string_end(s: &str, close: &str, interpolate: bool)->Result<usize, LexerError> {
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\'=>{chars.next();},
+            '$' if interpolate && s[i..].starts_with("${")=>{
+                chars.next();
+                let len = interpolation_len(&s[(i + 2)..])?;
+                // skip the expression and the closing `}`
+                for _ in s[(i + 2)..(i + 2 + len + 1)].chars() {
+                    chars.next();
+                }
+            },
+            _ if s[i..].starts_with(close)=>return Ok(i),
+            _=>{},
+        }
     }
 
-    return Ok(s);
+    return Err(LexerError::UnterminatedString);
+}
+
+/// The length of the expression in `${...}`, up to the `}` that closes it. `s` starts after the
+/// `${`.
+fn interpolation_len(s: &str)->Result<usize, LexerError> {
+    let mut depth = 0;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // a char literal like `\}`
+            '\\'=>{chars.next();},
+            '"'=>{
+                let len = find_string_end(&s[(i + 1)..], "\"", true)
+                    .map_err(|_|LexerError::UnterminatedInterpolation)?;
+                for _ in s[(i + 1)..(i + 1 + len + 1)].chars() {
+                    chars.next();
+                }
+            },
+            '{'=>depth += 1,
+            '}' if depth == 0=>return Ok(i),
+            '}'=>depth -= 1,
+            _=>{},
+        }
+    }
+
+    return Err(LexerError::UnterminatedInterpolation);
+}
+
+/// Process the escapes and interpolations in the contents of a string. `offset` is where `s`
+/// starts in the source. With `indent`, up to that much indentation is removed after every
+/// newline in the source.
+fn string_parts<'a>(s: &'a str, offset: usize, indent: Option<usize>)->Result<Vec<StringPart<'a>>, LexerError> {
+    let mut parts = Vec::new();
+    let mut lit = String::new();
+    let mut error = None;
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\'=>match parse_escape(&mut chars) {
+                Ok(Some(c))=>lit.push(c),
+                // a line continuation
+                Ok(None)=>{
+                    while let Some((_, ' '|'\t'|'\r'|'\n')) = chars.peek() {
+                        chars.next();
+                    }
+                },
+                Err(e)=>{error.get_or_insert(e);},
+            },
+            '$' if s[i..].starts_with("${")=>{
+                chars.next();
+                let start = i + 2;
+                let len = interpolation_len(&s[start..])?;
+                if !lit.is_empty() {
+                    parts.push(StringPart::Literal(std::mem::take(&mut lit)));
+                }
+                parts.push(StringPart::Expr {
+                    source: &s[start..(start + len)],
+                    offset: offset + start,
+                });
+                while let Some((j, _)) = chars.next() {
+                    if j == start + len {
+                        break;
+                    }
+                }
+            },
+            '\n'=>{
+                lit.push('\n');
+                for _ in 0..indent.unwrap_or(0) {
+                    match chars.peek() {
+                        Some((_, ' '|'\t'))=>{chars.next();},
+                        _=>break,
+                    }
+                }
+            },
+            _=>lit.push(c),
+        }
+    }
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if !lit.is_empty() {
+        parts.push(StringPart::Literal(lit));
+    }
+
+    return Ok(parts);
+}
+
+/// Parse an escape after the `\`. Returns `None` for a line continuation, which is a `\` at the
+/// end of a line.
+fn parse_escape(chars: &mut Peekable<CharIndices>)->Result<Option<char>, LexerError> {
+    let Some((_, c)) = chars.next() else {
+        return Err(LexerError::UnterminatedString);
+    };
+
+    let c = match c {
+        '\\'=>'\\',
+        'n'=>'\n',
+        't'=>'\t',
+        'r'=>'\r',
+        '0'=>'\0',
+        '"'=>'"',
+        '$'=>'$',
+        '\n'=>return Ok(None),
+        '\r' if matches!(chars.peek(), Some((_, '\n')))=>{
+            chars.next();
+            return Ok(None);
+        },
+        'x'=>{
+            let digits = chars.by_ref().take(2).map(|(_, c)|c).collect::<String>();
+            match u8::from_str_radix(&digits, 16) {
+                Ok(n) if digits.len() == 2 && digits.chars().all(|c|c.is_ascii_hexdigit()) && n <= 0x7f=>{
+                    n as char
+                },
+                _=>return Err(LexerError::InvalidHexEscape),
+            }
+        },
+        'u'=>{
+            if !matches!(chars.next(), Some((_, '{'))) {
+                return Err(LexerError::InvalidUnicodeEscape);
+            }
+            let mut digits = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}'))=>break,
+                    Some((_, c)) if c.is_ascii_hexdigit() && digits.len() < 6=>digits.push(c),
+                    _=>return Err(LexerError::InvalidUnicodeEscape),
+                }
+            }
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(LexerError::InvalidUnicodeEscape)?
+        },
+        c=>return Err(LexerError::InvalidStringEscape(c)),
+    };
+
+    return Ok(Some(c));
 }
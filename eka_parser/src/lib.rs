@@ -1,46 +1,133 @@
-use parser_helper::{
-    LogosTokenStream,
-    LookaheadLexer,
-    SimpleError,
-    Span,
-    new_parser,
-};
-use logos::Logos;
 use anyhow::{
     Context,
     Result,
+    anyhow,
     bail,
 };
-use std::rc::Rc;
-use eka_core::ast::*;
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+use std::{
+    ops::Range,
+    rc::Rc,
+    mem,
+};
+use eka_core::{
+    ast::*,
+    source::*,
+    diagnostics::*,
+};
 use lexer::*;
+use cst::*;
 
 
 pub mod lexer;
-
-
-new_parser!(pub struct Parser<'a, 2, Token<'a>, LogosTokenStream<'a, Token<'a>>, ParserData>);
+pub mod cst;
+pub mod fmt;
+pub mod modules;
+
+
+/// Lowers the [`CstNode`] of a source into `Expr`s. The trivia is dropped and the rest of the
+/// tokens are parsed with recursive descent.
+pub struct Parser<'a> {
+    cst: CstNode<'a>,
+    /// The tokens of the CST without trivia, and their byte ranges in the file. The last one is
+    /// always EOF.
+    tokens: Vec<(Token<'a>, Range<usize>)>,
+    pos: usize,
+    state: ParseState,
+}
 // public methods
 impl<'a> Parser<'a> {
     pub fn new_from_source(source: &'a str)->Parser<'a> {
-        Parser::new(
-            Token::lexer(source),
-            ParserData {
-                interner: Interner::default(),
-                exprs: ExprStore::default(),
-                funcs: FunctionStore::default(),
-            },
-        )
+        Parser::new_with_data(source, ParserData::default())
+    }
+
+    /// Parse the source into existing data. New roots are added after the existing ones.
+    pub fn new_with_data(source: &'a str, data: ParserData)->Parser<'a> {
+        Parser::new_with_file("<string>", source, data)
+    }
+
+    /// Parse the source into existing data and name it `name` in the spans. Usually `name` is the
+    /// path of the file.
+    pub fn new_with_file(name: &str, source: &'a str, mut data: ParserData)->Parser<'a> {
+        let file = data.sources.add(name, source);
+        let cst = CstNode::parse(source);
+        let state = ParseState {
+            data,
+            file,
+            docs: cst.doc_comments(),
+            offset: 0,
+            eof: source.len(),
+            last_start: 0,
+            last_end: 0,
+            last_bad: false,
+            open: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        Parser::from_cst(cst, state)
+    }
+
+    fn from_cst(cst: CstNode<'a>, state: ParseState)->Parser<'a> {
+        let offset = state.offset;
+        let mut tokens = cst.tokens()
+            .into_iter()
+            .filter_map(|tok|match &tok.kind {
+                CstTokenKind::Token(t)=>Some((t.clone(), (tok.offset + offset)..(tok.end() + offset))),
+                CstTokenKind::Error(_)=>Some((Token::Error, (tok.offset + offset)..(tok.end() + offset))),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+        tokens.push((Token::EOF, state.eof..state.eof));
+
+        return Parser {
+            cst,
+            tokens,
+            pos: 0,
+            state,
+        };
+    }
+
+    /// The lossless syntax tree of the source.
+    #[inline]
+    pub fn cst(&self)->&CstNode<'a> {
+        &self.cst
     }
 
     pub fn finish(self)->ParserData {
-        self.0.finish()
+        self.state.data
     }
 
+    /// Parse every top-level form in the source. The parser recovers from errors by skipping to
+    /// the end of the broken form, so every error is returned together as [`Diagnostics`]. The
+    /// broken forms become [`Expr::Error`] nodes and the rest is still added to the data, which
+    /// can be taken with [`Parser::finish`].
+    ///
+    /// Source the lexer rejected becomes an [`Expr::Error`], or breaks the form it is in if an
+    /// expression can't go there. The lexer errors are returned with the parser errors.
     pub fn parse(&mut self)->Result<()> {
+        let lexer_errors = self.lexer_errors();
+        self.state.errors.extend(lexer_errors);
+
         while self.peek() != &Token::EOF {
-            let id = self.parse_expr()?;
-            self.user_data.exprs.add_root(id);
+            let start = self.peek_span().start;
+            let id = match self.parse_expr() {
+                Ok(id)=>id,
+                Err(e)=>{
+                    self.record_error(e);
+                    self.skip_to_top_level();
+                    self.expr(start, Expr::Error)
+                },
+            };
+            self.state.data.exprs.add_root(id);
+        }
+
+        let mut errors = mem::take(&mut self.state.errors);
+        if !errors.is_empty() {
+            errors.sort_by_key(|diag|diag.primary_span().map(|span|span.start));
+            bail!(Diagnostics(errors));
         }
 
         return Ok(());
@@ -55,6 +142,9 @@ impl<'a> Parser<'a> {
                     "defn"=>self.parse_func(),
                     "begin"=>self.parse_begin(),
                     "cond"=>self.parse_cond(),
+                    "module"=>self.parse_module(),
+                    "import"=>self.parse_import(),
+                    "export"=>self.parse_export(),
                     _=>self.parse_call(),
                 },
                 _=>self.parse_call(),
@@ -64,6 +154,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_cond(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
         self.match_ident("cond")?;
 
@@ -75,20 +166,20 @@ impl<'a> Parser<'a> {
                 Token::Keyword("default")=>{
                     self.take_token();
                     if default.is_some() {
-                        bail!("Cannot have multiple default branches in a cond expression");
+                        bail!(self.error("Cannot have multiple default branches in a cond expression"));
                     }
-                    default = Some(self.parse_expr().context("Cond default branch")?);
+                    default = Some(self.parse_item().context("Cond default branch")?);
                 },
                 _=>branches.push(self.parse_cond_branch()?),
             }
         }
 
-        return Ok(self.expr(Expr::Cond {branches, default}));
+        return Ok(self.expr(start, Expr::Cond {branches, default}));
     }
 
     fn parse_cond_branch(&mut self)->Result<CondBranch> {
-        let condition = self.parse_expr().context("In cond branch (condition)")?;
-        let branch = self.parse_expr().context("In cond branch (body)")?;
+        let condition = self.parse_item().context("In cond branch (condition)")?;
+        let branch = self.parse_item().context("In cond branch (body)")?;
 
         return Ok(CondBranch {
             condition,
@@ -97,95 +188,170 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_begin(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
         self.match_ident("begin")?;
 
         let mut body = Vec::new();
 
         while !self.try_paren_end() {
-            body.push(self.parse_expr().context("In begin expression")?);
+            body.push(self.parse_item().context("In begin expression")?);
+        }
+
+        return Ok(self.expr(start, Expr::Begin(body)));
+    }
+
+    pub fn parse_module(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
+        self.paren_start()?;
+        self.match_ident("module")?;
+
+        let name = self.ident().context("In module definition")?;
+
+        let mut body = Vec::new();
+
+        while !self.try_paren_end() {
+            body.push(self.parse_item().context("In module body")?);
+        }
+
+        return Ok(self.expr(start, Expr::Module(name, body)));
+    }
+
+    pub fn parse_import(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
+        self.paren_start()?;
+        self.match_ident("import")?;
+
+        let path = match self.next() {
+            Token::String(parts)=>match literal(&parts) {
+                Some(path)=>Rc::new(path),
+                None=>bail!(self.error("Import paths cannot be interpolated")),
+            },
+            _=>bail!(self.error("Expected the path of the module to import")),
+        };
+
+        let alias = match self.peek() {
+            Token::Keyword("as")=>{
+                self.take_token();
+                Some(self.ident().context("In import alias")?)
+            },
+            _=>None,
+        };
+
+        self.paren_end()?;
+
+        return Ok(self.expr(start, Expr::Import(path, alias)));
+    }
+
+    pub fn parse_export(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
+        self.paren_start()?;
+        self.match_ident("export")?;
+
+        let mut names = Vec::new();
+
+        while !self.try_paren_end() {
+            names.push(self.ident().context("In export list")?);
         }
 
-        return Ok(self.expr(Expr::Begin(body)));
+        return Ok(self.expr(start, Expr::Export(names)));
     }
 
     pub fn parse_def(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
         self.match_ident("def")?;
 
         let name = self.ident()?;
+        let ty = self.parse_type_annotation()?;
 
-        let expr = self.parse_expr()?;
+        let expr = self.parse_item()?;
 
         self.paren_end()?;
 
-        return Ok(self.expr(Expr::DefVar(name, expr)));
+        return Ok(self.expr(start, Expr::DefVar(name, ty, expr)));
     }
 
     pub fn parse_set(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
         self.match_ident("set")?;
 
         // check if there is a path. If so, then branch to the helper function
         match self.peek() {
-            Token::Path(_)=>return self.parse_set_path_branch(),
+            Token::Path(_)=>return self.parse_set_path_branch(start),
             _=>{},
         }
 
         let name = self.ident()?;
 
-        let data = self.parse_expr()?;
+        let data = self.parse_item()?;
 
         self.paren_end()?;
 
-        return Ok(self.expr(Expr::SetVar(name, data)));
+        return Ok(self.expr(start, Expr::SetVar(name, data)));
     }
 
-    fn parse_set_path_branch(&mut self)->Result<ExprId> {
+    fn parse_set_path_branch(&mut self, start: usize)->Result<ExprId> {
         let path = self.path()?;
 
-        let data = self.parse_expr()?;
+        let data = self.parse_item()?;
 
         self.paren_end()?;
 
-        return Ok(self.expr(Expr::SetPath{path, data}));
+        return Ok(self.expr(start, Expr::SetPath{path, data}));
     }
 
     pub fn parse_func(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
         self.match_ident("defn")?;
 
         let name = self.ident().context("In function definition")?;
+        let doc = match self.peek() {
+            Token::String(_)=>{
+                let Token::String(parts) = self.next() else {unreachable!()};
+                match literal(&parts) {
+                    Some(doc)=>Some(Rc::new(doc)),
+                    None=>bail!(self.error("Docstrings cannot be interpolated")),
+                }
+            },
+            _=>self.state.docs.remove(&start).map(Rc::new),
+        };
         let caps = self.parse_func_caps().context("In function definition")?;
-        let params = self.parse_func_params().context("In function definition")?;
-        
+        let (params, param_types) = self.parse_func_params().context("In function definition")?;
+        let ret_type = self.parse_return_type();
+
         let mut body = Vec::new();
 
         while !self.try_paren_end() {
-            body.push(self.parse_expr().context("In function body")?);
+            body.push(self.parse_item().context("In function body")?);
         }
 
         let block = if body.len() == 1 {
             body[0]
         } else {
-            self.expr(Expr::Begin(body))
+            self.expr(start, Expr::Begin(body))
         };
 
         let is_closure = !caps.is_empty();
 
-        let function = self.func(Function {
+        let function = self.func(start, Function {
             name,
             captures: caps,
             params,
+            param_types,
+            ret_type,
             block,
+            doc,
         });
         let expr_func = if is_closure {
-            self.expr(Expr::Closure(function))
+            self.expr(start, Expr::Closure(function))
         } else {
-            self.expr(Expr::Function(function))
+            self.expr(start, Expr::Function(function))
         };
 
-        return Ok(self.expr(Expr::DefVar(name, expr_func)));
+        return Ok(self.expr(start, Expr::DefVar(name, None, expr_func)));
     }
 
     fn parse_func_caps(&mut self)->Result<Vec<Ident>> {
@@ -201,51 +367,116 @@ impl<'a> Parser<'a> {
         return Ok(caps);
     }
 
-    fn parse_func_params(&mut self)->Result<Vec<Ident>> {
+    fn parse_func_params(&mut self)->Result<(Vec<Ident>, Vec<Option<Type>>)> {
         let mut params = Vec::new();
+        let mut types = Vec::new();
         self.match_token(Token::Vector(Start), "Expected function params")?;
 
         while !self.try_match_token(Token::Vector(End)) {
             params.push(self.ident()?);
+            match self.peek() {
+                Token::Keyword(_)=>{
+                    let Token::Keyword(name) = self.next() else {unreachable!()};
+                    types.push(Some(self.annotation(name)?));
+                },
+                _=>types.push(None),
+            }
+        }
+
+        return Ok((params, types));
+    }
+
+    /// Parses an optional type annotation like `:int`. A keyword is only an annotation if it is
+    /// followed by another expression, so `(def x :kw)` still defines `x` as the keyword `:kw`.
+    fn parse_type_annotation(&mut self)->Result<Option<Type>> {
+        match self.peek() {
+            Token::Keyword(_)=>{},
+            _=>return Ok(None),
+        }
+        if self.peek1() == &Token::Paren(End) {
+            return Ok(None);
+        }
+
+        let Token::Keyword(name) = self.next() else {unreachable!()};
+        return self.annotation(name).map(Some);
+    }
+
+    /// Parses the optional return type of a function. The body can start with a keyword, so only
+    /// a keyword naming a type is taken as the return type.
+    fn parse_return_type(&mut self)->Option<Type> {
+        let Token::Keyword(name) = self.peek() else {return None};
+        let name = *name;
+        if self.peek1() == &Token::Paren(End) {
+            return None;
+        }
+
+        let ty = self.type_from_name(name)?;
+        self.next();
+
+        return Some(ty);
+    }
+
+    /// The type named by an annotation that was just taken. Unknown names are errors.
+    fn annotation(&mut self, name: &str)->Result<Type> {
+        match self.type_from_name(name) {
+            Some(ty)=>Ok(ty),
+            None=>bail!(self.error(format!("Unknown type `:{name}`. Object types have to be declared before they are used"))),
+        }
+    }
+
+    /// A builtin type or a declared object type.
+    fn type_from_name(&mut self, name: &str)->Option<Type> {
+        if let Some(ty) = Type::from_name(name) {
+            return Some(ty);
+        }
+
+        let ident = self.intern(name);
+        let data = &self.state.data;
+        if data.any_object_type || data.object_types.contains(&ident) {
+            return Some(Type::Object(ident));
         }
 
-        return Ok(params);
+        return None;
     }
 
     pub fn parse_call(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         self.paren_start()?;
-        let first = self.parse_expr()?;
+        let first = self.parse_item()?;
 
         let mut others = Vec::new();
 
         while !self.try_paren_end() {
-            others.push(self.parse_expr()?);
+            others.push(self.parse_item()?);
         }
 
-        return Ok(self.expr(Expr::Call(first, others)));
+        return Ok(self.expr(start, Expr::Call(first, others)));
     }
 
     pub fn parse_primitive(&mut self)->Result<ExprId> {
+        let start = self.peek_span().start;
         match self.next() {
             Token::Ident(name)=>{
                 let ident = self.intern(name);
-                Ok(self.expr(Expr::GetVar(ident)))
+                Ok(self.expr(start, Expr::GetVar(ident)))
             },
             Token::Keyword(name)=>{
                 let ident = self.intern(name);
-                Ok(self.expr(Expr::Keyword(ident)))
+                Ok(self.expr(start, Expr::Keyword(ident)))
             },
             Token::Path(p)=>{
                 let expr = Expr::GetPath(p.into_iter().map(|s|self.intern(s)).collect());
-                Ok(self.expr(expr))
+                Ok(self.expr(start, expr))
             },
-            Token::Number(n)=>Ok(self.expr(Expr::Number(n))),
-            Token::Float(n)=>Ok(self.expr(Expr::Float(n))),
-            Token::String(s)=>Ok(self.expr(Expr::String(Rc::new(s)))),
-            Token::Char(c)=>Ok(self.expr(Expr::Char(c))),
-            Token::HashLiteral("#t")=>Ok(self.expr(Expr::Bool(true))),
-            Token::HashLiteral("#f")=>Ok(self.expr(Expr::Bool(false))),
-            Token::HashLiteral("#N")=>Ok(self.expr(Expr::None)),
+            Token::Number(n)=>Ok(self.expr(start, Expr::Number(n))),
+            Token::Float(n)=>Ok(self.expr(start, Expr::Float(n))),
+            Token::String(parts)=>self.string(start, parts),
+            Token::Char(c)=>Ok(self.expr(start, Expr::Char(c))),
+            Token::HashLiteral("#t")=>Ok(self.expr(start, Expr::Bool(true))),
+            Token::HashLiteral("#f")=>Ok(self.expr(start, Expr::Bool(false))),
+            Token::HashLiteral("#N")=>Ok(self.expr(start, Expr::None)),
+            // the lexer error is already reported
+            Token::Error=>Ok(self.expr(start, Expr::Error)),
             t=>bail!(self.error(format!("Expected primitive expression, but got `{:?}`", t))),
         }
     }
@@ -254,23 +485,39 @@ impl<'a> Parser<'a> {
 #[allow(unused)]
 impl<'a> Parser<'a> {
     #[inline]
-    fn func(&mut self, func: Function)->FnId {
-        self.user_data.funcs.insert(func)
+    fn func(&mut self, start: usize, func: Function)->FnId {
+        let span = self.span_from(start);
+        let id = self.state.data.funcs.insert(func);
+        self.state.data.funcs.set_span(id, span);
+
+        return id;
     }
 
     #[inline]
-    fn expr(&mut self, expr: Expr)->ExprId {
-        self.user_data.exprs.insert(expr)
+    fn expr(&mut self, start: usize, expr: Expr)->ExprId {
+        let span = self.span_from(start);
+        let id = self.state.data.exprs.insert(expr);
+        self.state.data.exprs.set_span(id, span);
+
+        return id;
+    }
+
+    /// The span from `start` to the end of the last token taken.
+    fn span_from(&self, start: usize)->Span {
+        let state = &self.state;
+        state.data.sources.span(state.file, start, state.last_end)
     }
 
     #[inline]
     fn update_expr(&mut self, id: ExprId, expr: Expr) {
-        self.user_data.exprs[id] = expr;
+        self.state.data.exprs[id] = expr;
     }
 
     #[inline]
     fn match_token<M: Into<String>>(&mut self, tok: Token<'a>, msg: M)->Result<()> {
-        self.0.match_token(tok, msg)?;
+        if self.take_token() != tok {
+            bail!(self.error(msg));
+        }
         return Ok(())
     }
 
@@ -286,16 +533,193 @@ impl<'a> Parser<'a> {
 
     #[inline]
     fn peek(&mut self)->&Token<'a> {
-        self.lookahead(0)
+        &self.tokens[self.pos].0
     }
 
     fn peek1(&mut self)->&Token<'a> {
-        self.lookahead(1)
+        let i = (self.pos + 1).min(self.tokens.len() - 1);
+        &self.tokens[i].0
     }
     
+    /// The byte range of the next token in the file.
     #[inline]
-    fn peek_span(&mut self)->Span {
-        self.lookahead_span(0)
+    fn peek_span(&mut self)->Range<usize> {
+        self.tokens[self.pos].1.clone()
+    }
+
+    /// A string literal, or a call to `format` if the string is interpolated.
+    fn string(&mut self, start: usize, parts: Vec<StringPart<'a>>)->Result<ExprId> {
+        if let Some(s) = literal(&parts) {
+            return Ok(self.expr(start, Expr::String(Rc::new(s))));
+        }
+
+        let format = self.intern("format");
+        let format = self.expr(start, Expr::GetVar(format));
+
+        let mut args = Vec::new();
+        for part in parts {
+            let arg = match part {
+                StringPart::Literal(s)=>self.expr(start, Expr::String(Rc::new(s))),
+                StringPart::Expr{source, offset}=>self.parse_interpolation(source, offset)
+                    .context("In string interpolation")?,
+            };
+            args.push(arg);
+        }
+
+        return Ok(self.expr(start, Expr::Call(format, args)));
+    }
+
+    /// Parse the expression in a `${...}` with a parser over just its source, sharing our data.
+    fn parse_interpolation(&mut self, source: &'a str, offset: usize)->Result<ExprId> {
+        let sub_state = ParseState {
+            data: mem::take(&mut self.state.data),
+            file: self.state.file,
+            docs: FxHashMap::default(),
+            offset,
+            eof: offset + source.len(),
+            last_start: offset,
+            last_end: offset,
+            last_bad: false,
+            open: Vec::new(),
+            errors: Vec::new(),
+        };
+        let mut parser = Parser::from_cst(CstNode::parse(source), sub_state);
+
+        let lexer_errors = parser.lexer_errors();
+        parser.state.errors.extend(lexer_errors);
+        let res = parser.parse_expr().and_then(|id|match parser.peek() {
+            Token::EOF=>Ok(id),
+            _=>{
+                parser.take_token();
+                bail!(parser.error("Expected only one expression in the interpolation"));
+            },
+        });
+
+        // the interpolation can't recover from its own errors, so they are returned together
+        let res = res.map_err(|e|{
+            parser.record_error(e);
+            anyhow!(Diagnostics(mem::take(&mut parser.state.errors)))
+        });
+
+        let sub_state = parser.state;
+        self.state.data = sub_state.data;
+        self.state.errors.extend(sub_state.errors);
+
+        return res;
+    }
+
+    /// A diagnostic for every token the lexer rejected.
+    fn lexer_errors(&self)->Vec<Diagnostic> {
+        let state = &self.state;
+        self.cst.errors()
+            .into_iter()
+            .map(|(e, start, end)|{
+                e.diagnostic(&state.data.sources, state.file, start + state.offset, end + state.offset)
+            })
+            .collect()
+    }
+
+    /// Parse an expression inside of a form. If it fails, the error is recorded and the rest of
+    /// the expression is skipped, so the enclosing form can keep parsing. Errors that already
+    /// closed the enclosing form, or that hit the end of the source, are returned instead.
+    fn parse_item(&mut self)->Result<ExprId> {
+        let depth = self.state.open.len();
+        let start = self.peek_span().start;
+        if self.peek() == &Token::EOF {
+            return self.parse_expr();
+        }
+
+        match self.parse_expr() {
+            Ok(id)=>Ok(id),
+            // at the end of the source every open form is broken, so the top level reports it once
+            Err(e) if self.state.open.len() >= depth && self.peek() != &Token::EOF=>{
+                self.record_error(e);
+                while self.state.open.len() > depth && self.peek() != &Token::EOF {
+                    self.take_token();
+                }
+                Ok(self.expr(start, Expr::Error))
+            },
+            Err(e)=>Err(e),
+        }
+    }
+
+    /// Skip the rest of a broken top-level form. A `(` at the start of a line is most likely the
+    /// next form, so it stops there even if the broken form is missing a `)`.
+    fn skip_to_top_level(&mut self) {
+        while !self.state.open.is_empty() {
+            match self.peek() {
+                Token::EOF=>break,
+                Token::Paren(Start)=>if self.at_line_start() {
+                    self.state.open.clear();
+                    break;
+                },
+                _=>{},
+            }
+            self.take_token();
+        }
+    }
+
+    fn at_line_start(&mut self)->bool {
+        let start = self.peek_span().start;
+        let state = &self.state;
+        return state.data.sources[state.file].line_col(start).1 == 1;
+    }
+
+    fn record_error(&mut self, err: anyhow::Error) {
+        // the lexer error for a rejected token already explains why it doesn't fit here
+        if self.state.last_bad {
+            return;
+        }
+
+        let mut diags = from_error(&err);
+
+        // running out of source is almost always a missing `)`, so point at the unclosed bracket
+        let state = &self.state;
+        let at_end = state.last_start == state.eof;
+        if let (true, Some(open)) = (at_end, state.open.last()) {
+            let span = state.data.sources.span(state.file, *open, *open + 1);
+            for diag in diags.iter_mut() {
+                diag.labels.push(Label {
+                    span,
+                    message: "this is never closed".into(),
+                    primary: false,
+                });
+            }
+        }
+
+        self.state.errors.extend(diags);
+    }
+
+    /// Take the next token and remember where it is for spans and errors.
+    fn take_token(&mut self)->Token<'a> {
+        let span = self.peek_span();
+        let tok = if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+            mem::replace(&mut self.tokens[self.pos - 1].0, Token::EOF)
+        } else {
+            Token::EOF
+        };
+
+        let state = &mut self.state;
+        match tok {
+            Token::Paren(Start)|Token::Vector(Start)|Token::Squiggle(Start)=>{
+                state.open.push(span.start);
+            },
+            Token::Paren(End)|Token::Vector(End)|Token::Squiggle(End)=>{
+                state.open.pop();
+            },
+            _=>{},
+        }
+        if tok == Token::EOF {
+            state.last_start = state.eof;
+            state.last_end = state.eof;
+        } else {
+            state.last_bad = tok == Token::Error;
+            state.last_start = span.start;
+            state.last_end = span.end;
+        }
+
+        return tok;
     }
 
     #[inline]
@@ -303,14 +727,24 @@ impl<'a> Parser<'a> {
         self.take_token()
     }
 
-    #[inline]
-    fn error(&mut self, msg: impl Into<String>)->SimpleError<String> {
-        self.0.error(msg)
+    /// An error pointing at the last token taken.
+    fn error(&mut self, msg: impl Into<String>)->Diagnostic {
+        let state = &self.state;
+        let span = state.data.sources.span(state.file, state.last_start, state.last_end);
+        let label = if state.last_start == state.eof && state.offset > 0 {
+            "the interpolation ends here"
+        } else if state.last_start == state.eof {
+            "the source ends here"
+        } else {
+            "unexpected token"
+        };
+
+        return Diagnostic::error(msg).with_label(span, label);
     }
 
     #[inline]
     fn intern(&mut self, s: &str)->Ident {
-        self.user_data.interner.intern(s)
+        self.state.data.interner.intern(s)
     }
 
     fn ident(&mut self)->Result<Ident> {
@@ -363,9 +797,65 @@ impl<'a> Parser<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ParserData {
     pub interner: Interner,
     pub exprs: ExprStore,
     pub funcs: FunctionStore,
+    /// Every source parsed into this data. The spans in `exprs` and `funcs` point into these.
+    pub sources: SourceMap,
+    /// The object types that can be used in type annotations like `[pos :Vec2]`. Other names that
+    /// aren't builtin types are errors.
+    pub object_types: FxHashSet<Ident>,
+    /// Take every unknown type name as an object type. Used by tools like the formatter that don't
+    /// know the types of the host.
+    pub any_object_type: bool,
+}
+impl ParserData {
+    /// Allow the object type `name` in type annotations. It should match the
+    /// [`type_name`](eka_core::interpreter::object::Object::type_name) of the objects.
+    pub fn declare_object_type(&mut self, name: &str) {
+        let ident = self.interner.intern(name);
+        self.object_types.insert(ident);
+    }
+
+    /// Parse the source into this data, naming it `name` like [`Parser::new_with_file`]. Returns
+    /// the new roots. Everything parsed before is kept, so this can be called again with more
+    /// source.
+    ///
+    /// On errors, the roots of the partially parsed source are still added.
+    pub fn parse_source(&mut self, name: &str, source: &str)->Result<Vec<ExprId>> {
+        let old_roots = self.exprs.root_count();
+
+        let mut parser = Parser::new_with_file(name, source, mem::take(self));
+        let res = parser.parse();
+        *self = parser.finish();
+        res?;
+
+        return Ok(self.exprs.iter_roots().skip(old_roots).copied().collect());
+    }
+}
+
+/// The data being parsed into along with the state only needed while parsing.
+#[derive(Debug)]
+pub struct ParseState {
+    data: ParserData,
+    file: FileId,
+    /// The doc comments in the source, keyed by the start of the item they document
+    docs: FxHashMap<usize, String>,
+    /// Where the lexed source starts in the file. Only interpolated expressions in strings are
+    /// lexed on their own, so it is usually 0.
+    offset: usize,
+    /// Where the lexed source ends in the file
+    eof: usize,
+    /// The byte range of the last token taken
+    last_start: usize,
+    last_end: usize,
+    /// The last token taken was rejected by the lexer. Running out of source right after one is
+    /// usually because of it, like with an unterminated string, so taking `EOF` doesn't clear it.
+    last_bad: bool,
+    /// Where each open bracket starts. Used to skip to the end of a broken form.
+    open: Vec<usize>,
+    /// Errors recovered from so far
+    errors: Vec<Diagnostic>,
 }
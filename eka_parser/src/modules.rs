@@ -0,0 +1,522 @@
+//! Loads modules and resolves their namespaces before anything is run.
+//!
+//! Every module gets a unique prefix, and its top-level definitions are renamed to
+//! `prefix/name`. Identifiers can't contain a `/`, so these names never clash with each other or
+//! with the names in scripts. Paths through an imported module like `m/func` become the renamed
+//! global, so the interpreter only ever sees one flat global scope.
+//!
+//! The roots of a module are added to the [`ExprStore`] before the roots of the source that
+//! imports it, so running the new roots in order runs every module before it is used. Each file
+//! is only loaded once, and importing a file that is still loading is an import cycle.
+//!
+//! Host modules are registered with [`ModuleLoader::host_export`] and imported by name.
+
+
+use anyhow::{
+    Context,
+    Result,
+    anyhow,
+    bail,
+};
+use rustc_hash::{
+    FxHashMap,
+    FxHashSet,
+};
+use std::{
+    path::{
+        Path,
+        PathBuf,
+    },
+    fs::read_to_string,
+    mem,
+};
+use eka_core::{
+    ast::*,
+    source::with_span,
+};
+use crate::ParserData;
+
+
+/// Where a loader was before a load, so a failed load can be undone with
+/// [`ModuleLoader::restore`].
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    modules: usize,
+    aliases: IdentMap<usize>,
+    prefixes: FxHashSet<String>,
+}
+
+#[derive(Debug)]
+struct ModuleInfo {
+    prefix: String,
+    /// The names importers can use, before they are prefixed
+    exports: FxHashSet<Ident>,
+}
+
+/// The names visible while resolving a file or `(module ...)` form.
+#[derive(Debug)]
+struct Scope {
+    /// The prefix of the module's globals, or `None` for source that isn't in a module
+    prefix: Option<String>,
+    /// Imports are relative to this
+    dir: PathBuf,
+    /// Namespace names and the modules they refer to
+    aliases: IdentMap<usize>,
+    /// The top-level definitions
+    globals: FxHashSet<Ident>,
+}
+
+
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    modules: Vec<ModuleInfo>,
+    /// The loaded files by canonical path
+    files: FxHashMap<PathBuf, usize>,
+    /// The host modules by name. They are kept apart from the files, so a host module can't be
+    /// mistaken for a file with the same path or the other way around.
+    host_modules: FxHashMap<String, usize>,
+    prefixes: FxHashSet<String>,
+    /// The files currently loading, in import order
+    loading: Vec<PathBuf>,
+    /// The namespaces visible to source that isn't in a module. They are kept between loads, so
+    /// a REPL can import a module once and use it later.
+    aliases: IdentMap<usize>,
+}
+impl ModuleLoader {
+    pub fn checkpoint(&self)->Checkpoint {
+        Checkpoint {
+            modules: self.modules.len(),
+            aliases: self.aliases.clone(),
+            prefixes: self.prefixes.clone(),
+        }
+    }
+
+    /// Forget every module loaded since the checkpoint. Use it when the roots of a load are thrown
+    /// away, so the modules are loaded again the next time they are imported.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.modules.truncate(checkpoint.modules);
+        self.files.retain(|_, id|*id < checkpoint.modules);
+        self.host_modules.retain(|_, id|*id < checkpoint.modules);
+        self.aliases = checkpoint.aliases;
+        self.prefixes = checkpoint.prefixes;
+    }
+
+    /// Add an export to the host module `module`, creating it if needed. Returns the name of the
+    /// global the host has to define for it. `(import "module")` imports it.
+    pub fn host_export(&mut self, module: &str, name: &str, interner: &mut Interner)->Ident {
+        let id = match self.host_modules.get(module) {
+            Some(id)=>*id,
+            None=>{
+                let prefix = self.unique_prefix(module);
+                self.modules.push(ModuleInfo {
+                    prefix,
+                    exports: FxHashSet::default(),
+                });
+                self.host_modules.insert(module.to_string(), self.modules.len() - 1);
+                self.modules.len() - 1
+            },
+        };
+
+        let name = interner.intern(name);
+        self.modules[id].exports.insert(name);
+
+        return mangle(&self.modules[id].prefix, name, interner);
+    }
+
+    /// Parse the source and the modules it imports into the data. `name` is used in spans, and
+    /// imports are relative to `dir`. The source is not a module, so its definitions keep their
+    /// names.
+    ///
+    /// On errors, the data may have new roots that shouldn't be run.
+    pub fn load_source(&mut self, data: &mut ParserData, name: &str, source: &str, dir: &Path)->Result<()> {
+        let scope = Scope {
+            prefix: None,
+            dir: dir.to_path_buf(),
+            aliases: self.aliases.clone(),
+            globals: FxHashSet::default(),
+        };
+
+        let (_, scope) = self.load(data, name, source, scope)?;
+        self.aliases = scope.aliases;
+
+        return Ok(());
+    }
+
+    /// Read the file and load it with [`ModuleLoader::load_source`]. Its imports are relative to
+    /// the directory it is in.
+    pub fn load_file(&mut self, data: &mut ParserData, path: &Path)->Result<()> {
+        let source = read_to_string(path)
+            .with_context(||format!("Could not read `{}`", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        return self.load_source(data, &path.display().to_string(), &source, dir);
+    }
+
+    /// Parse the source and move its roots after the roots of the modules it imports.
+    fn load(&mut self, data: &mut ParserData, name: &str, source: &str, mut scope: Scope)->Result<(Option<usize>, Scope)> {
+        let old_roots = data.exprs.root_count();
+        data.parse_source(name, source)?;
+        let roots = data.exprs.split_off_roots(old_roots);
+
+        let (roots, id) = self.resolve(data, roots, &mut scope)?;
+        for root in roots {
+            data.exprs.add_root(root);
+        }
+
+        return Ok((id, scope));
+    }
+
+    /// Find the module the import refers to, loading it if needed. Host modules are found by
+    /// name, and files by their canonical path.
+    fn import(&mut self, data: &mut ParserData, path: &str, dir: &Path)->Result<usize> {
+        if let Some(id) = self.host_modules.get(path) {
+            return Ok(*id);
+        }
+
+        let full_path = dir.join(path);
+        let canonical = full_path.canonicalize()
+            .map_err(|_|anyhow!("Could not find the module `{path}`"))?;
+        if let Some(id) = self.files.get(&canonical) {
+            return Ok(*id);
+        }
+
+        if let Some(start) = self.loading.iter().position(|p|*p == canonical) {
+            let cycle = self.loading[start..].iter()
+                .chain([&canonical])
+                .map(|p|file_name(p))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            bail!("Import cycle: {cycle}");
+        }
+
+        let source = read_to_string(&canonical)
+            .with_context(||format!("Could not read `{}`", full_path.display()))?;
+        let scope = Scope {
+            prefix: Some(self.unique_prefix(&default_alias(path))),
+            dir: canonical.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            aliases: IdentMap::default(),
+            globals: FxHashSet::default(),
+        };
+
+        self.loading.push(canonical.clone());
+        let res = self.load(data, &full_path.display().to_string(), &source, scope);
+        self.loading.pop();
+
+        let id = res?.0.unwrap();
+        self.files.insert(canonical, id);
+
+        return Ok(id);
+    }
+
+    /// Handle the imports, exports, and modules in the roots, then rename the globals in the rest.
+    /// Returns the roots to run in order, and the module if the scope is one.
+    fn resolve(&mut self, data: &mut ParserData, roots: Vec<ExprId>, scope: &mut Scope)->Result<(Vec<ExprId>, Option<usize>)> {
+        // the roots of inner modules are already resolved, so they are marked with `false`
+        let mut out = Vec::new();
+        let mut exports = Vec::new();
+        let mut has_export = false;
+
+        for root in roots {
+            let span = data.exprs.span(root);
+            match &data.exprs[root] {
+                Expr::Import(path, alias)=>{
+                    let (path, alias) = (path.clone(), *alias);
+                    let id = self.import(data, &path, &scope.dir)
+                        .map_err(|e|with_span(e, span))?;
+                    let alias = match alias {
+                        Some(alias)=>alias,
+                        None=>data.interner.intern(&default_alias(&path)),
+                    };
+                    scope.aliases.insert(alias, id);
+                },
+                Expr::Export(names)=>{
+                    exports.extend(names.iter().map(|name|(*name, span)));
+                    has_export = true;
+                },
+                Expr::Module(name, body)=>{
+                    let (name, body) = (*name, body.clone());
+                    let name_str = data.interner.get(name);
+                    let prefix = match &scope.prefix {
+                        Some(prefix)=>format!("{prefix}.{name_str}"),
+                        None=>name_str.to_string(),
+                    };
+
+                    let mut inner = Scope {
+                        prefix: Some(self.unique_prefix(&prefix)),
+                        dir: scope.dir.clone(),
+                        aliases: scope.aliases.clone(),
+                        globals: FxHashSet::default(),
+                    };
+                    let (roots, id) = self.resolve(data, body, &mut inner)?;
+                    scope.aliases.insert(name, id.unwrap());
+                    out.extend(roots.into_iter().map(|root|(root, false)));
+                },
+                Expr::DefVar(name, ..)=>{
+                    scope.globals.insert(*name);
+                    out.push((root, true));
+                },
+                _=>out.push((root, true)),
+            }
+        }
+
+        for (name, span) in exports.iter() {
+            if !scope.globals.contains(name) {
+                let err = anyhow!("`{}` is exported, but never defined in the module", data.interner.get(*name));
+                bail!(with_span(err, *span));
+            }
+        }
+
+        let mut rewriter = Rewriter {
+            data,
+            modules: &self.modules,
+            scope,
+            locals: Vec::new(),
+        };
+        for (root, own) in out.iter() {
+            if *own {
+                rewriter.expr(*root)?;
+            }
+        }
+
+        let Some(prefix) = scope.prefix.clone() else {
+            // exports outside of modules don't do anything
+            return Ok((out.into_iter().map(|(root, _)|root).collect(), None));
+        };
+
+        let exports = if has_export {
+            exports.into_iter().map(|(name, _)|name).collect()
+        } else {
+            scope.globals.clone()
+        };
+        self.modules.push(ModuleInfo {prefix, exports});
+
+        return Ok((out.into_iter().map(|(root, _)|root).collect(), Some(self.modules.len() - 1)));
+    }
+
+    /// Reserve a prefix based on `name`. Modules from files with the same name get a number added.
+    fn unique_prefix(&mut self, name: &str)->String {
+        let mut prefix = name.to_string();
+        let mut i = 2;
+        while self.prefixes.contains(&prefix) {
+            prefix = format!("{name}#{i}");
+            i += 1;
+        }
+        self.prefixes.insert(prefix.clone());
+
+        return prefix;
+    }
+}
+
+
+/// Renames the globals of a module and the paths into imported modules.
+struct Rewriter<'a> {
+    data: &'a mut ParserData,
+    modules: &'a [ModuleInfo],
+    scope: &'a Scope,
+    /// The vars defined in each enclosing function and block, innermost last. Empty at the top
+    /// level.
+    locals: Vec<FxHashSet<Ident>>,
+}
+impl<'a> Rewriter<'a> {
+    fn expr(&mut self, id: ExprId)->Result<()> {
+        // take the expr so the data can be changed while rewriting it
+        let expr = mem::replace(&mut self.data.exprs[id], Expr::None);
+        let res = self.expr_inner(expr);
+        let span = self.data.exprs.span(id);
+
+        self.data.exprs[id] = res.map_err(|e|with_span(e, span))?;
+
+        return Ok(());
+    }
+
+    fn expr_inner(&mut self, expr: Expr)->Result<Expr> {
+        let out = match expr {
+            Expr::Begin(block)=>{
+                self.locals.push(FxHashSet::default());
+                let res = block.iter().try_for_each(|id|self.expr(*id));
+                self.locals.pop();
+                res?;
+
+                Expr::Begin(block)
+            },
+
+            Expr::DefVar(name, ty, data)=>{
+                self.expr(data)?;
+                let name = match self.locals.last_mut() {
+                    Some(locals)=>{
+                        locals.insert(name);
+                        name
+                    },
+                    // vars defined outside of any function or block are globals
+                    None=>self.global_def(name),
+                };
+
+                Expr::DefVar(name, ty, data)
+            },
+            Expr::SetVar(name, data)=>{
+                self.expr(data)?;
+                Expr::SetVar(self.global(name), data)
+            },
+            Expr::GetVar(name)=>Expr::GetVar(self.global(name)),
+
+            Expr::Cond{branches,default}=>{
+                for branch in branches.iter() {
+                    self.expr(branch.condition)?;
+                    self.expr(branch.body)?;
+                }
+                if let Some(default) = default {
+                    self.expr(default)?;
+                }
+
+                Expr::Cond {branches, default}
+            },
+
+            Expr::Function(id)=>{
+                self.function(id)?;
+                Expr::Function(id)
+            },
+            Expr::Closure(id)=>{
+                self.function(id)?;
+                Expr::Closure(id)
+            },
+
+            Expr::Call(lhs, args)=>{
+                self.expr(lhs)?;
+                for arg in args.iter() {
+                    self.expr(*arg)?;
+                }
+
+                Expr::Call(lhs, args)
+            },
+            Expr::Method(lhs, name, args)=>{
+                self.expr(lhs)?;
+                for arg in args.iter() {
+                    self.expr(*arg)?;
+                }
+
+                Expr::Method(lhs, name, args)
+            },
+
+            Expr::GetPath(mut path)=>match self.namespaced(&path)? {
+                Some(global) if path.len() == 2=>Expr::GetVar(global),
+                Some(global)=>{
+                    path.splice(0..2, [global]);
+                    Expr::GetPath(path)
+                },
+                None=>{
+                    path[0] = self.global(path[0]);
+                    Expr::GetPath(path)
+                },
+            },
+            Expr::SetPath{mut path,data}=>{
+                self.expr(data)?;
+                match self.namespaced(&path)? {
+                    Some(_) if path.len() == 2=>bail!(
+                        "Cannot set `{}/{}` from outside of its module",
+                        self.data.interner.get(path[0]),
+                        self.data.interner.get(path[1]),
+                    ),
+                    Some(global)=>{path.splice(0..2, [global]);},
+                    None=>path[0] = self.global(path[0]),
+                }
+
+                Expr::SetPath {path, data}
+            },
+
+            Expr::Module(..)|Expr::Import(..)|Expr::Export(_)=>{
+                bail!("`module`, `import`, and `export` are only allowed at the top level of a file or module");
+            },
+
+            expr=>expr,
+        };
+
+        return Ok(out);
+    }
+
+    fn function(&mut self, id: FnId)->Result<()> {
+        let func = &self.data.funcs[id];
+        let block = func.block;
+        let mut locals = func.params.iter().copied().collect::<FxHashSet<_>>();
+        let captures = func.captures.clone();
+
+        // captures are looked up where the closure is created, outside of the function. Captured
+        // locals are still locals inside of it.
+        let captures = captures.into_iter()
+            .map(|name|{
+                let global = self.global(name);
+                if global == name {
+                    locals.insert(name);
+                }
+                global
+            })
+            .collect();
+        self.data.funcs[id].captures = captures;
+
+        let old_locals = mem::replace(&mut self.locals, vec![locals]);
+        let res = self.expr(block);
+        self.locals = old_locals;
+
+        return res;
+    }
+
+    fn is_local(&self, name: Ident)->bool {
+        self.locals.iter().any(|locals|locals.contains(&name))
+    }
+
+    /// The name of the var after renaming, if it is a global of the module.
+    fn global(&mut self, name: Ident)->Ident {
+        if self.is_local(name) || !self.scope.globals.contains(&name) {
+            return name;
+        }
+
+        return self.global_def(name);
+    }
+
+    fn global_def(&mut self, name: Ident)->Ident {
+        match &self.scope.prefix {
+            Some(prefix)=>mangle(prefix, name, &mut self.data.interner),
+            None=>name,
+        }
+    }
+
+    /// If the path starts with a namespace, the renamed global for the export it names.
+    fn namespaced(&mut self, path: &[Ident])->Result<Option<Ident>> {
+        if self.is_local(path[0]) {
+            return Ok(None);
+        }
+        let Some(id) = self.scope.aliases.get(&path[0]) else {
+            return Ok(None);
+        };
+
+        let module = &self.modules[*id];
+        if !module.exports.contains(&path[1]) {
+            bail!(
+                "Module `{}` has no export `{}`",
+                self.data.interner.get(path[0]),
+                self.data.interner.get(path[1]),
+            );
+        }
+
+        return Ok(Some(mangle(&module.prefix, path[1], &mut self.data.interner)));
+    }
+}
+
+
+fn mangle(prefix: &str, name: Ident, interner: &mut Interner)->Ident {
+    let name = format!("{prefix}/{}", interner.get(name));
+    interner.intern(&name)
+}
+
+fn file_name(path: &Path)->String {
+    path.file_name()
+        .map(|name|name.to_string_lossy().into_owned())
+        .unwrap_or_else(||path.display().to_string())
+}
+
+/// The file name without the extension, or the whole path for host modules.
+fn default_alias(path: &str)->String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem|stem.to_string_lossy().into_owned())
+        .unwrap_or_else(||path.to_string())
+}
@@ -0,0 +1,84 @@
+//! Tests for comments and docs on functions.
+
+
+use eka_core::ast::*;
+use eka_parser::{
+    Parser,
+    ParserData,
+};
+
+
+fn parse(source: &str)->ParserData {
+    let mut parser = Parser::new_from_source(source);
+    parser.parse().unwrap();
+
+    return parser.finish();
+}
+
+/// The docs of every function, in the order they were defined.
+fn docs(data: &ParserData)->Vec<Option<String>> {
+    data.exprs.iter_roots()
+        .filter_map(|id|match &data.exprs[*id] {
+            Expr::DefVar(_, _, func)=>match &data.exprs[*func] {
+                Expr::Function(id)=>Some(data.funcs[*id].doc.as_ref().map(|d|d.to_string())),
+                _=>None,
+            },
+            _=>None,
+        })
+        .collect()
+}
+
+
+#[test]
+fn comments_are_ignored_everywhere() {
+    let data = parse("\
+; before
+(def ; after the paren
+    x ; after the name
+    1) ; after the form
+(defn f [a ; in the params
+         b]
+    ; in the body
+    (+ a ; in a call
+       b))
+; at the end");
+
+    assert_eq!(data.exprs.root_count(), 2);
+}
+
+#[test]
+fn doc_comments() {
+    let data = parse("\
+;;; Adds the numbers.
+;;;
+;;;     indented
+(defn add [a b] (+ a b))
+
+;; not a doc comment
+(defn sub [a b] (- a b))
+
+;;; Separated by a normal comment
+; so it doesn't count
+(defn mul [a b] (* a b))");
+
+    assert_eq!(docs(&data), [
+        Some("Adds the numbers.\n\n    indented".into()),
+        None,
+        None,
+    ]);
+}
+
+#[test]
+fn docstrings() {
+    let data = parse("\
+(defn add \"Adds the numbers.\" [a b] (+ a b))
+;;; The docstring wins.
+(defn sub \"Subtracts.\" [a b] (- a b))
+(defn greeting [] \"Hello\")");
+
+    assert_eq!(docs(&data), [
+        Some("Adds the numbers.".into()),
+        Some("Subtracts.".into()),
+        None,
+    ]);
+}
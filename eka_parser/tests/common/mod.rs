@@ -0,0 +1,15 @@
+//! Helpers shared by the lexer tests.
+
+
+use logos::Logos;
+use eka_parser::lexer::*;
+
+
+/// Lex the source, which should be a single token.
+pub fn lex(source: &str)->Result<Token<'_>, LexerError> {
+    let mut lexer = Token::lexer(source);
+    let tok = lexer.next().expect("The source should not be empty");
+    assert_eq!(lexer.next(), None, "`{source}` should be a single token");
+
+    return tok;
+}
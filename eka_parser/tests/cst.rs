@@ -0,0 +1,110 @@
+//! Tests for the lossless syntax tree.
+
+
+use eka_parser::{
+    cst::*,
+    lexer::*,
+};
+
+
+#[track_caller]
+fn assert_lossless(source: &str) {
+    assert_eq!(CstNode::parse(source).text(), source);
+}
+
+
+#[test]
+fn every_byte_is_kept() {
+    assert_lossless("");
+    assert_lossless("   \n\t ");
+    assert_lossless(include_str!("../../example.eka"));
+    assert_lossless("(defn f [a b] ; comment\n    ;;; doc\n    (+ a b))\r\n");
+    assert_lossless("(def s \"\"\"\n    text ${(+ 1 2)}\n    \"\"\")");
+}
+
+#[test]
+fn broken_source_is_kept() {
+    assert_lossless("(def a (foo");
+    assert_lossless("(def a))) ]");
+    assert_lossless("(def a [)");
+    assert_lossless("(def a \"unterminated\n(def b 2)");
+    assert_lossless("(def a 0xfg 1e)");
+}
+
+#[test]
+fn tree_shape() {
+    let cst = CstNode::parse("; hi\n(def v [1 2]) ");
+    assert_eq!(cst.debug_tree(), "\
+Root@0..19
+  Comment@0..4 \"; hi\"
+  Whitespace@4..5 \"\\n\"
+  Paren@5..18
+    Paren(Start)@5..6 \"(\"
+    Ident(\"def\")@6..9 \"def\"
+    Whitespace@9..10 \" \"
+    Ident(\"v\")@10..11 \"v\"
+    Whitespace@11..12 \" \"
+    Vector@12..17
+      Vector(Start)@12..13 \"[\"
+      Number(1)@13..14 \"1\"
+      Whitespace@14..15 \" \"
+      Number(2)@15..16 \"2\"
+      Vector(End)@16..17 \"]\"
+    Paren(End)@17..18 \")\"
+  Whitespace@18..19 \" \"
+");
+}
+
+#[test]
+fn items_skip_trivia_and_brackets() {
+    let cst = CstNode::parse("(def ; c\n x 1)");
+    let CstElement::Node(form) = &cst.children[0] else {panic!("Expected a node")};
+
+    assert_eq!(form.kind, NodeKind::Paren);
+    assert_eq!(form.open().unwrap().text, "(");
+    assert_eq!(form.close().unwrap().text, ")");
+
+    let items = form.items()
+        .map(|item|match item {
+            CstElement::Token(tok)=>tok.text,
+            CstElement::Node(_)=>panic!("Expected only tokens"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(items, ["def", "x", "1"]);
+}
+
+#[test]
+fn unclosed_and_stray_brackets() {
+    let cst = CstNode::parse("(a [b) c");
+    let CstElement::Node(paren) = &cst.children[0] else {panic!("Expected a node")};
+    assert_eq!(paren.close(), None);
+    assert_eq!(paren.end(), 8);
+
+    // the `)` doesn't close the `[`, so it stays in the vector
+    let vector = paren.children.iter()
+        .find_map(|c|match c {
+            CstElement::Node(n)=>Some(n),
+            _=>None,
+        })
+        .unwrap();
+    assert_eq!(vector.kind, NodeKind::Vector);
+    assert_eq!(vector.close(), None);
+    assert!(vector.items().any(|c|matches!(c, CstElement::Token(CstToken {kind: CstTokenKind::Token(Token::Paren(End)), ..}))));
+}
+
+#[test]
+fn lexer_errors_become_tokens() {
+    let cst = CstNode::parse("(a 0b12 \"oops");
+    assert_eq!(cst.errors(), [
+        (LexerError::InvalidDigit {digit: '2', radix: 2}, 3, 7),
+        (LexerError::UnterminatedString, 8, 13),
+    ]);
+}
+
+#[test]
+fn doc_comments_key_the_next_token() {
+    let cst = CstNode::parse(";;; one\n;;; two\n(defn f [] 1)\n; plain\n(def x 1)");
+    let docs = cst.doc_comments();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[&16], "one\ntwo");
+}
@@ -0,0 +1,288 @@
+//! Snapshot tests for the diagnostics of lexer and parser errors.
+
+
+use eka_core::{
+    ast::Expr,
+    diagnostics::render_error,
+};
+use eka_parser::{
+    Parser,
+    ParserData,
+};
+
+
+/// Parse the source and render the error. Panics if it parses.
+fn parse_error(source: &str)->String {
+    let mut parser = Parser::new_with_file("test.eka", source, ParserData::default());
+    let err = parser.parse().expect_err("The source should not parse");
+    let data = parser.finish();
+
+    return render_error(&err, &data.sources, false);
+}
+
+
+#[test]
+fn lexer_errors() {
+    assert_eq!(parse_error("(def x \"bad \\q\")\n(def y 99999999999999999999)\n"), "\
+error: Invalid String escape: `\\q`
+ --> test.eka:1:8
+  |
+1 | (def x \"bad \\q\")
+  |        ^^^^^^^^ in this string
+  |
+  = help: the valid escapes are `\\\\`, `\\n`, `\\t`, `\\r`, `\\0`, `\\\"`, `\\$`, `\\x41`, and `\\u{1F600}`
+
+error: Integer Overflow
+ --> test.eka:2:8
+  |
+2 | (def y 99999999999999999999)
+  |        ^^^^^^^^^^^^^^^^^^^^ does not fit in a 64 bit integer
+");
+}
+
+#[test]
+fn unterminated_string() {
+    assert_eq!(parse_error("(def a 1)\n(def b \"abc)\n(def c 3)\n"), "\
+error: Unterminated string
+ --> test.eka:2:8
+  |
+2 | (def b \"abc)
+  |        ^ this string is never closed
+");
+}
+
+#[test]
+fn error_in_interpolation() {
+    assert_eq!(parse_error("(def a \"hp: ${(+ hp}\")"), "\
+error: Expected primitive expression, but got `EOF`
+ --> test.eka:1:20
+  |
+1 | (def a \"hp: ${(+ hp}\")
+  |               - this is never closed
+  |                    ^ the interpolation ends here
+  |
+  = note: In string interpolation
+");
+}
+
+#[test]
+fn unexpected_token() {
+    assert_eq!(parse_error("(def 5 6)"), "\
+error: Expected identifier
+ --> test.eka:1:6
+  |
+1 | (def 5 6)
+  |      ^ unexpected token
+");
+}
+
+#[test]
+fn unexpected_eof_keeps_context() {
+    assert_eq!(parse_error("(defn f [a]\n    (+ a 1)\n"), "\
+error: Expected primitive expression, but got `EOF`
+ --> test.eka:3:1
+  |
+1 | (defn f [a]
+  | - this is never closed
+  |
+3 | 
+  | ^ the source ends here
+  |
+  = note: In function body
+");
+}
+
+#[test]
+fn spans_point_into_the_right_file() {
+    let mut data = ParserData::default();
+    let mut parser = Parser::new_with_file("first.eka", "(def a 1)", data);
+    parser.parse().unwrap();
+    data = parser.finish();
+
+    let mut parser = Parser::new_with_file("second.eka", "(def b 2)\n(def)", data);
+    let err = parser.parse().unwrap_err();
+    let data = parser.finish();
+
+    assert_eq!(render_error(&err, &data.sources, false), "\
+error: Expected identifier
+ --> second.eka:2:5
+  |
+2 | (def)
+  |     ^ unexpected token
+");
+}
+
+#[test]
+fn reports_every_error() {
+    assert_eq!(parse_error("(def 5 6)\n(def ok 1)\n(print (def) 2)\n(set x 1 2)\n"), "\
+error: Expected identifier
+ --> test.eka:1:6
+  |
+1 | (def 5 6)
+  |      ^ unexpected token
+
+error: Expected identifier
+ --> test.eka:3:12
+  |
+3 | (print (def) 2)
+  |            ^ unexpected token
+
+error: Expected `)`
+ --> test.eka:4:10
+  |
+4 | (set x 1 2)
+  |          ^ unexpected token
+");
+}
+
+#[test]
+fn points_at_unclosed_form() {
+    assert_eq!(parse_error("(defn f [a]\n    (+ a (#x)))\n\n(defn g [] (h 1\n(def y 2)\n"), "\
+error: Expected primitive expression, but got `HashLiteral(\"#x\")`
+ --> test.eka:2:11
+  |
+2 |     (+ a (#x)))
+  |           ^^ unexpected token
+
+error: Expected primitive expression, but got `EOF`
+ --> test.eka:6:1
+  |
+4 | (defn g [] (h 1
+  |            - this is never closed
+  |
+6 | 
+  | ^ the source ends here
+  |
+  = note: In function body
+");
+}
+
+#[test]
+fn resyncs_at_next_top_level_form() {
+    assert_eq!(parse_error("(def 5 (foo\n(def y 2 3)\n(def z 3)\n"), "\
+error: Expected identifier
+ --> test.eka:1:6
+  |
+1 | (def 5 (foo
+  |      ^ unexpected token
+
+error: Expected `)`
+ --> test.eka:2:10
+  |
+2 | (def y 2 3)
+  |          ^ unexpected token
+");
+}
+
+#[test]
+fn resync_keeps_following_forms() {
+    let mut parser = Parser::new_from_source("(def 5 (foo\n(def y 2 3)\n(def z 3)\n");
+    parser.parse().expect_err("The source should not parse");
+    let data = parser.finish();
+
+    let roots = data.exprs.iter_roots()
+        .map(|id|&data.exprs[*id])
+        .collect::<Vec<_>>();
+    assert_eq!(roots.len(), 3);
+    assert!(matches!(roots[0], Expr::Error));
+    assert!(matches!(roots[1], Expr::Error));
+    assert!(matches!(roots[2], Expr::DefVar(..)));
+}
+
+#[test]
+fn keeps_partial_ast() {
+    let mut parser = Parser::new_from_source("(def a 1)\n(def 5 6)\n(print a (def) 3)\n(def b 2)\n");
+    parser.parse().expect_err("The source should not parse");
+    let data = parser.finish();
+
+    let roots = data.exprs.iter_roots()
+        .map(|id|&data.exprs[*id])
+        .collect::<Vec<_>>();
+    assert_eq!(roots.len(), 4);
+    assert!(matches!(roots[0], Expr::DefVar(..)));
+    assert!(matches!(roots[1], Expr::Error));
+    assert!(matches!(roots[3], Expr::DefVar(..)));
+
+    let Expr::Call(_, args) = roots[2] else {panic!("Expected a call, got {:?}", roots[2])};
+    assert!(matches!(data.exprs[args[0]], Expr::GetVar(_)));
+    assert!(matches!(data.exprs[args[1]], Expr::Error));
+    assert!(matches!(data.exprs[args[2]], Expr::Number(3)));
+}
+
+#[test]
+fn import_paths_are_literal() {
+    assert_eq!(parse_error("(import \"${name}.eka\")\n(import m)\n(import \"m.eka\" :as)\n"), "\
+error: Import paths cannot be interpolated
+ --> test.eka:1:9
+  |
+1 | (import \"${name}.eka\")
+  |         ^^^^^^^^^^^^^ unexpected token
+
+error: Expected the path of the module to import
+ --> test.eka:2:9
+  |
+2 | (import m)
+  |         ^ unexpected token
+
+error: Expected identifier
+ --> test.eka:3:20
+  |
+3 | (import \"m.eka\" :as)
+  |                    ^ unexpected token
+  |
+  = note: In import alias
+");
+}
+
+#[test]
+fn lexer_and_parser_errors_together() {
+    // the lexer errors are in source order with the parser errors, and the bad `0b2` doesn't
+    // cause an `Expected identifier` error of its own
+    assert_eq!(parse_error("(def x 0xfg)\n(def 5 6)\n(def 0b2 \"ok\")\n"), "\
+error: Invalid digit `g` in hex literal
+ --> test.eka:1:8
+  |
+1 | (def x 0xfg)
+  |        ^^^^ in this hex literal
+
+error: Expected identifier
+ --> test.eka:2:6
+  |
+2 | (def 5 6)
+  |      ^ unexpected token
+
+error: Invalid digit `2` in binary literal
+ --> test.eka:3:6
+  |
+3 | (def 0b2 \"ok\")
+  |      ^^^ in this binary literal
+");
+}
+
+#[test]
+fn lexer_errors_keep_partial_ast() {
+    let mut parser = Parser::new_from_source("(def a 1)\n(def b 0b2)\n(print 1e \"${0o9}\")\n(def 1e 2)\n(def c 3)\n");
+    let err = parser.parse().expect_err("The source should not parse");
+    assert_eq!(err.to_string(), "\
+4 errors:
+    Invalid digit `2` in binary literal
+    Invalid Float
+    Invalid digit `9` in octal literal
+    Invalid Float");
+    let data = parser.finish();
+
+    let roots = data.exprs.iter_roots()
+        .map(|id|&data.exprs[*id])
+        .collect::<Vec<_>>();
+    assert_eq!(roots.len(), 5);
+    assert!(matches!(roots[0], Expr::DefVar(..)));
+    assert!(matches!(roots[3], Expr::Error));
+    assert!(matches!(roots[4], Expr::DefVar(..)));
+
+    let Expr::DefVar(_, _, value) = roots[1] else {panic!("Expected a def, got {:?}", roots[1])};
+    assert!(matches!(data.exprs[*value], Expr::Error));
+
+    let Expr::Call(_, args) = roots[2] else {panic!("Expected a call, got {:?}", roots[2])};
+    assert!(matches!(data.exprs[args[0]], Expr::Error));
+    assert!(matches!(data.exprs[args[1]], Expr::Call(..)));
+}
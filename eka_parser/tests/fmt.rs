@@ -0,0 +1,212 @@
+//! Tests for the formatter. Every file in `fmt_corpus` and the example script is checked for
+//! idempotence and for keeping the tokens and comments of the source.
+
+
+use eka_core::source::SourceMap;
+use eka_parser::{
+    cst::*,
+    fmt::*,
+    lexer::*,
+};
+use std::fs;
+
+
+fn format_with(source: &str, options: &FormatOptions)->String {
+    let mut sources = SourceMap::default();
+    format_source("test.eka", source, &mut sources, options).unwrap()
+}
+
+fn format(source: &str)->String {
+    format_with(source, &FormatOptions::default())
+}
+
+fn corpus()->Vec<(String, String)> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fmt_corpus");
+    let mut files = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry|{
+            let path = entry.unwrap().path();
+            (path.display().to_string(), fs::read_to_string(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    files.push(("example.eka".into(), include_str!("../../example.eka").into()));
+
+    return files;
+}
+
+/// The comments and tokens of the source. Strings are compared by their value, since the
+/// formatter rewrites their escapes.
+fn significant(source: &str)->Vec<String> {
+    CstNode::parse(source)
+        .tokens()
+        .into_iter()
+        .filter_map(|tok|match &tok.kind {
+            CstTokenKind::Whitespace=>None,
+            CstTokenKind::Comment=>Some(tok.text.trim_end().to_string()),
+            CstTokenKind::Token(Token::String(parts))=>{
+                let parts = parts.iter()
+                    .map(|part|match part {
+                        StringPart::Expr{source,..}=>StringPart::Expr {source, offset: 0},
+                        part=>part.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                Some(format!("{parts:?}"))
+            },
+            _=>Some(tok.text.to_string()),
+        })
+        .collect()
+}
+
+
+#[test]
+fn corpus_is_idempotent() {
+    for width in [100, 60, 30] {
+        let options = FormatOptions {width, ..FormatOptions::default()};
+        for (name, source) in corpus() {
+            let once = format_with(&source, &options);
+            let twice = format_with(&once, &options);
+            assert_eq!(once, twice, "`{name}` is not idempotent at width {width}");
+        }
+    }
+}
+
+#[test]
+fn corpus_keeps_tokens_and_comments() {
+    for width in [100, 30] {
+        let options = FormatOptions {width, ..FormatOptions::default()};
+        for (name, source) in corpus() {
+            let formatted = format_with(&source, &options);
+            assert_eq!(significant(&source), significant(&formatted), "`{name}` changed at width {width}");
+        }
+    }
+}
+
+#[test]
+fn calls_that_fit_are_joined() {
+    assert_eq!(format("\
+(defn sayName [name]
+    (console/print
+        (format
+            \"Hello \"
+            name
+            \"!\\n\")))"), "\
+(defn sayName [name]
+    (console/print (format \"Hello \" name \"!\\n\")))
+");
+}
+
+#[test]
+fn short_forms_are_joined() {
+    assert_eq!(format("(def   a\n  (+ 1\n 2))"), "(def a (+ 1 2))\n");
+    assert_eq!(format("\n\n(def a 1)\n\n\n\n(def b 2)\n(def c 3)\n\n"), "(def a 1)\n\n(def b 2)\n(def c 3)\n");
+    assert_eq!(format(""), "");
+}
+
+#[test]
+fn bodies_are_indented() {
+    assert_eq!(format("(defn add [a b] :int (begin (+ a b) (- a b)))"), "\
+(defn add [a b] :int
+    (begin
+        (+ a b)
+        (- a b)))
+");
+    assert_eq!(format("(def f (foo (cond (= a 1) 2 :default 3)))"), "\
+(def f
+    (foo
+        (cond
+            (= a 1) 2
+            :default 3)))
+");
+
+    let options = FormatOptions {width: 30, ..FormatOptions::default()};
+    assert_eq!(format_with("(defn f [a] (cond (= a 1) \"one\" (= a 2) (format \"two and \" a \"!\") :default a))", &options), "\
+(defn f [a]
+    (cond
+        (= a 1) \"one\"
+        (= a 2)
+            (format \"two and \"
+                a \"!\")
+        :default a))
+");
+}
+
+#[test]
+fn long_argument_lists_break() {
+    let options = FormatOptions {width: 20, ..FormatOptions::default()};
+    assert_eq!(format_with("(foo (bar 1 2) (baz 3 4) 5)", &options), "\
+(foo
+    (bar 1 2)
+    (baz 3 4)
+    5)
+");
+    assert_eq!(format_with("(+ 100 200 300 400 500 600)", &options), "\
+(+ 100 200 300 400
+    500 600)
+");
+}
+
+#[test]
+fn comments_are_kept() {
+    assert_eq!(format("(def a 1)   ; one\n;;; doc\n(defn f [] ; header\n  1)"), "\
+(def a 1) ; one
+;;; doc
+(defn f [] ; header
+    1)
+");
+    assert_eq!(format("(begin\n(foo)\n; last\n)"), "\
+(begin
+    (foo)
+    ; last
+)
+");
+}
+
+#[test]
+fn string_escapes_are_normalized() {
+    assert_eq!(format(r#"(def s "\x41\x09\u{7}\$x \${y} ${z}")"#), "(def s \"A\\t\\u{7}$x \\${y} ${z}\")\n");
+    assert_eq!(format("(def s \"a \\\n    b\")"), "(def s \"a b\")\n");
+    assert_eq!(format(r###"(def s r#"\x41"#)"###), "(def s r#\"\\x41\"#)\n");
+}
+
+#[test]
+fn syntax_errors_are_not_formatted() {
+    let mut sources = SourceMap::default();
+    let err = format_source("test.eka", "(def a", &mut sources, &FormatOptions::default()).unwrap_err();
+    assert!(eka_core::diagnostics::render_error(&err, &sources, false).contains("test.eka"));
+}
+
+#[test]
+fn leading_comments() {
+    // a comment before the head starts the body, so nothing can go after it on its line
+    assert_eq!(format("(; note\nprint 1 2)"), "\
+(
+    ; note
+    print
+    1
+    2)
+");
+}
+
+#[test]
+fn quotes_stay_with_their_operand() {
+    // the parser doesn't take quotes yet, so this formats the tree directly
+    let options = FormatOptions {width: 20, ..FormatOptions::default()};
+    let format_cst = |source|format_cst(&CstNode::parse(source), &options);
+
+    assert_eq!(format_cst("(foo ' a '(b c))"), "(foo 'a '(b c))\n");
+    assert_eq!(format_cst("(foo 'a '(bar baz) ''(quux 1 2))"), "\
+(foo
+    'a
+    '(bar baz)
+    ''(quux 1 2))
+");
+    assert_eq!(format_cst("(+ 'one 'two 'three 'four)"), "(+ 'one 'two 'three\n    'four)\n");
+
+    // quotes without an operand after them are kept as they are
+    assert_eq!(format_cst("(foo ' ; note\n a ')"), "\
+(foo
+    ' ; note
+    a
+    ')
+");
+}
@@ -0,0 +1,127 @@
+//! Lexer tests for number and char literals.
+
+
+mod common;
+
+use eka_parser::lexer::*;
+use common::lex;
+
+
+fn int(source: &str)->i64 {
+    match lex(source) {
+        Ok(Token::Number(n))=>n,
+        other=>panic!("Expected `{source}` to be an int, but got {other:?}"),
+    }
+}
+
+fn float(source: &str)->f64 {
+    match lex(source) {
+        Ok(Token::Float(f))=>f,
+        other=>panic!("Expected `{source}` to be a float, but got {other:?}"),
+    }
+}
+
+fn char(source: &str)->char {
+    match lex(source) {
+        Ok(Token::Char(c))=>c,
+        other=>panic!("Expected `{source}` to be a char, but got {other:?}"),
+    }
+}
+
+
+#[test]
+fn decimal_ints() {
+    assert_eq!(int("0"), 0);
+    assert_eq!(int("42"), 42);
+    assert_eq!(int("-42"), -42);
+    assert_eq!(int("1_000_000"), 1_000_000);
+    assert_eq!(int("9223372036854775807"), i64::MAX);
+    assert_eq!(int("-9223372036854775808"), i64::MIN);
+}
+
+#[test]
+fn radix_ints() {
+    assert_eq!(int("0xff"), 255);
+    assert_eq!(int("0xDEAD_BEEF"), 0xDEAD_BEEF);
+    assert_eq!(int("-0x10"), -16);
+    assert_eq!(int("0o17"), 15);
+    assert_eq!(int("0b1010_1010"), 0b1010_1010);
+    assert_eq!(int("0x7fff_ffff_ffff_ffff"), i64::MAX);
+    assert_eq!(int("-0x8000_0000_0000_0000"), i64::MIN);
+}
+
+#[test]
+fn int_errors() {
+    assert_eq!(lex("9223372036854775808"), Err(LexerError::IntegerOverflow));
+    assert_eq!(lex("-9223372036854775809"), Err(LexerError::IntegerOverflow));
+    assert_eq!(lex("99999999999999999999999"), Err(LexerError::IntegerOverflow));
+    assert_eq!(lex("0x8000_0000_0000_0000"), Err(LexerError::IntegerOverflow));
+    assert_eq!(lex("0b1_0000000000000000000000000000000000000000000000000000000000000000"), Err(LexerError::IntegerOverflow));
+    assert_eq!(lex("0b102"), Err(LexerError::InvalidDigit {digit: '2', radix: 2}));
+    assert_eq!(lex("0o8"), Err(LexerError::InvalidDigit {digit: '8', radix: 8}));
+    assert_eq!(lex("0xfg"), Err(LexerError::InvalidDigit {digit: 'g', radix: 16}));
+    assert_eq!(lex("0x"), Err(LexerError::MissingDigits));
+    assert_eq!(lex("0b__"), Err(LexerError::MissingDigits));
+}
+
+#[test]
+fn floats() {
+    assert_eq!(float("1.5"), 1.5);
+    assert_eq!(float("-1.5"), -1.5);
+    assert_eq!(float("1."), 1.0);
+    assert_eq!(float("1_000.000_1"), 1000.0001);
+    assert_eq!(float("1e-3"), 1e-3);
+    assert_eq!(float("2.5E10"), 2.5e10);
+    assert_eq!(float("-2.5e+3"), -2500.0);
+    assert_eq!(float("1.e3"), 1000.0);
+    assert_eq!(float("1e-999"), 0.0);
+}
+
+#[test]
+fn special_floats() {
+    assert_eq!(float("inf"), f64::INFINITY);
+    assert_eq!(float("-inf"), f64::NEG_INFINITY);
+    assert!(float("nan").is_nan());
+
+    // only the exact words are floats
+    assert_eq!(lex("info"), Ok(Token::Ident("info")));
+    assert_eq!(lex("nana"), Ok(Token::Ident("nana")));
+}
+
+#[test]
+fn float_errors() {
+    assert_eq!(lex("1e999"), Err(LexerError::FloatOverflow));
+    assert_eq!(lex("-1.5e400"), Err(LexerError::FloatOverflow));
+    assert_eq!(lex("1e"), Err(LexerError::InvalidFloat));
+    assert_eq!(lex("1.5e-"), Err(LexerError::InvalidFloat));
+}
+
+#[test]
+fn minus_is_still_an_ident() {
+    assert_eq!(lex("-"), Ok(Token::Ident("-")));
+    assert_eq!(lex("-x"), Ok(Token::Ident("-x")));
+}
+
+#[test]
+fn chars() {
+    assert_eq!(char("\\a"), 'a');
+    assert_eq!(char("\\😀"), '😀');
+    assert_eq!(char("\\space"), ' ');
+    assert_eq!(char("\\newline"), '\n');
+    assert_eq!(char("\\tab"), '\t');
+    assert_eq!(char("\\return"), '\r');
+    assert_eq!(char("\\nul"), '\0');
+    assert_eq!(char("\\u{41}"), 'A');
+    assert_eq!(char("\\u{1F600}"), '😀');
+}
+
+#[test]
+fn char_errors() {
+    assert_eq!(lex("\\u{}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\u{D800}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\u{110000}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\u{1234567}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\u{zz}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\u{+41}"), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(lex("\\"), Err(LexerError::UnexpectedEof));
+}
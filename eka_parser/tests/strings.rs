@@ -0,0 +1,120 @@
+//! Tests for lexing string literals and desugaring interpolation.
+
+
+mod common;
+
+use eka_core::ast::*;
+use eka_parser::{
+    lexer::*,
+    Parser,
+};
+use common::lex;
+
+
+/// Lex the source, which should be a single string.
+fn parts(source: &str)->Result<Vec<StringPart<'_>>, LexerError> {
+    match lex(source)? {
+        Token::String(parts)=>Ok(parts),
+        other=>panic!("Expected a string, but got {other:?}"),
+    }
+}
+
+fn lit(source: &str)->String {
+    literal(&parts(source).unwrap()).expect("The string should not be interpolated")
+}
+
+
+#[test]
+fn escapes() {
+    assert_eq!(lit(r#""a\\b\n\t\r\"""#), "a\\b\n\t\r\"");
+    assert_eq!(lit(r#""\0\x41\x7f""#), "\0A\x7f");
+    assert_eq!(lit(r#""\u{1F600}\u{41}\u{10FFFF}""#), "😀A\u{10FFFF}");
+    assert_eq!(lit(r#""\${not interpolated}""#), "${not interpolated}");
+    assert_eq!(lit("\"\""), "");
+}
+
+#[test]
+fn invalid_escapes() {
+    assert_eq!(parts(r#""\q""#), Err(LexerError::InvalidStringEscape('q')));
+    assert_eq!(parts(r#""\x80""#), Err(LexerError::InvalidHexEscape));
+    assert_eq!(parts(r#""\x4""#), Err(LexerError::InvalidHexEscape));
+    assert_eq!(parts(r#""\u41""#), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(parts(r#""\u{}""#), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(parts(r#""\u{D800}""#), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(parts(r#""\u{1000000}""#), Err(LexerError::InvalidUnicodeEscape));
+    assert_eq!(parts(r#""\u{+41}""#), Err(LexerError::InvalidUnicodeEscape));
+}
+
+#[test]
+fn unterminated() {
+    assert_eq!(parts("\"abc"), Err(LexerError::UnterminatedString));
+    assert_eq!(parts("\"abc\\\""), Err(LexerError::UnterminatedString));
+    assert_eq!(parts("r#\"abc\""), Err(LexerError::UnterminatedString));
+    assert_eq!(parts("\"\"\"abc\"\""), Err(LexerError::UnterminatedString));
+    assert_eq!(parts("\"${(+ 1 2\""), Err(LexerError::UnterminatedInterpolation));
+}
+
+#[test]
+fn raw_strings() {
+    assert_eq!(lit(r#"r"C:\path\n""#), "C:\\path\\n");
+    assert_eq!(lit(r##"r#"say "hi" ${x}"#"##), "say \"hi\" ${x}");
+    assert_eq!(lit(r###"r##"a "# b"##"###), "a \"# b");
+}
+
+#[test]
+fn line_continuation() {
+    assert_eq!(lit("\"one \\\n     two\""), "one two");
+    assert_eq!(lit("\"one \\\r\n\ttwo\""), "one two");
+    assert_eq!(lit("\"one\n  two\""), "one\n  two");
+}
+
+#[test]
+fn text_blocks() {
+    let source = "\"\"\"
+        first
+          indented
+        last
+        \"\"\"";
+    assert_eq!(lit(source), "first\n  indented\nlast");
+
+    // the blank line in the middle doesn't count for the indentation
+    let source = "\"\"\"
+    a
+
+    b\"\"\"";
+    assert_eq!(lit(source), "a\n\nb");
+
+    assert_eq!(lit("\"\"\"one line\"\"\""), "one line");
+    assert_eq!(lit("\"\"\"\n    \"quoted\" \\t\n\"\"\""), "\"quoted\" \t");
+}
+
+#[test]
+fn interpolation_parts() {
+    let source = "\"hp: ${hp}, ${(format \"}\" x)}!\"";
+    assert_eq!(parts(source).unwrap(), [
+        StringPart::Literal("hp: ".into()),
+        StringPart::Expr {source: "hp", offset: 7},
+        StringPart::Literal(", ".into()),
+        StringPart::Expr {source: "(format \"}\" x)", offset: 14},
+        StringPart::Literal("!".into()),
+    ]);
+}
+
+#[test]
+fn interpolation_desugars_to_format() {
+    let mut parser = Parser::new_from_source("\"hp: ${hp}\"");
+    parser.parse().unwrap();
+    let data = parser.finish();
+
+    let root = data.exprs.iter_roots().next().unwrap();
+    let Expr::Call(callee, args) = &data.exprs[*root] else {panic!("Expected a call")};
+    let format = data.interner.lookup("format").unwrap();
+    let hp = data.interner.lookup("hp").unwrap();
+    assert!(matches!(data.exprs[*callee], Expr::GetVar(name) if name == format));
+    assert!(matches!(&data.exprs[args[0]], Expr::String(s) if s.as_str() == "hp: "));
+    assert!(matches!(data.exprs[args[1]], Expr::GetVar(name) if name == hp));
+
+    // spans of interpolated expressions point into the string
+    let span = data.exprs.span(args[1]).unwrap();
+    assert_eq!((span.start, span.end, span.col), (7, 9, 8));
+}
@@ -0,0 +1,130 @@
+//! Tests for type annotations on definitions, params, and return types.
+
+
+use eka_core::{
+    ast::*,
+    diagnostics::render_error,
+};
+use eka_parser::{
+    Parser,
+    ParserData,
+};
+
+
+fn parse_with(source: &str, mut data: ParserData)->ParserData {
+    data.declare_object_type("Vec2");
+    let mut parser = Parser::new_with_file("test.eka", source, data);
+    parser.parse().unwrap();
+
+    return parser.finish();
+}
+
+fn parse(source: &str)->ParserData {
+    parse_with(source, ParserData::default())
+}
+
+fn parse_error(source: &str)->String {
+    let mut data = ParserData::default();
+    data.declare_object_type("Vec2");
+    let mut parser = Parser::new_with_file("test.eka", source, data);
+    let err = parser.parse().expect_err("The source should not parse");
+
+    return render_error(&err, &parser.finish().sources, false);
+}
+
+/// The function defined by the first root.
+fn func(data: &ParserData)->&Function {
+    let root = *data.exprs.iter_roots().next().unwrap();
+    match &data.exprs[root] {
+        Expr::DefVar(_, _, func)=>match &data.exprs[*func] {
+            Expr::Function(id)=>&data.funcs[*id],
+            e=>panic!("Expected a function, but got {e:?}"),
+        },
+        e=>panic!("Expected a def, but got {e:?}"),
+    }
+}
+
+fn def_type(data: &ParserData)->Option<Type> {
+    let root = *data.exprs.iter_roots().next().unwrap();
+    match &data.exprs[root] {
+        Expr::DefVar(_, ty, _)=>*ty,
+        e=>panic!("Expected a def, but got {e:?}"),
+    }
+}
+
+fn object(data: &ParserData, name: &str)->Type {
+    Type::Object(data.interner.lookup(name).unwrap())
+}
+
+
+#[test]
+fn builtin_annotations() {
+    let data = parse("(defn f [a :int b c :string] :bool #t)");
+    let f = func(&data);
+    assert_eq!(f.param_types, [Some(Type::Int), None, Some(Type::String)]);
+    assert_eq!(f.ret_type, Some(Type::Bool));
+
+    assert_eq!(def_type(&parse("(def x :float 1.0)")), Some(Type::Float));
+    assert_eq!(def_type(&parse("(def x 1.0)")), None);
+
+    // a keyword on its own is the value, not an annotation
+    assert_eq!(def_type(&parse("(def x :kw)")), None);
+}
+
+#[test]
+fn declared_object_types() {
+    let data = parse("(defn f [pos :Vec2] :Vec2 pos)");
+    let f = func(&data);
+    assert_eq!(f.param_types, [Some(object(&data, "Vec2"))]);
+    assert_eq!(f.ret_type, Some(object(&data, "Vec2")));
+}
+
+#[test]
+fn keyword_starting_a_body() {
+    let data = parse("(defn f [x] :kw (g))");
+    let f = func(&data);
+    assert_eq!(f.ret_type, None);
+    match &data.exprs[f.block] {
+        Expr::Begin(body)=>{
+            assert_eq!(body.len(), 2);
+            assert!(matches!(&data.exprs[body[0]], Expr::Keyword(k) if data.interner.get(*k) == "kw"));
+        },
+        e=>panic!("Expected a body with two exprs, but got {e:?}"),
+    }
+
+    // only the return type is taken from the body
+    let data = parse("(defn f [x] :int :kw)");
+    let f = func(&data);
+    assert_eq!(f.ret_type, Some(Type::Int));
+    assert!(matches!(&data.exprs[f.block], Expr::Keyword(_)));
+}
+
+#[test]
+fn unknown_types() {
+    assert_eq!(parse_error("(def x :Int 1)"), "\
+error: Unknown type `:Int`. Object types have to be declared before they are used
+ --> test.eka:1:8
+  |
+1 | (def x :Int 1)
+  |        ^^^^ unexpected token
+");
+    assert_eq!(parse_error("(defn f [s :strng] s)"), "\
+error: Unknown type `:strng`. Object types have to be declared before they are used
+ --> test.eka:1:12
+  |
+1 | (defn f [s :strng] s)
+  |            ^^^^^^ unexpected token
+  |
+  = note: In function definition
+");
+}
+
+#[test]
+fn any_object_type() {
+    let data = ParserData {
+        any_object_type: true,
+        ..ParserData::default()
+    };
+    let data = parse_with("(def x :Whatever 1)", data);
+    assert_eq!(def_type(&data), Some(object(&data, "Whatever")));
+}